@@ -1,24 +1,161 @@
+//! requests to the X server, wrapped by [`ConnectionHandler`]
+
+use std::cell::RefCell;
 use std::collections::HashMap;
-use std::process::Command;
-use std::process::exit;
+use std::io::{self, Read, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
 
 use x11rb::protocol::xproto::ConnectionExt;
 use x11rb::{
-    COPY_DEPTH_FROM_PARENT, CURRENT_TIME,
+    COPY_DEPTH_FROM_PARENT, CURRENT_TIME, atom_manager,
     connection::Connection,
     cursor,
-    errors::{ReplyError, ReplyOrIdError},
-    protocol::{ErrorKind, xproto::*},
+    errors::ReplyError,
+    properties::WmSizeHints,
+    protocol::{
+        ErrorKind,
+        randr::{self, ConnectionExt as _},
+        shape::{self, ConnectionExt as _},
+        xinerama::{self, ConnectionExt as _},
+        xproto::*,
+    },
     resource_manager,
+    wrapper::ConnectionExt as _,
 };
 
 use crate::{
-    config::{self, Config},
-    keys::KeyHandler,
+    config::{self, Config, MouseContext, WindowProperties, WorkspaceModel},
+    error::WmError,
+    keys::{HotkeyAction, KeyHandler, LOCK_MASKS, SpawnCommand, parse_modifiers},
     state::*,
 };
 
-pub type Res = Result<(), ReplyOrIdError>;
+pub type Res = Result<(), WmError>;
+
+/// position, size, titlebar height, fullscreen and borderless of a frame
+type FrameGeometry = (i16, i16, u16, u16, u16, bool, bool);
+
+atom_manager! {
+    /// every atom hematite uses, interned in one round trip at startup
+    pub Atoms: AtomsCookie {
+        UTF8_STRING,
+        WM_PROTOCOLS,
+        WM_DELETE_WINDOW,
+        WM_NAME,
+        _NET_WM_NAME,
+        _NET_SUPPORTED,
+        _NET_CLIENT_LIST,
+        _NET_NUMBER_OF_DESKTOPS,
+        _NET_DESKTOP_GEOMETRY,
+        _NET_DESKTOP_VIEWPORT,
+        _NET_CURRENT_DESKTOP,
+        _NET_DESKTOP_NAMES,
+        _NET_ACTIVE_WINDOW,
+        _NET_WORKAREA,
+        _NET_SUPPORTING_WM_CHECK,
+        _NET_VIRTUAL_ROOTS,
+        _NET_DESKTOP_LAYOUT,
+        _NET_SHOWING_DESKTOP,
+        _NET_WM_ALLOWED_ACTIONS,
+        _NET_WM_STATE_MODAL,
+        _NET_WM_STATE,
+        _NET_WM_STATE_STICKY,
+        _NET_WM_STATE_MAXIMIZED_VERT,
+        _NET_WM_STATE_MAXIMIZED_HORZ,
+        _NET_WM_STATE_SHADED,
+        _NET_WM_STATE_SKIP_TASKBAR,
+        _NET_WM_STATE_SKIP_PAGER,
+        _NET_WM_STATE_HIDDEN,
+        _NET_WM_STATE_FULLSCREEN,
+        _NET_WM_STATE_ABOVE,
+        _NET_WM_STATE_BELOW,
+        _NET_WM_STATE_DEMANDS_ATTENTION,
+        _NET_WM_STATE_FOCUSED,
+        _NET_WM_ACTION_MOVE,
+        _NET_WM_ACTION_RESIZE,
+        _NET_WM_ACTION_MINIMIZE,
+        _NET_WM_ACTION_SHADE,
+        _NET_WM_ACTION_STICK,
+        _NET_WM_ACTION_MAXIMIZE_HORZ,
+        _NET_WM_ACTION_MAXIMIZE_VERT,
+        _NET_WM_ACTION_FULLSCREEN,
+        _NET_WM_ACTION_CHANGE_DESKTOP,
+        _NET_WM_ACTION_CLOSE,
+        _NET_WM_ACTION_ABOVE,
+        _NET_WM_ACTION_BELOW,
+        _NET_WM_WINDOW_TYPE,
+        _NET_WM_WINDOW_TYPE_DOCK,
+        _NET_WM_STRUT,
+        _NET_WM_STRUT_PARTIAL,
+        _NET_WM_FULLSCREEN_MONITORS,
+        _NET_WM_PID,
+        _NET_WM_WINDOW_OPACITY,
+        _RWM_ACTIVE_TAG,
+        _RWM_VISIBLE_TAGS,
+        _RWM_TAG_OCCUPANCY,
+        _RWM_LAYOUT,
+        I3_SOCKET_PATH,
+    }
+}
+
+impl Atoms {
+    /// the EWMH hints announced in `_NET_SUPPORTED`
+    fn supported(&self) -> Vec<Atom> {
+        vec![
+            self._NET_WM_NAME,
+            self._NET_SUPPORTED,
+            self._NET_CLIENT_LIST,
+            self._NET_NUMBER_OF_DESKTOPS,
+            self._NET_DESKTOP_GEOMETRY,
+            self._NET_DESKTOP_VIEWPORT,
+            self._NET_CURRENT_DESKTOP,
+            self._NET_DESKTOP_NAMES,
+            self._NET_ACTIVE_WINDOW,
+            self._NET_WORKAREA,
+            self._NET_SUPPORTING_WM_CHECK,
+            self._NET_VIRTUAL_ROOTS,
+            self._NET_DESKTOP_LAYOUT,
+            self._NET_SHOWING_DESKTOP,
+            self._NET_WM_ALLOWED_ACTIONS,
+            self._NET_WM_STATE_MODAL,
+            self._NET_WM_STATE,
+            self._NET_WM_STATE_STICKY,
+            self._NET_WM_STATE_MAXIMIZED_VERT,
+            self._NET_WM_STATE_MAXIMIZED_HORZ,
+            self._NET_WM_STATE_SHADED,
+            self._NET_WM_STATE_SKIP_TASKBAR,
+            self._NET_WM_STATE_SKIP_PAGER,
+            self._NET_WM_STATE_HIDDEN,
+            self._NET_WM_STATE_FULLSCREEN,
+            self._NET_WM_STATE_ABOVE,
+            self._NET_WM_STATE_BELOW,
+            self._NET_WM_STATE_DEMANDS_ATTENTION,
+            self._NET_WM_STATE_FOCUSED,
+            self._NET_WM_ACTION_MOVE,
+            self._NET_WM_ACTION_RESIZE,
+            self._NET_WM_ACTION_MINIMIZE,
+            self._NET_WM_ACTION_SHADE,
+            self._NET_WM_ACTION_STICK,
+            self._NET_WM_ACTION_MAXIMIZE_HORZ,
+            self._NET_WM_ACTION_MAXIMIZE_VERT,
+            self._NET_WM_ACTION_FULLSCREEN,
+            self._NET_WM_ACTION_CHANGE_DESKTOP,
+            self._NET_WM_ACTION_CLOSE,
+            self._NET_WM_ACTION_ABOVE,
+            self._NET_WM_ACTION_BELOW,
+            self._NET_WM_WINDOW_TYPE,
+            self._NET_WM_WINDOW_TYPE_DOCK,
+            self._NET_WM_STRUT,
+            self._NET_WM_STRUT_PARTIAL,
+            self._NET_WM_FULLSCREEN_MONITORS,
+            self._NET_WM_PID,
+            self._NET_WM_WINDOW_OPACITY,
+        ]
+    }
+}
 
 pub struct ConnectionHandler<'a, C: Connection> {
     pub conn: &'a C,
@@ -27,77 +164,43 @@ pub struct ConnectionHandler<'a, C: Connection> {
     pub id_graphics_context: Gcontext,
     id_inverted_graphics_context: Gcontext,
     pub graphics: (u32, u32, u32),
+    /// focused, unfocused and urgent border pixels
+    pub borders: (u32, u32, u32),
+    bar_graphics: BarGraphics,
     pub font_ascent: i16,
     font_width: i16,
-    pub atoms: HashMap<String, u32>,
+    pub atoms: Atoms,
+    /// names of atoms looked up before, which don't change while the server runs
+    atom_names: RefCell<HashMap<Atom, String>>,
+    /// pixels of the colors allocated before, so reloads and rules allocate each color once
+    /// instead of leaking a colormap entry every time
+    pixels: RefCell<HashMap<Rgb, u32>>,
+    /// what each managed window was last configured and shaped from, to skip doing it again
+    configured: RefCell<HashMap<Window, FrameGeometry>>,
     pub config: Config,
-    pub bar: WindowState,
+    pub bar_height: u16,
 }
 
 impl<'a, C: Connection> ConnectionHandler<'a, C> {
-    pub fn new(conn: &'a C, screen_num: usize, config: &Config) -> Result<Self, ReplyOrIdError> {
+    pub fn new(conn: &'a C, screen_num: usize, config: &Config) -> Result<Self, WmError> {
         let screen = &conn.setup().roots[screen_num];
-        become_window_manager(conn, screen.root)?;
+        become_window_manager(conn, screen_num, screen.root)?;
         log::debug!("screen num {screen_num} root {}", screen.root);
 
+        let config = &config.scaled(get_scale(conn, screen, config)?);
+
         let id_graphics_context = conn.generate_id()?;
         let id_inverted_graphics_context = conn.generate_id()?;
+        let bar_graphics = BarGraphics::new(conn)?;
         let id_font = conn.generate_id()?;
 
-        let atom_strings = vec![
-            "UTF8_STRING",
-            "WM_PROTOCOLS",
-            "WM_DELETE_WINDOW",
-            "WM_NAME",
-            "_NET_WM_NAME",
-            "_NET_SUPPORTED",
-            "_NET_CLIENT_LIST",
-            "_NET_NUMBER_OF_DESKTOPS",
-            "_NET_DESKTOP_GEOMETRY",
-            "_NET_DESKTOP_VIEWPORT",
-            "_NET_CURRENT_DESKTOP",
-            "_NET_DESKTOP_NAMES",
-            "_NET_ACTIVE_WINDOW",
-            "_NET_WORKAREA",
-            "_NET_SUPPORTING_WM_CHECK",
-            "_NET_VIRTUAL_ROOTS",
-            "_NET_DESKTOP_LAYOUT",
-            "_NET_SHOWING_DESKTOP",
-            "_NET_WM_NAME",
-            "_NET_WM_ALLOWED_ACTIONS",
-            "_NET_WM_STATE_MODAL",
-            "_NET_WM_STATE",
-            "_NET_WM_STATE_STICKY",
-            "_NET_WM_STATE_MAXIMIZED_VERT",
-            "_NET_WM_STATE_MAXIMIZED_HORZ",
-            "_NET_WM_STATE_SHADED",
-            "_NET_WM_STATE_SKIP_TASKBAR",
-            "_NET_WM_STATE_SKIP_PAGER",
-            "_NET_WM_STATE_HIDDEN",
-            "_NET_WM_STATE_FULLSCREEN",
-            "_NET_WM_STATE_ABOVE",
-            "_NET_WM_STATE_BELOW",
-            "_NET_WM_STATE_DEMANDS_ATTENTION",
-            "_NET_WM_STATE_FOCUSED",
-            "_NET_WM_ACTION_MOVE",
-            "_NET_WM_ACTION_RESIZE",
-            "_NET_WM_ACTION_MINIMIZE",
-            "_NET_WM_ACTION_SHADE",
-            "_NET_WM_ACTION_STICK",
-            "_NET_WM_ACTION_MAXIMIZE_HORZ",
-            "_NET_WM_ACTION_MAXIMIZE_VERT",
-            "_NET_WM_ACTION_FULLSCREEN",
-            "_NET_WM_ACTION_CHANGE_DESKTOP",
-            "_NET_WM_ACTION_CLOSE",
-            "_NET_WM_ACTION_ABOVE",
-            "_NET_WM_ACTION_BELOW",
-        ];
-
-        let atom_nums = get_atom_nums(conn, &atom_strings)?;
-        let atoms = get_atom_mapping(&atom_strings, &atom_nums);
+        let atoms = Atoms::new(conn)?.reply()?;
 
-        let main_color = get_color_id(conn, screen, config.main_color)?;
-        let secondary_color = get_color_id(conn, screen, config.secondary_color)?;
+        let pixels = RefCell::new(HashMap::new());
+        let colors = get_colors(conn, config)?;
+        let borders = get_border_ids(conn, screen, &pixels, config, colors)?;
+        let bar_colors = BarColors::new(conn, screen, &pixels, config, colors)?;
+        let (main_color, secondary_color) = (bar_colors.background, bar_colors.foreground);
 
         let graphics_context = CreateGCAux::new()
             .graphics_exposures(0)
@@ -119,6 +222,14 @@ impl<'a, C: Connection> ConnectionHandler<'a, C> {
             screen.root,
             &inverted_graphics_context,
         )?;
+        for (gc, foreground, background) in bar_graphics.contexts(&bar_colors) {
+            let aux = CreateGCAux::new()
+                .graphics_exposures(0)
+                .foreground(foreground)
+                .background(background)
+                .font(id_font);
+            conn.create_gc(gc, screen.root, &aux)?;
+        }
 
         //get font parameters
         let f = conn.query_font(id_font)?.reply()?.max_bounds;
@@ -137,32 +248,96 @@ impl<'a, C: Connection> ConnectionHandler<'a, C> {
             id_graphics_context,
             id_inverted_graphics_context,
             graphics: (main_color, secondary_color, id_font),
+            borders,
+            bar_graphics,
             font_ascent: f.ascent,
-            font_width: f.character_width as i16,
+            font_width: f.character_width,
             atoms,
+            atom_names: RefCell::new(HashMap::new()),
+            pixels,
+            configured: RefCell::new(HashMap::new()),
             config: config.clone(),
-            bar: WindowState {
-                window: conn.generate_id()?,
-                frame_window: conn.generate_id()?,
-                x: 0,
-                y: 0,
-                width: screen.width_in_pixels,
-                height: f.ascent as u16 * 3 / 2,
-                group: WindowGroup::Floating,
-            },
+            bar_height: f.ascent as u16 * 3 / 2,
         };
 
-        handler.change_atom_prop(screen.root, "_NET_SUPPORTED", unsafe {
-            atom_nums.as_slice().align_to::<u8>().1
+        let supported = handler.atoms.supported();
+        handler.change_atom_prop(screen.root, handler.atoms._NET_SUPPORTED, unsafe {
+            supported.as_slice().align_to::<u8>().1
         })?;
         handler.add_heartbeat_window()?;
-        handler.grab_keys(&KeyHandler::new(conn, &config)?)?;
+        handler.select_screen_change_input()?;
+        handler.grab_keys(&KeyHandler::new(conn, config)?)?;
         handler.set_cursor()?;
-        handler.create_bar_window()?;
 
         Ok(handler)
     }
 
+    /// takes over a reloaded config, updating the colors and font of the graphics contexts
+    pub fn apply_config(&mut self, config: &Config) -> Res {
+        // titlebars and corners may have changed size
+        self.configured.borrow_mut().clear();
+        let config = config.scaled(get_scale(self.conn, self.screen, config)?);
+        let colors = get_colors(self.conn, &config)?;
+        self.borders = get_border_ids(self.conn, self.screen, &self.pixels, &config, colors)?;
+        let bar_colors = BarColors::new(self.conn, self.screen, &self.pixels, &config, colors)?;
+        let (main_color, secondary_color) = (bar_colors.background, bar_colors.foreground);
+
+        let id_font = self.conn.generate_id()?;
+        set_font(self.conn, id_font, &config)?;
+        self.conn.change_gc(
+            self.id_graphics_context,
+            &ChangeGCAux::new()
+                .background(main_color)
+                .foreground(secondary_color)
+                .font(id_font),
+        )?;
+        self.conn.change_gc(
+            self.id_inverted_graphics_context,
+            &ChangeGCAux::new()
+                .background(secondary_color)
+                .foreground(main_color)
+                .font(id_font),
+        )?;
+        for (gc, foreground, background) in self.bar_graphics.contexts(&bar_colors) {
+            let aux = ChangeGCAux::new()
+                .foreground(foreground)
+                .background(background)
+                .font(id_font);
+            self.conn.change_gc(gc, &aux)?;
+        }
+        let f = self.conn.query_font(id_font)?.reply()?.max_bounds;
+        self.conn.close_font(id_font)?;
+
+        self.graphics = (main_color, secondary_color, id_font);
+        self.font_ascent = f.ascent;
+        self.font_width = f.character_width;
+        self.bar_height = f.ascent as u16 * 3 / 2;
+        self.config = config;
+        Ok(())
+    }
+
+    /// spacing, ratio, padding and bar height of the monitor from the config,
+    /// keeping whether the bar is shown
+    pub fn update_monitor_tiling(&self, index: usize, monitor: &mut Monitor) {
+        monitor.tiling = TilingInfo {
+            show_bar: monitor.tiling.show_bar,
+            ..self.get_monitor_tiling(index, &monitor.name, monitor.width)
+        };
+        monitor.bar.height = self.bar_height;
+    }
+
+    /// resizes and repaints a bar after the font or colors changed
+    pub fn restyle_bar(&self, bar: &WindowState) -> Res {
+        let background = ChangeWindowAttributesAux::new().background_pixel(self.graphics.0);
+        self.conn
+            .change_window_attributes(bar.window, &background)?;
+        self.conn
+            .change_window_attributes(bar.frame_window, &background)?;
+        self.config_frame(bar)?;
+        self.conn.clear_area(false, bar.window, 0, 0, 0, 0)?;
+        Ok(())
+    }
+
     pub fn map(&self, window: &WindowState) -> Res {
         log::debug!("handling map of {}", window.window);
         self.conn.map_window(window.frame_window)?;
@@ -170,6 +345,81 @@ impl<'a, C: Connection> ConnectionHandler<'a, C> {
         Ok(())
     }
 
+    /// stacks `window` right above `sibling`
+    pub fn stack_above(&self, window: Window, sibling: Window) -> Res {
+        self.conn.configure_window(
+            window,
+            &ConfigureWindowAux::new()
+                .sibling(sibling)
+                .stack_mode(StackMode::ABOVE),
+        )?;
+        Ok(())
+    }
+
+    /// hands every managed window back to the root window, showing hidden ones
+    /// and moving those left off-screen back onto it
+    pub fn release_windows(&self, wm_state: &StateHandler) -> Res {
+        let (width, height) = (
+            self.screen.width_in_pixels as i16,
+            self.screen.height_in_pixels as i16,
+        );
+        wm_state
+            .tags
+            .iter()
+            .flat_map(|t| t.windows.iter())
+            .chain(wm_state.scratch.iter())
+            .chain(wm_state.swallowed.values())
+            .try_for_each(|w| {
+                let w = WindowState {
+                    x: w.x.clamp(0, (width - w.width as i16).max(0)),
+                    y: w.y.clamp(0, (height - w.height as i16).max(0)),
+                    ..*w
+                };
+                self.destroy_window(&w)?;
+                self.conn.map_window(w.window)?;
+                Ok::<(), WmError>(())
+            })?;
+        wm_state
+            .monitors
+            .iter()
+            .try_for_each(|m| self.destroy_bar_window(&m.bar))?;
+        self.release_root()
+    }
+
+    /// takes the properties naming the window manager off the root window and leaves the
+    /// focus to the pointer, so the next window manager starts from a clean root
+    fn release_root(&self) -> Res {
+        let root = self.screen.root;
+        let check = self
+            .conn
+            .get_property(
+                false,
+                root,
+                self.atoms._NET_SUPPORTING_WM_CHECK,
+                AtomEnum::WINDOW,
+                0,
+                1,
+            )?
+            .reply()?
+            .value32()
+            .and_then(|mut v| v.next());
+        for atom in [
+            self.atoms._NET_SUPPORTING_WM_CHECK,
+            self.atoms._NET_ACTIVE_WINDOW,
+            self.atoms._NET_CLIENT_LIST,
+            self.atoms._NET_SHOWING_DESKTOP,
+            self.atoms.I3_SOCKET_PATH,
+        ] {
+            self.conn.delete_property(root, atom)?;
+        }
+        if let Some(window) = check {
+            self.conn.destroy_window(window)?;
+        }
+        self.conn
+            .set_input_focus(InputFocus::POINTER_ROOT, 1_u32, CURRENT_TIME)?;
+        Ok(())
+    }
+
     pub fn unmap(&self, window: &WindowState) -> Res {
         log::debug!("handling unmap of {}", window.window);
         self.conn.unmap_window(window.window)?;
@@ -179,8 +429,194 @@ impl<'a, C: Connection> ConnectionHandler<'a, C> {
 
     pub fn refresh(&self, wm_state: &StateHandler) -> Res {
         log::debug!("refreshing");
-        self.draw_bar(wm_state, wm_state.tags[wm_state.active_tag].focus)?;
-        Ok(())
+        wm_state
+            .monitors
+            .iter()
+            .try_for_each(|m| self.draw_bar(wm_state, m))
+    }
+
+    pub fn create_monitors(&self) -> Result<Vec<Monitor>, WmError> {
+        self.get_monitor_geometries()?
+            .iter()
+            .enumerate()
+            .map(|(i, (name, g))| {
+                self.create_monitor(
+                    i,
+                    name,
+                    g,
+                    StateHandler::initial_tag(self.config.workspaces, self.config.tag_count, i),
+                )
+            })
+            .collect()
+    }
+
+    pub fn create_monitor(
+        &self,
+        index: usize,
+        name: &str,
+        geometry: &Rectangle,
+        active_tag: usize,
+    ) -> Result<Monitor, WmError> {
+        log::info!(
+            "creating monitor {index} {name} x {} y {} w {} h {}",
+            geometry.x,
+            geometry.y,
+            geometry.width,
+            geometry.height
+        );
+        let monitor = Monitor {
+            name: name.to_owned(),
+            x: geometry.x,
+            y: geometry.y,
+            width: geometry.width,
+            height: geometry.height,
+            active_tag,
+            extra_tags: Vec::new(),
+            tiling: self.get_monitor_tiling(index, name, geometry.width),
+            strut: Edges::default(),
+            bar: WindowState {
+                x: geometry.x,
+                y: geometry.y,
+                width: geometry.width,
+                height: self.bar_height,
+                group: WindowGroup::Floating,
+                ..WindowState::new(self.conn.generate_id()?, self.conn.generate_id()?)?
+            },
+        };
+        self.create_bar_window(&monitor.bar)?;
+        if !monitor.tiling.show_bar {
+            self.unmap(&monitor.bar)?;
+        }
+        Ok(monitor)
+    }
+
+    /// returns the name and geometry of every connected monitor
+    pub fn get_monitor_geometries(&self) -> Result<Vec<(String, Rectangle)>, WmError> {
+        let mut geometries = match self.get_randr_monitors()? {
+            Some(g) => g,
+            None => match self.get_xinerama_monitors()? {
+                Some(g) => g,
+                None => {
+                    log::info!("no monitor information available, using whole screen");
+                    let root = self.conn.get_geometry(self.screen.root)?.reply()?;
+                    vec![(
+                        "screen".to_owned(),
+                        Rectangle {
+                            x: 0,
+                            y: 0,
+                            width: root.width,
+                            height: root.height,
+                        },
+                    )]
+                }
+            },
+        };
+        // with global tags every monitor shows a tag of its own, per-monitor tags have enough
+        if self.config.workspaces == WorkspaceModel::Global {
+            geometries.truncate(self.config.tag_count);
+        }
+        Ok(geometries)
+    }
+
+    fn get_monitor_tiling(&self, index: usize, name: &str, width: u16) -> TilingInfo {
+        let monitor_config = self.config.monitors.iter().find(|c| c.matches(index, name));
+        TilingInfo {
+            gap: monitor_config
+                .and_then(|c| c.spacing)
+                .unwrap_or(self.config.spacing)
+                .pixels(width) as u16,
+            ratio: monitor_config
+                .and_then(|c| c.ratio)
+                .unwrap_or(self.config.ratio),
+            bar_height: self.bar_height,
+            show_bar: monitor_config.and_then(|c| c.show_bar).unwrap_or(true),
+            padding: monitor_config
+                .and_then(|c| c.padding)
+                .map(|p| Edges {
+                    left: p.left,
+                    right: p.right,
+                    top: p.top,
+                    bottom: p.bottom,
+                })
+                .unwrap_or_default(),
+        }
+    }
+
+    fn get_randr_monitors(&self) -> Result<Option<Vec<(String, Rectangle)>>, WmError> {
+        if self
+            .conn
+            .extension_information(randr::X11_EXTENSION_NAME)?
+            .is_none()
+        {
+            return Ok(None);
+        }
+        let version = self.conn.randr_query_version(1, 5)?.reply()?;
+        if (version.major_version, version.minor_version) < (1, 5) {
+            log::info!(
+                "randr version {}.{} has no monitor support",
+                version.major_version,
+                version.minor_version
+            );
+            return Ok(None);
+        }
+        let monitors = self
+            .conn
+            .randr_get_monitors(self.screen.root, true)?
+            .reply()?
+            .monitors;
+        if monitors.is_empty() {
+            return Ok(None);
+        }
+        log::debug!("got {} monitors from randr", monitors.len());
+        Ok(Some(
+            monitors
+                .iter()
+                .map(|m| {
+                    Ok((
+                        self.get_atom_name(m.name)?,
+                        Rectangle {
+                            x: m.x,
+                            y: m.y,
+                            width: m.width,
+                            height: m.height,
+                        },
+                    ))
+                })
+                .collect::<Result<_, WmError>>()?,
+        ))
+    }
+
+    fn get_xinerama_monitors(&self) -> Result<Option<Vec<(String, Rectangle)>>, WmError> {
+        if self
+            .conn
+            .extension_information(xinerama::X11_EXTENSION_NAME)?
+            .is_none()
+            || self.conn.xinerama_is_active()?.reply()?.state == 0
+        {
+            return Ok(None);
+        }
+        let screens = self.conn.xinerama_query_screens()?.reply()?.screen_info;
+        if screens.is_empty() {
+            return Ok(None);
+        }
+        log::debug!("got {} monitors from xinerama", screens.len());
+        Ok(Some(
+            screens
+                .iter()
+                .enumerate()
+                .map(|(i, s)| {
+                    (
+                        format!("xinerama-{i}"),
+                        Rectangle {
+                            x: s.x_org,
+                            y: s.y_org,
+                            width: s.width,
+                            height: s.height,
+                        },
+                    )
+                })
+                .collect(),
+        ))
     }
 
     pub fn handle_config(&self, event: ConfigureRequestEvent) -> Res {
@@ -197,7 +633,7 @@ impl<'a, C: Connection> ConnectionHandler<'a, C> {
         Ok(())
     }
 
-    pub fn create_frame_of_window(&self, window: &WindowState) -> Res {
+    pub fn create_frame_of_window(&self, window: &WindowState, context: MouseContext) -> Res {
         log::debug!("creating frame of {}", window.window);
         self.conn.create_window(
             COPY_DEPTH_FROM_PARENT,
@@ -216,10 +652,11 @@ impl<'a, C: Connection> ConnectionHandler<'a, C> {
                         | EventMask::SUBSTRUCTURE_NOTIFY
                         | EventMask::ENTER_WINDOW
                         | EventMask::PROPERTY_CHANGE
-                        | EventMask::RESIZE_REDIRECT,
+                        | EventMask::RESIZE_REDIRECT
+                        | EventMask::EXPOSURE,
                 )
                 .background_pixel(self.graphics.0)
-                .border_pixel(self.graphics.1),
+                .border_pixel(self.borders.0),
         )?;
 
         self.conn.change_window_attributes(
@@ -232,24 +669,24 @@ impl<'a, C: Connection> ConnectionHandler<'a, C> {
                     | EventMask::RESIZE_REDIRECT,
             ),
         )?;
+        self.grab_buttons(window, context)?;
 
         let allowed_actions = [
-            "_NET_WM_ACTION_MOVE",
-            "_NET_WM_ACTION_RESIZE",
-            "_NET_WM_ACTION_MINIMIZE",
-            "_NET_WM_ACTION_SHADE",
-            "_NET_WM_ACTION_STICK",
-            "_NET_WM_ACTION_MAXIMIZE_HORZ",
-            "_NET_WM_ACTION_MAXIMIZE_VERT",
-            "_NET_WM_ACTION_FULLSCREEN",
-            "_NET_WM_ACTION_CHANGE_DESKTOP",
-            "_NET_WM_ACTION_CLOSE",
-            "_NET_WM_ACTION_ABOVE",
-            "_NET_WM_ACTION_BELOW",
-        ]
-        .map(|a| self.atoms[a]);
+            self.atoms._NET_WM_ACTION_MOVE,
+            self.atoms._NET_WM_ACTION_RESIZE,
+            self.atoms._NET_WM_ACTION_MINIMIZE,
+            self.atoms._NET_WM_ACTION_SHADE,
+            self.atoms._NET_WM_ACTION_STICK,
+            self.atoms._NET_WM_ACTION_MAXIMIZE_HORZ,
+            self.atoms._NET_WM_ACTION_MAXIMIZE_VERT,
+            self.atoms._NET_WM_ACTION_FULLSCREEN,
+            self.atoms._NET_WM_ACTION_CHANGE_DESKTOP,
+            self.atoms._NET_WM_ACTION_CLOSE,
+            self.atoms._NET_WM_ACTION_ABOVE,
+            self.atoms._NET_WM_ACTION_BELOW,
+        ];
 
-        self.change_atom_prop(window.window, "_NET_WM_ALLOWED_ACTIONS", &unsafe {
+        self.change_atom_prop(window.window, self.atoms._NET_WM_ALLOWED_ACTIONS, unsafe {
             allowed_actions.align_to::<u8>().1
         })?;
 
@@ -268,99 +705,492 @@ impl<'a, C: Connection> ConnectionHandler<'a, C> {
         self.conn
             .reparent_window(window.window, self.screen.root, window.x, window.y)?;
         self.conn.destroy_window(window.frame_window)?;
+        self.forget_configured(window.window);
+
+        Ok(())
+    }
 
+    /// destroys the frame of a client that is already gone
+    pub fn destroy_frame(&self, window: &WindowState) -> Res {
+        log::debug!("destroying frame of dead window: {}", window.window);
+        self.conn.destroy_window(window.frame_window)?;
+        self.forget_configured(window.window);
         Ok(())
     }
 
-    pub fn set_focus_window(&self, windows: &Vec<WindowState>, window: &WindowState) -> Res {
+    /// the border width of tiled windows, with percentages of the width of the whole X screen
+    /// as borders are the same on every monitor
+    pub fn border_size(&self) -> u32 {
+        self.config.border_size.pixels(self.screen.width_in_pixels)
+    }
+
+    /// the border width of tiled windows on the tag at index `tag`
+    pub fn tag_border_size(&self, tag: usize) -> u32 {
+        match self.config.get_tag_config(tag).and_then(|t| t.border_size) {
+            Some(size) => size.pixels(self.screen.width_in_pixels),
+            None => self.border_size(),
+        }
+    }
+
+    /// the border width of `window` on the tag at index `tag`
+    pub fn window_border_size(&self, window: &WindowState, tag: usize) -> u32 {
+        match window.borderless {
+            true => 0,
+            false => window
+                .border_width
+                .unwrap_or_else(|| self.tag_border_size(tag)),
+        }
+    }
+
+    /// hands the spacing and layout of tags that have their own, and the margin floating
+    /// windows keep on screen, to the state
+    pub fn update_tag_settings(&self, wm_state: &mut StateHandler) {
+        wm_state.tiled = None;
+        wm_state.float_placement = self.config.float_placement;
+        wm_state.float_margin = self.config.float_margin.pixels(self.screen.width_in_pixels) as u16;
+        wm_state.tag_spacing = self
+            .config
+            .tags
+            .iter()
+            .filter_map(|t| Some((t.tag.checked_sub(1)?, t.spacing?)))
+            .collect();
+        wm_state.floating_tags = self
+            .config
+            .tags
+            .iter()
+            .filter(|t| t.floating)
+            .filter_map(|t| t.tag.checked_sub(1))
+            .collect();
+    }
+
+    /// the unfocused border of a tiled window on the tag at index `tag`, or the urgent one
+    pub fn restyle_border(&self, window: &WindowState, tag: usize) -> Res {
+        let color = match window.urgent {
+            true => self.borders.2,
+            false => window.border_color.unwrap_or(self.borders.1),
+        };
+        self.conn.configure_window(
+            window.frame_window,
+            &ConfigureWindowAux::new().border_width(self.window_border_size(window, tag)),
+        )?;
+        self.conn.change_window_attributes(
+            window.frame_window,
+            &ChangeWindowAttributesAux::new().border_pixel(color),
+        )?;
+        Ok(())
+    }
+
+    /// focuses `window` out of the visible `windows`, given with the index of their tag
+    pub fn set_focus_window(&self, windows: &[(usize, WindowState)], window: &WindowState) -> Res {
         log::debug!("setting focus to: {:?}", window.window);
         self.conn
             .set_input_focus(InputFocus::PARENT, window.window, CURRENT_TIME)?;
 
         //set borders
-        windows.iter().try_for_each(|w| {
-            if w.group == WindowGroup::Floating {
-                return Ok(());
-            }
-            self.conn.configure_window(
-                w.frame_window,
-                &ConfigureWindowAux::new().border_width(self.config.border_size as u32),
-            )?;
-            self.conn.change_window_attributes(
-                w.frame_window,
-                &ChangeWindowAttributesAux::new().border_pixel(self.graphics.0),
-            )?;
-            Ok::<(), ReplyOrIdError>(())
-        })?;
+        windows
+            .iter()
+            .filter(|(_, w)| !w.is_floating())
+            .try_for_each(|(tag, w)| self.restyle_border(w, *tag))?;
 
         self.conn.change_window_attributes(
             window.frame_window,
-            &ChangeWindowAttributesAux::new().border_pixel(self.graphics.1),
+            &ChangeWindowAttributesAux::new().border_pixel(self.borders.0),
         )?;
+
+        // dimming, left to the compositor
+        if let Some(inactive) = self.config.inactive_opacity {
+            windows
+                .iter()
+                .map(|(_, w)| w)
+                .filter(|w| w.opacity.is_none())
+                .try_for_each(|w| match w.window == window.window {
+                    true => self.set_opacity(w, 1.0),
+                    false => self.set_opacity(w, inactive),
+                })?;
+        }
+        Ok(())
+    }
+
+    pub fn warp_pointer(&self, x: i16, y: i16) -> Res {
+        log::debug!("warping pointer to x {x} y {y}");
+        self.conn
+            .warp_pointer(x11rb::NONE, self.screen.root, 0, 0, 0, 0, x, y)?;
+        Ok(())
+    }
+
+    /// sends every key to the wm while a mode is active, returns false if another client holds a grab
+    pub fn grab_keyboard(&self) -> Result<bool, WmError> {
+        let reply = self
+            .conn
+            .grab_keyboard(
+                false,
+                self.screen.root,
+                CURRENT_TIME,
+                GrabMode::ASYNC,
+                GrabMode::ASYNC,
+            )?
+            .reply()?;
+        if reply.status != GrabStatus::SUCCESS {
+            log::error!("couldn't grab keyboard: {:?}", reply.status);
+        }
+        Ok(reply.status == GrabStatus::SUCCESS)
+    }
+
+    pub fn ungrab_keyboard(&self) -> Res {
+        self.conn.ungrab_keyboard(CURRENT_TIME)?;
         Ok(())
     }
 
-    pub fn get_focus(&self) -> Result<u32, ReplyOrIdError> {
+    /// lets a click that focused a window through to the client
+    pub fn replay_pointer(&self, time: u32) -> Res {
+        self.conn.allow_events(Allow::REPLAY_POINTER, time)?;
+        Ok(())
+    }
+
+    pub fn get_size_hints(&self, window: Window) -> Result<SizeHints, WmError> {
+        let hints = match WmSizeHints::get_normal_hints(self.conn, window)?.reply()? {
+            Some(h) => h,
+            None => return Ok(SizeHints::default()),
+        };
+        let size = |s: (i32, i32)| {
+            (
+                s.0.clamp(0, u16::MAX as i32) as u16,
+                s.1.clamp(0, u16::MAX as i32) as u16,
+            )
+        };
+        Ok(SizeHints {
+            size: hints
+                .size
+                .map(|(_, w, h)| size((w, h)))
+                .filter(|s| s.0 > 0 && s.1 > 0),
+            min: hints
+                .min_size
+                .or(hints.base_size)
+                .map(size)
+                .unwrap_or_default(),
+            max: hints.max_size.map(size),
+            base: hints
+                .base_size
+                .or(hints.min_size)
+                .map(size)
+                .unwrap_or_default(),
+            increment: hints.size_increment.map(size).unwrap_or_default(),
+        })
+    }
+
+    pub fn get_focus(&self) -> Result<u32, WmError> {
         Ok(self.conn.get_input_focus()?.reply()?.focus)
     }
 
+    /// places a managed window and its frame, shaping the frame to its new size,
+    /// unless it is already where it should be
     pub fn config_window_from_state(&self, window: &WindowState) -> Res {
-        log::debug!("configuring window {} from state", window.window);
-        self.conn
-            .configure_window(
+        let geometry = (
+            window.x,
+            window.y,
+            window.width,
+            window.height,
+            self.titlebar_height(window),
+            window.fullscreen,
+            window.borderless,
+        );
+        if self.configured.borrow().get(&window.window) == Some(&geometry) {
+            return Ok(());
+        }
+        self.config_frame(window)?;
+        self.shape_frame(window)?;
+        self.configured.borrow_mut().insert(window.window, geometry);
+        Ok(())
+    }
+
+    /// makes the next [`Self::config_window_from_state`] of the window configure it again,
+    /// after something else moved it
+    pub fn forget_configured(&self, window: Window) {
+        self.configured.borrow_mut().remove(&window);
+    }
+
+    /// rounds the corners of the frame, border included, by the configured radius.
+    /// fullscreen windows stay square
+    fn shape_frame(&self, window: &WindowState) -> Res {
+        if self
+            .conn
+            .extension_information(shape::X11_EXTENSION_NAME)?
+            .is_none()
+        {
+            return Ok(());
+        }
+        let radius = match window.fullscreen {
+            true => 0,
+            false => self
+                .config
+                .corner_radius
+                .pixels(self.screen.width_in_pixels) as u16,
+        };
+        if radius == 0 {
+            self.conn.shape_mask(
+                shape::SO::SET,
+                shape::SK::BOUNDING,
                 window.frame_window,
-                &ConfigureWindowAux {
-                    x: Some(window.x as i32),
-                    y: Some(window.y as i32),
-                    width: Some(window.width as u32),
-                    height: Some(window.height as u32),
-                    border_width: None,
-                    sibling: None,
-                    stack_mode: None,
-                },
-            )?
-            .check()?;
-        self.conn
-            .configure_window(
-                window.window,
-                &ConfigureWindowAux {
-                    x: Some(0),
-                    y: Some(0),
-                    width: Some(window.width as u32),
-                    height: Some(window.height as u32),
-                    border_width: None,
-                    sibling: None,
-                    stack_mode: None,
-                },
-            )?
-            .check()?;
+                0,
+                0,
+                x11rb::NONE,
+            )?;
+            return Ok(());
+        }
+        let border = self
+            .conn
+            .get_geometry(window.frame_window)?
+            .reply()?
+            .border_width;
+        let (width, height) = (window.width + border * 2, window.height + border * 2);
+        let rectangles = rounded_rectangle(width, height, radius);
+        self.conn.shape_rectangles(
+            shape::SO::SET,
+            shape::SK::BOUNDING,
+            ClipOrdering::UNSORTED,
+            window.frame_window,
+            -(border as i16),
+            -(border as i16),
+            &rectangles,
+        )?;
+        Ok(())
+    }
+
+    /// places a frame and the window in it, also used for bars
+    pub fn config_frame(&self, window: &WindowState) -> Res {
+        log::debug!("configuring window {} from state", window.window);
+        self.conn.configure_window(
+            window.frame_window,
+            &ConfigureWindowAux {
+                x: Some(window.x as i32),
+                y: Some(window.y as i32),
+                width: Some(window.width as u32),
+                height: Some(window.height as u32),
+                border_width: None,
+                sibling: None,
+                stack_mode: None,
+            },
+        )?;
+        let title = self.titlebar_height(window);
+        self.conn.configure_window(
+            window.window,
+            &ConfigureWindowAux {
+                x: Some(0),
+                y: Some(title as i32),
+                width: Some(window.width as u32),
+                height: Some(window.height.saturating_sub(title).max(1) as u32),
+                border_width: None,
+                sibling: None,
+                stack_mode: None,
+            },
+        )?;
+
+        Ok(())
+    }
 
+    /// the height of the titlebar above the window in its frame, none while fullscreen
+    pub fn titlebar_height(&self, window: &WindowState) -> u16 {
+        match window.titlebar && !window.fullscreen {
+            true => self.bar_height,
+            false => 0,
+        }
+    }
+
+    /// whether a point of the frame is on the close area at the right end of the titlebar
+    pub fn in_close_area(&self, window: &WindowState, x: i16, y: i16) -> bool {
+        let h = self.titlebar_height(window) as i16;
+        y < h && x >= window.width as i16 - h
+    }
+
+    /// draws the title of the window in its titlebar, in the colors of the active tag
+    /// when focused and of the other tags otherwise, with an `x` in the close area
+    pub fn draw_titlebar(&self, window: &WindowState, focused: bool) -> Res {
+        let h = self.titlebar_height(window);
+        if h == 0 {
+            return Ok(());
+        }
+        let graphics = &self.bar_graphics;
+        let (fill, text) = match focused {
+            true => (graphics.active_tag, graphics.active_tag_text),
+            false => (graphics.inactive_tag, graphics.inactive_tag_text),
+        };
+        self.conn.poly_fill_rectangle(
+            window.frame_window,
+            fill,
+            &[Rectangle {
+                x: 0,
+                y: 0,
+                width: window.width,
+                height: h,
+            }],
+        )?;
+        let text_y = (h as i16 / 2) + self.font_ascent / 5 * 2;
+        // as much of the title as fits left of the close area, image_text8 taking 255 bytes
+        let room = (window.width.saturating_sub(h * 2) as i16 / self.font_width.max(1)) as usize;
+        let title = self.get_window_name(window.window)?;
+        let title = title
+            .char_indices()
+            .map(|(i, c)| i + c.len_utf8())
+            .take_while(|end| *end <= room.min(255))
+            .last()
+            .map_or("", |end| &title[..end]);
+        self.conn.image_text8(
+            window.frame_window,
+            text,
+            h as i16 / 2,
+            text_y,
+            title.as_bytes(),
+        )?;
+        self.conn.image_text8(
+            window.frame_window,
+            text,
+            window.width as i16 - (h as i16 + self.font_width) / 2,
+            text_y,
+            b"x",
+        )?;
         Ok(())
     }
 
-    pub fn set_focus_to_root(&self) -> Result<(), ReplyOrIdError> {
+    pub fn set_focus_to_root(&self) -> Result<(), WmError> {
         log::debug!("setting focus to root");
         self.conn
-            .set_input_focus(InputFocus::NONE, 1 as u32, CURRENT_TIME)?;
+            .set_input_focus(InputFocus::NONE, 1_u32, CURRENT_TIME)?;
+        Ok(())
+    }
+
+    pub fn set_showing_desktop(&self, showing: bool) -> Res {
+        self.conn.change_property32(
+            PropMode::REPLACE,
+            self.screen.root,
+            self.atoms._NET_SHOWING_DESKTOP,
+            AtomEnum::CARDINAL,
+            &[showing as u32],
+        )?;
         Ok(())
     }
 
-    pub fn create_bar_window(&self) -> Res {
-        log::debug!("creating bar: {}", self.bar.window);
+    pub fn create_bar_window(&self, bar: &WindowState) -> Res {
+        log::debug!("creating bar: {}", bar.window);
         self.conn.create_window(
             COPY_DEPTH_FROM_PARENT,
-            self.bar.window,
+            bar.window,
             self.screen.root,
             0,
             0,
-            self.screen.width_in_pixels,
-            self.font_ascent as u16 * 3 / 2,
+            bar.width,
+            bar.height,
             0,
             WindowClass::INPUT_OUTPUT,
             0,
             &CreateWindowAux::new().background_pixel(self.graphics.0),
         )?;
-        self.create_frame_of_window(&self.bar)?;
+        self.create_frame_of_window(bar, MouseContext::Bar)?;
+        Ok(())
+    }
+
+    /// creates an unmapped popup for listing windows or hotkeys
+    pub fn create_overlay(&self) -> Result<Window, WmError> {
+        let window = self.conn.generate_id()?;
+        self.conn.create_window(
+            COPY_DEPTH_FROM_PARENT,
+            window,
+            self.screen.root,
+            0,
+            0,
+            1,
+            1,
+            self.border_size() as u16,
+            WindowClass::INPUT_OUTPUT,
+            0,
+            &CreateWindowAux::new()
+                .override_redirect(1)
+                .background_pixel(self.graphics.0)
+                .border_pixel(self.graphics.1),
+        )?;
+        Ok(window)
+    }
+
+    /// shows `lines` centered on `monitor`, highlighting the selected one
+    pub fn draw_overlay(
+        &self,
+        overlay: Window,
+        monitor: &Monitor,
+        lines: &[String],
+        selected: Option<usize>,
+    ) -> Res {
+        // computed wide, so long lists and lines are cut off by the monitor instead of wrapping
+        let h = self.bar_height as u32;
+        let longest = lines.iter().map(|n| n.len()).max().unwrap_or_default() as u32;
+        let width = ((longest + 2) * self.font_width as u32).clamp(1, monitor.width.max(1) as u32);
+        let height = (lines.len() as u32 * h).clamp(1, monitor.height.max(1) as u32);
+        self.conn.configure_window(
+            overlay,
+            &ConfigureWindowAux::new()
+                .x(monitor.x as i32 + (monitor.width as i32 - width as i32) / 2)
+                .y(monitor.y as i32 + (monitor.height as i32 - height as i32) / 2)
+                .width(width)
+                .height(height)
+                .stack_mode(StackMode::ABOVE),
+        )?;
+        self.conn.map_window(overlay)?;
+        self.conn
+            .clear_area(false, overlay, 0, 0, width as u16, height as u16)?;
+
+        // only the lines that fit are drawn
+        let shown = (height / h.max(1)) as usize;
+        if let Some(selected) = selected.filter(|s| *s < shown) {
+            self.conn.poly_fill_rectangle(
+                overlay,
+                self.id_graphics_context,
+                &[Rectangle {
+                    x: 0,
+                    y: (selected as u32 * h) as i16,
+                    width: width as u16,
+                    height: h as u16,
+                }],
+            )?;
+        }
+        let text_y = (h as i32 / 2) + self.font_ascent as i32 / 5 * 2;
+        lines
+            .iter()
+            .take(shown)
+            .enumerate()
+            .try_for_each(|(i, line)| {
+                self.conn.image_text8(
+                    overlay,
+                    if Some(i) == selected {
+                        self.id_inverted_graphics_context
+                    } else {
+                        self.id_graphics_context
+                    },
+                    self.font_width,
+                    (text_y + (i as u32 * h) as i32) as i16,
+                    line.as_bytes(),
+                )?;
+                Ok::<(), WmError>(())
+            })
+    }
+
+    pub fn destroy_overlay(&self, overlay: Window) -> Res {
+        self.conn.destroy_window(overlay)?;
+        Ok(())
+    }
+
+    /// modifiers currently held down
+    pub fn get_modifier_state(&self) -> Result<KeyButMask, WmError> {
+        Ok(self.conn.query_pointer(self.screen.root)?.reply()?.mask)
+    }
+
+    /// where the pointer is on the root window
+    pub fn get_pointer_position(&self) -> Result<(i16, i16), WmError> {
+        let pointer = self.conn.query_pointer(self.screen.root)?.reply()?;
+        Ok((pointer.root_x, pointer.root_y))
+    }
+
+    pub fn destroy_bar_window(&self, bar: &WindowState) -> Res {
+        log::debug!("destroying bar: {}", bar.window);
+        self.conn.destroy_window(bar.window)?;
+        self.conn.destroy_window(bar.frame_window)?;
         Ok(())
     }
 
@@ -373,50 +1203,52 @@ impl<'a, C: Connection> ConnectionHandler<'a, C> {
             ClientMessageEvent::new(
                 32,
                 focus,
-                self.atoms["WM_PROTOCOLS"],
-                [self.atoms["WM_DELETE_WINDOW"], 0, 0, 0, 0],
+                self.atoms.WM_PROTOCOLS,
+                [self.atoms.WM_DELETE_WINDOW, 0, 0, 0, 0],
             ),
         )?;
         Ok(())
     }
 
-    pub fn draw_bar(&self, wm_state: &StateHandler, active_window: Option<Window>) -> Res {
-        let bar_text = match active_window {
+    pub fn draw_bar(&self, wm_state: &StateHandler, monitor: &Monitor) -> Res {
+        if !monitor.tiling.show_bar {
+            return Ok(());
+        }
+        let bar = &monitor.bar;
+        let offset = wm_state.get_tag_offset(monitor);
+        let tags = &wm_state.tags[offset..offset + wm_state.tag_count];
+        let active_tag = monitor.active_tag - offset;
+        // tags toggled into view are drawn like the active one
+        let viewed = |x: usize| monitor.viewed_tags().any(|t| t == offset + x - 1);
+        let bar_text = match tags[active_tag].focus {
             Some(w) => self.get_window_name(w)?,
             None => "".to_owned(),
         };
 
         log::debug!("drawing bar with text: {bar_text}");
 
-        self.conn.clear_area(
-            false,
-            self.bar.window,
-            self.bar.x,
-            self.bar.y,
-            self.bar.width / 2,
-            self.bar.height,
-        )?;
+        self.conn
+            .clear_area(false, bar.window, 0, 0, bar.width / 2, bar.height)?;
 
-        let h = self.font_ascent as u16 * 3 / 2;
+        let h = self.bar_height;
 
+        let graphics = &self.bar_graphics;
         //draw regular tag rect
         self.conn.poly_fill_rectangle(
-            self.bar.window,
-            self.id_inverted_graphics_context,
-            &(1..=9)
-                .filter(|x| *x != wm_state.active_tag + 1)
+            bar.window,
+            graphics.inactive_tag,
+            &(1..=tags.len())
+                .filter(|x| !viewed(*x))
                 .map(|x| self.create_tag_rectangle(h, x))
                 .collect::<Vec<_>>(),
         )?;
 
         //draw indicator that windows are active in tag
         self.conn.poly_fill_rectangle(
-            self.bar.window,
-            self.id_graphics_context,
-            &(1..=9)
-                .filter(|x| {
-                    *x != wm_state.active_tag + 1 && !wm_state.tags[x - 1].windows.is_empty()
-                })
+            bar.window,
+            graphics.indicator,
+            &(1..=tags.len())
+                .filter(|x| !viewed(*x) && wm_state.is_tag_occupied(offset + x - 1))
                 .map(|x| Rectangle {
                     x: h as i16 * (x as i16 - 1) + h as i16 / 9,
                     y: h as i16 / 9,
@@ -428,53 +1260,77 @@ impl<'a, C: Connection> ConnectionHandler<'a, C> {
 
         //draw active tag rect
         self.conn.poly_fill_rectangle(
-            self.bar.window,
-            self.id_graphics_context,
-            &[self.create_tag_rectangle(h, wm_state.active_tag + 1)],
+            bar.window,
+            graphics.active_tag,
+            &(1..=tags.len())
+                .filter(|x| viewed(*x))
+                .map(|x| self.create_tag_rectangle(h, x))
+                .collect::<Vec<_>>(),
         )?;
 
-        if !wm_state.tags[wm_state.active_tag].windows.is_empty() {
-            self.conn.poly_fill_rectangle(
-                self.bar.window,
-                self.id_inverted_graphics_context,
-                &[Rectangle {
-                    x: h as i16 * (wm_state.active_tag as i16) + h as i16 / 9,
+        // in the inactive tag color, to stand out from the active one
+        self.conn.poly_fill_rectangle(
+            bar.window,
+            graphics.inactive_tag,
+            &(1..=tags.len())
+                .filter(|x| viewed(*x) && wm_state.is_tag_occupied(offset + x - 1))
+                .map(|x| Rectangle {
+                    x: h as i16 * (x as i16 - 1) + h as i16 / 9,
                     y: h as i16 / 9,
                     width: h / 7,
                     height: h / 7,
-                }],
-            )?;
-        }
+                })
+                .collect::<Vec<Rectangle>>(),
+        )?;
 
         let text_y = (h as i16 / 2) + self.font_ascent / 5 * 2;
         //draw regular text
-        (1..=9).try_for_each(|x| {
+        (1..=tags.len()).try_for_each(|x| {
             let text = x.to_string();
-            if x == wm_state.active_tag + 1 {
+            let text_x = (h / 2).saturating_sub(self.font_width as u16 * text.len() as u16 / 2);
+            if viewed(x) {
                 self.conn.image_text8(
-                    self.bar.window,
-                    self.id_inverted_graphics_context,
-                    (h * (x as u16 - 1) + (h / 2 - (self.font_width as u16 / 2))) as i16,
+                    bar.window,
+                    graphics.active_tag_text,
+                    (h * (x as u16 - 1) + text_x) as i16,
                     text_y,
                     text.as_bytes(),
                 )?;
             } else {
                 self.conn.image_text8(
-                    self.bar.window,
-                    self.id_graphics_context,
-                    (h * (x as u16 - 1) + (h / 2 - (self.font_width as u16 / 2))) as i16,
+                    bar.window,
+                    graphics.inactive_tag_text,
+                    (h * (x as u16 - 1) + text_x) as i16,
                     text_y,
                     text.as_bytes(),
                 )?;
             }
-            Ok::<(), ReplyOrIdError>(())
+            Ok::<(), WmError>(())
         })?;
 
+        //draw monitor name, highlighted on the active monitor
+        let mut text_x = h as i16 * tags.len() as i16 + h as i16 / 2;
+        if wm_state.monitors.len() > 1 {
+            let is_active = wm_state.monitors[wm_state.active_monitor].name == monitor.name;
+            self.conn.image_text8(
+                bar.window,
+                if is_active {
+                    self.id_inverted_graphics_context
+                } else {
+                    self.id_graphics_context
+                },
+                text_x,
+                text_y,
+                format!(" {} ", monitor.name).as_bytes(),
+            )?;
+            text_x += (monitor.name.len() as i16 + 3) * self.font_width;
+        }
+
         //draw window name text
         self.conn.image_text8(
-            self.bar.window,
+            bar.window,
             self.id_graphics_context,
-            h as i16 * 9 + h as i16 / 2,
+            text_x,
             text_y,
             bar_text.as_bytes(),
         )?;
@@ -482,28 +1338,28 @@ impl<'a, C: Connection> ConnectionHandler<'a, C> {
         Ok(())
     }
 
-    pub fn draw_status_bar(&self) -> Res {
+    pub fn draw_status_bar(&self, monitor: &Monitor) -> Res {
+        if !monitor.tiling.show_bar {
+            return Ok(());
+        }
+        let bar = &monitor.bar;
         let status_text = self.get_window_name(self.screen.root)?;
         log::debug!("drawing root windows name on bar with text: {status_text}");
-        self.conn
-            .clear_area(
-                false,
-                self.bar.window,
-                self.bar.width as i16 - (status_text.len() + 5) as i16 * self.font_width,
-                self.bar.y,
-                self.bar.width,
-                self.bar.height,
-            )?
-            .check()?;
-        self.conn
-            .image_text8(
-                self.bar.window,
-                self.id_graphics_context,
-                self.bar.width as i16 - status_text.len() as i16 * self.font_width,
-                (self.bar.height as i16 / 2) + self.font_ascent / 3,
-                status_text.as_bytes(),
-            )?
-            .check()?;
+        self.conn.clear_area(
+            false,
+            bar.window,
+            bar.width as i16 - (status_text.len() + 5) as i16 * self.font_width,
+            0,
+            bar.width,
+            bar.height,
+        )?;
+        self.conn.image_text8(
+            bar.window,
+            self.bar_graphics.status_text,
+            bar.width as i16 - status_text.len() as i16 * self.font_width,
+            (bar.height as i16 / 2) + self.font_ascent / 3,
+            status_text.as_bytes(),
+        )?;
         Ok(())
     }
 
@@ -512,8 +1368,8 @@ impl<'a, C: Connection> ConnectionHandler<'a, C> {
         self.config_window_from_state(window)?;
         self.change_atom_prop(
             window.window,
-            "_NET_WM_STATE",
-            &self.atoms["_NET_WM_STATE_FULLSCREEN"].to_ne_bytes(),
+            self.atoms._NET_WM_STATE,
+            &self.atoms._NET_WM_STATE_FULLSCREEN.to_ne_bytes(),
         )?;
         self.conn.configure_window(
             window.frame_window,
@@ -522,14 +1378,37 @@ impl<'a, C: Connection> ConnectionHandler<'a, C> {
         Ok(())
     }
 
-    pub fn get_atom_name(&self, atom: u32) -> Result<String, ReplyOrIdError> {
-        match String::from_utf8(self.conn.get_atom_name(atom)?.reply()?.name) {
-            Ok(s) => Ok(s),
-            Err(_) => Ok("".to_string()),
+    pub fn set_fullscreen_monitors(&self, window: Window, monitors: Option<[usize; 4]>) -> Res {
+        match monitors {
+            Some(m) => {
+                self.conn.change_property32(
+                    PropMode::REPLACE,
+                    window,
+                    self.atoms._NET_WM_FULLSCREEN_MONITORS,
+                    AtomEnum::CARDINAL,
+                    &m.map(|i| i as u32),
+                )?;
+            }
+            None => {
+                self.conn
+                    .delete_property(window, self.atoms._NET_WM_FULLSCREEN_MONITORS)?;
+            }
+        };
+        Ok(())
+    }
+
+    /// the name of `atom`, asking the server only the first time
+    pub fn get_atom_name(&self, atom: Atom) -> Result<String, WmError> {
+        if let Some(name) = self.atom_names.borrow().get(&atom) {
+            return Ok(name.clone());
         }
+        let name =
+            String::from_utf8(self.conn.get_atom_name(atom)?.reply()?.name).unwrap_or_default();
+        self.atom_names.borrow_mut().insert(atom, name.clone());
+        Ok(name)
     }
 
-    fn get_window_name(&self, window: Window) -> Result<String, ReplyOrIdError> {
+    pub fn get_window_name(&self, window: Window) -> Result<String, WmError> {
         log::debug!("getting window name of {window}");
 
         let result = String::from_utf8(
@@ -537,8 +1416,8 @@ impl<'a, C: Connection> ConnectionHandler<'a, C> {
                 .get_property(
                     false,
                     window,
-                    self.atoms["_NET_WM_NAME"],
-                    self.atoms["UTF8_STRING"],
+                    self.atoms._NET_WM_NAME,
+                    self.atoms.UTF8_STRING,
                     0,
                     100,
                 )?
@@ -547,7 +1426,7 @@ impl<'a, C: Connection> ConnectionHandler<'a, C> {
         )
         .unwrap_or_default();
 
-        return if result.is_empty() {
+        if result.is_empty() {
             let result = String::from_utf8(
                 self.conn
                     .get_property(false, window, AtomEnum::WM_NAME, AtomEnum::STRING, 0, 100)?
@@ -558,7 +1437,238 @@ impl<'a, C: Connection> ConnectionHandler<'a, C> {
             Ok(result)
         } else {
             Ok(result)
+        }
+    }
+
+    pub fn is_dock(&self, window: Window) -> Result<bool, WmError> {
+        let dock = self.atoms._NET_WM_WINDOW_TYPE_DOCK;
+        Ok(self
+            .conn
+            .get_property(
+                false,
+                window,
+                self.atoms._NET_WM_WINDOW_TYPE,
+                AtomEnum::ATOM,
+                0,
+                32,
+            )?
+            .reply()?
+            .value32()
+            .is_some_and(|mut types| types.any(|t| t == dock)))
+    }
+
+    /// reads `_NET_WM_STRUT_PARTIAL`, falling back to `_NET_WM_STRUT`
+    pub fn get_dock(&self, window: Window) -> Result<Dock, WmError> {
+        let mut values = Vec::new();
+        for property in [self.atoms._NET_WM_STRUT_PARTIAL, self.atoms._NET_WM_STRUT] {
+            values = self
+                .conn
+                .get_property(false, window, property, AtomEnum::CARDINAL, 0, 12)?
+                .reply()?
+                .value32()
+                .map(|v| v.collect::<Vec<_>>())
+                .unwrap_or_default();
+            if values.len() >= 4 {
+                break;
+            }
+        }
+        values.resize(4, 0);
+        let geometry = self.conn.get_geometry(window)?.reply()?;
+        Ok(Dock {
+            window,
+            x: geometry.x + (geometry.width / 2) as i16,
+            y: geometry.y + (geometry.height / 2) as i16,
+            strut: Edges {
+                left: values[0] as u16,
+                right: values[1] as u16,
+                top: values[2] as u16,
+                bottom: values[3] as u16,
+            },
+        })
+    }
+
+    /// publishes the workarea of the monitor showing each tag, or of the active monitor
+    pub fn set_workarea(&self, wm_state: &StateHandler) -> Res {
+        let values = (0..wm_state.tags.len())
+            .flat_map(|t| {
+                let (x, y, w, h) = wm_state.get_workarea(
+                    wm_state
+                        .get_monitor_of_tag(t)
+                        .unwrap_or(wm_state.active_monitor),
+                );
+                [x as u32, y as u32, w as u32, h as u32]
+            })
+            .collect::<Vec<_>>();
+        self.conn.change_property32(
+            PropMode::REPLACE,
+            self.screen.root,
+            self.atoms._NET_WORKAREA,
+            AtomEnum::CARDINAL,
+            &values,
+        )?;
+        Ok(())
+    }
+
+    /// state for scripts polling with `xprop -root -spy`, tags counting from 1
+    pub fn set_state_properties(&self, wm_state: &StateHandler) -> Res {
+        let active_tag = wm_state.active_tag();
+        let visible = wm_state
+            .monitors
+            .iter()
+            .flat_map(|m| m.viewed_tags())
+            .map(|t| t as u32 + 1)
+            .collect::<Vec<_>>();
+        let occupancy = wm_state
+            .tags
+            .iter()
+            .map(|t| t.windows.len() as u32)
+            .collect::<Vec<_>>();
+        let layout = match self.config.get_layout_command(active_tag) {
+            Some(_) => "external",
+            None => "master-stack",
+        };
+        [
+            (self.atoms._RWM_ACTIVE_TAG, vec![active_tag as u32 + 1]),
+            (self.atoms._RWM_VISIBLE_TAGS, visible),
+            (self.atoms._RWM_TAG_OCCUPANCY, occupancy),
+        ]
+        .iter()
+        .try_for_each(|(name, values)| {
+            self.conn.change_property32(
+                PropMode::REPLACE,
+                self.screen.root,
+                *name,
+                AtomEnum::CARDINAL,
+                values,
+            )?;
+            Ok::<(), WmError>(())
+        })?;
+        self.conn.change_property8(
+            PropMode::REPLACE,
+            self.screen.root,
+            self.atoms._RWM_LAYOUT,
+            self.atoms.UTF8_STRING,
+            layout.as_bytes(),
+        )?;
+        Ok(())
+    }
+
+    /// advertises the i3 socket where i3 clients look for it
+    pub fn set_i3_socket_path(&self, path: &std::path::Path) -> Res {
+        self.conn.change_property8(
+            PropMode::REPLACE,
+            self.screen.root,
+            self.atoms.I3_SOCKET_PATH,
+            AtomEnum::STRING,
+            path.as_os_str().as_encoded_bytes(),
+        )?;
+        Ok(())
+    }
+
+    /// what window rules are matched against
+    pub fn get_window_properties(&self, window: Window) -> Result<WindowProperties, WmError> {
+        let (instance, class) = self.get_wm_class(window)?;
+        let window_type = match self
+            .conn
+            .get_property(
+                false,
+                window,
+                self.atoms._NET_WM_WINDOW_TYPE,
+                AtomEnum::ATOM,
+                0,
+                1,
+            )?
+            .reply()?
+            .value32()
+            .and_then(|mut types| types.next())
+        {
+            Some(atom) => self
+                .get_atom_name(atom)?
+                .trim_start_matches("_NET_WM_WINDOW_TYPE_")
+                .to_lowercase(),
+            None => "normal".to_owned(),
         };
+        Ok(WindowProperties {
+            instance,
+            class,
+            title: self.get_window_name(window)?,
+            window_type,
+        })
+    }
+
+    /// allocates a `#rrggbb` color, `None` if it isn't one
+    pub fn alloc_hex_color(&self, hex: &str) -> Result<Option<u32>, WmError> {
+        match config::parse_hex_color(hex) {
+            Some(rgb) => Ok(Some(get_color_id(
+                self.conn,
+                self.screen,
+                &self.pixels,
+                rgb,
+            )?)),
+            None => Ok(None),
+        }
+    }
+
+    /// sets `_NET_WM_WINDOW_OPACITY` on the frame, which compositors see as the window
+    pub fn set_opacity(&self, window: &WindowState, opacity: f32) -> Res {
+        let value = (opacity.clamp(0.0, 1.0) as f64 * u32::MAX as f64) as u32;
+        self.conn.change_property32(
+            PropMode::REPLACE,
+            window.frame_window,
+            self.atoms._NET_WM_WINDOW_OPACITY,
+            AtomEnum::CARDINAL,
+            &[value],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_pid(&self, window: Window) -> Result<Option<u32>, WmError> {
+        Ok(self
+            .conn
+            .get_property(
+                false,
+                window,
+                self.atoms._NET_WM_PID,
+                AtomEnum::CARDINAL,
+                0,
+                1,
+            )?
+            .reply()?
+            .value32()
+            .and_then(|mut pids| pids.next()))
+    }
+
+    /// mapped top level windows other than `ignore`, e.g. the ones left by a restart
+    pub fn get_existing_windows(&self, ignore: &[Window]) -> Result<Vec<Window>, WmError> {
+        let children = self.conn.query_tree(self.screen.root)?.reply()?.children;
+        let mut windows = Vec::new();
+        for window in children.into_iter().filter(|w| !ignore.contains(w)) {
+            // windows can be gone by the time they are asked about
+            let attributes = match self.conn.get_window_attributes(window)?.reply() {
+                Ok(a) => a,
+                Err(_) => continue,
+            };
+            if !attributes.override_redirect && attributes.map_state == MapState::VIEWABLE {
+                windows.push(window);
+            }
+        }
+        Ok(windows)
+    }
+
+    /// returns the instance and class parts of WM_CLASS
+    pub fn get_wm_class(&self, window: Window) -> Result<(String, String), WmError> {
+        let value = self
+            .conn
+            .get_property(false, window, AtomEnum::WM_CLASS, AtomEnum::STRING, 0, 100)?
+            .reply()?
+            .value;
+        let mut parts = value
+            .split(|b| *b == 0)
+            .map(|p| String::from_utf8_lossy(p).into_owned());
+        Ok((
+            parts.next().unwrap_or_default(),
+            parts.next().unwrap_or_default(),
+        ))
     }
 
     fn create_tag_rectangle(&self, h: u16, x: usize) -> Rectangle {
@@ -585,39 +1695,35 @@ impl<'a, C: Connection> ConnectionHandler<'a, C> {
         Ok(())
     }
 
-    fn change_atom_prop(&self, window: Window, property: &str, data: &[u8]) -> Res {
-        self.conn
-            .change_property(
-                PropMode::REPLACE,
-                window,
-                self.atoms[property],
-                AtomEnum::ATOM,
-                32,
-                data.len() as u32 / 4,
-                data,
-            )?
-            .check()?;
+    fn change_atom_prop(&self, window: Window, property: Atom, data: &[u8]) -> Res {
+        self.conn.change_property(
+            PropMode::REPLACE,
+            window,
+            property,
+            AtomEnum::ATOM,
+            32,
+            data.len() as u32 / 4,
+            data,
+        )?;
         Ok(())
     }
 
-    pub fn remove_atom_prop(&self, window: Window, property: &str) -> Res {
-        self.conn
-            .change_property(
-                PropMode::REPLACE,
-                window,
-                self.atoms[property],
-                AtomEnum::ATOM,
-                32,
-                1,
-                &[0, 0, 0, 0],
-            )?
-            .check()?;
+    pub fn remove_atom_prop(&self, window: Window, property: Atom) -> Res {
+        self.conn.change_property(
+            PropMode::REPLACE,
+            window,
+            property,
+            AtomEnum::ATOM,
+            32,
+            1,
+            &[0, 0, 0, 0],
+        )?;
         Ok(())
     }
 
     fn add_heartbeat_window(&self) -> Res {
-        let support_atom = "_NET_SUPPORTING_WM_CHECK";
-        let name_atom = "_NET_WM_NAME";
+        let support_atom = self.atoms._NET_SUPPORTING_WM_CHECK;
+        let name_atom = self.atoms._NET_WM_NAME;
         let proof_window_id = self.conn.generate_id()?;
 
         self.conn.create_window(
@@ -637,7 +1743,7 @@ impl<'a, C: Connection> ConnectionHandler<'a, C> {
         self.conn.change_property(
             PropMode::REPLACE,
             self.screen.root,
-            self.atoms[support_atom],
+            support_atom,
             AtomEnum::WINDOW,
             32,
             1,
@@ -646,7 +1752,7 @@ impl<'a, C: Connection> ConnectionHandler<'a, C> {
         self.conn.change_property(
             PropMode::REPLACE,
             proof_window_id,
-            self.atoms[support_atom],
+            support_atom,
             AtomEnum::WINDOW,
             32,
             1,
@@ -655,7 +1761,7 @@ impl<'a, C: Connection> ConnectionHandler<'a, C> {
         self.conn.change_property(
             PropMode::REPLACE,
             proof_window_id,
-            self.atoms[name_atom],
+            name_atom,
             AtomEnum::STRING,
             8,
             "hematite".len() as u32,
@@ -664,84 +1770,562 @@ impl<'a, C: Connection> ConnectionHandler<'a, C> {
         Ok(())
     }
 
+    fn select_screen_change_input(&self) -> Res {
+        if self
+            .conn
+            .extension_information(randr::X11_EXTENSION_NAME)?
+            .is_none()
+        {
+            return Ok(());
+        }
+        self.conn
+            .randr_select_input(self.screen.root, randr::NotifyMask::SCREEN_CHANGE)?;
+        Ok(())
+    }
+
+    /// grabs the mouse bindings of `context` on a frame
+    fn grab_buttons(&self, window: &WindowState, context: MouseContext) -> Res {
+        self.config
+            .mousebinds
+            .iter()
+            .filter(|m| m.context == context)
+            .try_for_each(|m| {
+                // clicks that focus are replayed to the client afterwards
+                let mode = match m.action {
+                    HotkeyAction::FocusClicked => GrabMode::SYNC,
+                    _ => GrabMode::ASYNC,
+                };
+                let modifier =
+                    ModMask::from(parse_modifiers(&m.modifiers, self.config.mod_key).bits());
+                LOCK_MASKS.iter().try_for_each(|lock| {
+                    self.conn.grab_button(
+                        false,
+                        window.frame_window,
+                        EventMask::BUTTON_PRESS
+                            | EventMask::BUTTON_RELEASE
+                            | EventMask::POINTER_MOTION,
+                        mode,
+                        GrabMode::ASYNC,
+                        x11rb::NONE,
+                        x11rb::NONE,
+                        ButtonIndex::from(m.button),
+                        modifier | ModMask::from(*lock),
+                    )?;
+                    Ok(())
+                })
+            })
+    }
+
+    /// replaces all key grabs with the hotkeys of `handler`
+    pub fn regrab_keys(&self, handler: &KeyHandler) -> Res {
+        self.conn
+            .ungrab_key(Grab::ANY, self.screen.root, ModMask::ANY)?;
+        self.grab_keys(handler)
+    }
+
+    /// grabs every hotkey, a binding that can't be grabbed is logged and skipped
     fn grab_keys(&self, handler: &KeyHandler) -> Res {
         handler.hotkeys.iter().try_for_each(|h| {
-            self.conn
-                .grab_key(
-                    false,
-                    self.screen.root,
-                    h.modifier,
-                    h.code,
-                    GrabMode::ASYNC,
-                    GrabMode::ASYNC,
-                )?
-                .check()
+            let result = LOCK_MASKS.iter().try_for_each(|lock| {
+                self.conn
+                    .grab_key(
+                        false,
+                        self.screen.root,
+                        h.modifier | ModMask::from(*lock),
+                        h.code,
+                        GrabMode::ASYNC,
+                        GrabMode::ASYNC,
+                    )?
+                    .check()
+            });
+            match result {
+                Err(ReplyError::X11Error(e)) => {
+                    let reason = match e.error_kind {
+                        ErrorKind::Access => "already grabbed by another client".to_owned(),
+                        kind => format!("{kind:?}"),
+                    };
+                    log::error!("couldn't grab hotkey \"{}\": {reason}", h.description);
+                    Ok(())
+                }
+                result => result,
+            }
         })?;
         Ok(())
     }
 }
 
-pub fn spawn_command(command: &str) {
-    match Command::new("sh").arg("-c").arg(command).spawn() {
-        Ok(_) => (),
+pub fn spawn(command: &SpawnCommand) {
+    let name = match command {
+        SpawnCommand::Shell(command) => command.clone(),
+        SpawnCommand::Exec { argv, .. } => argv.join(" "),
+    };
+    let result = match command {
+        SpawnCommand::Shell(command) => Command::new("sh").arg("-c").arg(command).spawn(),
+        SpawnCommand::Exec { argv, env, cwd } => {
+            let (program, args) = match argv.split_first() {
+                Some(a) => a,
+                None => {
+                    log::error!("spawn with an empty argv");
+                    return;
+                }
+            };
+            let mut command = Command::new(program);
+            command.args(args).envs(env);
+            if let Some(cwd) = cwd {
+                command.current_dir(cwd);
+            }
+            command.spawn()
+        }
+    };
+    // the child is reaped on SIGCHLD
+    match result {
+        Ok(child) => log::debug!("spawned {name:?} as {}", child.id()),
         Err(e) => log::error!("error when spawning command {e:?}"),
     };
 }
 
-fn get_atom_mapping(atom_strings: &[&str], atom_nums: &[u32]) -> HashMap<String, u32> {
-    let mut atoms: HashMap<String, u32> = HashMap::new();
-    atom_strings
-        .iter()
-        .map(|s| s.to_string())
-        .zip(atom_nums)
-        .for_each(|(k, v)| {
-            atoms.insert(k, *v);
-        });
-    atoms
+/// how long a layout command gets to answer before it is killed
+const LAYOUT_TIMEOUT: Duration = Duration::from_millis(300);
+
+/// writes `input` to the command's stdin and reads one `x y width height` line per window back.
+/// a command that hasn't closed its stdout within [`LAYOUT_TIMEOUT`] is killed along with what
+/// it started, even if it exited and left something in the background holding the pipe
+pub fn run_layout(command: &str, input: &str, count: usize) -> Result<Vec<[i32; 4]>, String> {
+    let deadline = Instant::now() + LAYOUT_TIMEOUT;
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .process_group(0)
+        .spawn()
+        .map_err(|e| e.to_string())?;
+    let result = exchange(&mut child, format!("{input}\n").as_bytes(), deadline);
+    // the answer is in, the command only gets until the deadline to exit
+    while result.is_ok() && child.try_wait().map_err(|e| e.to_string())?.is_none() {
+        if Instant::now() >= deadline {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    if result.is_err() || child.try_wait().map_err(|e| e.to_string())?.is_none() {
+        // SAFETY: plain syscall, the group is the one the child leads and it isn't reaped yet
+        unsafe { libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL) };
+    }
+    let _ = child.wait();
+    let output = result?;
+    let geometries = String::from_utf8_lossy(&output)
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| {
+            let numbers = l
+                .split_whitespace()
+                .map(|n| n.parse().map_err(|_| format!("bad number {n:?}")))
+                .collect::<Result<Vec<i32>, _>>()?;
+            numbers
+                .try_into()
+                .map_err(|_| format!("expected x y width height, got {l:?}"))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    match geometries.len() == count {
+        true => Ok(geometries),
+        false => Err(format!(
+            "expected {count} geometries, got {}",
+            geometries.len()
+        )),
+    }
 }
 
-fn get_atom_nums<C: Connection>(
+/// writes `input` to the child's stdin while reading its stdout until it is closed, without
+/// blocking past `deadline`. a command that exits without reading its input is fine
+fn exchange(child: &mut Child, input: &[u8], deadline: Instant) -> Result<Vec<u8>, String> {
+    let mut stdout = child.stdout.take().ok_or("no stdout")?;
+    let mut stdin = child.stdin.take();
+    if let Some(stdin) = &stdin {
+        // SAFETY: plain syscalls on a pipe the child handle owns
+        unsafe {
+            let flags = libc::fcntl(stdin.as_raw_fd(), libc::F_GETFL);
+            libc::fcntl(stdin.as_raw_fd(), libc::F_SETFL, flags | libc::O_NONBLOCK);
+        }
+    }
+    let (mut written, mut output, mut buffer) = (0, Vec::new(), [0u8; 4096]);
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(format!("no answer within {}ms", LAYOUT_TIMEOUT.as_millis()));
+        }
+        let mut fds = vec![libc::pollfd {
+            fd: stdout.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        }];
+        fds.extend(stdin.as_ref().map(|s| libc::pollfd {
+            fd: s.as_raw_fd(),
+            events: libc::POLLOUT,
+            revents: 0,
+        }));
+        // SAFETY: fds lives through the call and its length is passed along
+        let ready = unsafe {
+            libc::poll(
+                fds.as_mut_ptr(),
+                fds.len() as libc::nfds_t,
+                left.as_millis() as libc::c_int + 1,
+            )
+        };
+        if ready < 0 {
+            let error = io::Error::last_os_error();
+            match error.kind() {
+                io::ErrorKind::Interrupted => continue,
+                _ => return Err(error.to_string()),
+            }
+        }
+        if let (Some(pipe), Some(fd)) = (&mut stdin, fds.get(1))
+            && fd.revents != 0
+        {
+            match pipe.write(&input[written..]) {
+                Ok(n) => written += n,
+                Err(e) if is_transient(&e) => {}
+                // EPIPE, the command doesn't want its input
+                Err(_) => written = input.len(),
+            }
+            if written == input.len() {
+                // closing it tells the command the input is complete
+                stdin = None;
+            }
+        }
+        if fds[0].revents != 0 {
+            match stdout.read(&mut buffer) {
+                Ok(0) => return Ok(output),
+                Ok(n) => output.extend_from_slice(&buffer[..n]),
+                Err(e) if is_transient(&e) => {}
+                Err(e) => return Err(e.to_string()),
+            }
+        }
+    }
+}
+
+/// a read or write on a non-blocking pipe that can be tried again
+fn is_transient(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted
+    )
+}
+
+/// follows the parents of `pid` through /proc looking for `ancestor`
+pub fn is_descendant(pid: u32, ancestor: u32) -> bool {
+    let mut pid = pid;
+    while pid > 1 {
+        if pid == ancestor {
+            return true;
+        }
+        // the parent is the second field after the parenthesized command name
+        pid = match std::fs::read_to_string(format!("/proc/{pid}/stat"))
+            .ok()
+            .and_then(|stat| {
+                let (_, rest) = stat.rsplit_once(')')?;
+                rest.split_whitespace().nth(1)?.parse().ok()
+            }) {
+            Some(p) => p,
+            None => return false,
+        };
+    }
+    false
+}
+
+/// runs a hook from the config, if one is set
+pub fn run_hook(name: &str, command: &Option<String>, env: &[(&str, String)]) {
+    let command = match command {
+        Some(c) => c,
+        None => return,
+    };
+    log::debug!("running {name} hook with {env:?}");
+    let result = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .envs(env.iter().map(|(k, v)| (k, v)))
+        .spawn();
+    match result {
+        Ok(child) => log::debug!("running {name} hook as {}", child.id()),
+        Err(e) => log::error!("error when running {name} hook {e:?}"),
+    }
+}
+
+/// takes the ICCCM `WM_Sn` selection, waiting for the window manager holding it to exit
+/// when replacing it, and announces the new owner with a `MANAGER` message
+pub fn acquire_wm_selection<C: Connection>(
     conn: &C,
-    atom_strings: &[&str],
-) -> Result<Vec<u32>, ReplyOrIdError> {
-    Ok(atom_strings
-        .iter()
-        .flat_map(|s| -> Result<u32, ReplyOrIdError> {
-            Ok(conn.intern_atom(false, s.as_bytes())?.reply()?.atom)
-        })
-        .collect())
+    screen_num: usize,
+    replace: bool,
+) -> Result<Window, WmError> {
+    let root = conn.setup().roots[screen_num].root;
+    let selection = conn
+        .intern_atom(false, format!("WM_S{screen_num}").as_bytes())?
+        .reply()?
+        .atom;
+    let manager = conn.intern_atom(false, b"MANAGER")?.reply()?.atom;
+
+    let old_owner = conn.get_selection_owner(selection)?.reply()?.owner;
+    if old_owner != x11rb::NONE {
+        if !replace {
+            return Err(WmError::WmRunning);
+        }
+        log::info!("replacing the window manager owning {old_owner}");
+        conn.change_window_attributes(
+            old_owner,
+            &ChangeWindowAttributesAux::new().event_mask(EventMask::STRUCTURE_NOTIFY),
+        )?;
+    }
+
+    let window = conn.generate_id()?;
+    conn.create_window(
+        COPY_DEPTH_FROM_PARENT,
+        window,
+        root,
+        -1,
+        -1,
+        1,
+        1,
+        0,
+        WindowClass::INPUT_ONLY,
+        0,
+        &CreateWindowAux::new(),
+    )?;
+    conn.set_selection_owner(window, selection, CURRENT_TIME)?;
+    if conn.get_selection_owner(selection)?.reply()?.owner != window {
+        return Err(WmError::Selection(screen_num));
+    }
+
+    if old_owner != x11rb::NONE {
+        conn.flush()?;
+        // the old window manager's selection window goes away once it has let go of everything
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        loop {
+            match conn.poll_for_event()? {
+                Some(x11rb::protocol::Event::DestroyNotify(e)) if e.window == old_owner => break,
+                Some(_) => (),
+                None if std::time::Instant::now() > deadline => {
+                    log::error!("old window manager didn't exit, trying anyway");
+                    break;
+                }
+                None => std::thread::sleep(std::time::Duration::from_millis(20)),
+            }
+        }
+    }
+
+    conn.send_event(
+        false,
+        root,
+        EventMask::STRUCTURE_NOTIFY,
+        ClientMessageEvent::new(32, root, manager, [CURRENT_TIME, selection, window, 0, 0]),
+    )?;
+    Ok(window)
 }
 
-fn become_window_manager<C: Connection>(conn: &C, root: u32) -> Res {
+fn become_window_manager<C: Connection>(conn: &C, screen_num: usize, root: u32) -> Res {
     let change = ChangeWindowAttributesAux::default().event_mask(
         EventMask::SUBSTRUCTURE_REDIRECT
             | EventMask::SUBSTRUCTURE_NOTIFY
+            | EventMask::STRUCTURE_NOTIFY
+            | EventMask::POINTER_MOTION
+            | EventMask::BUTTON_PRESS
             | EventMask::KEY_PRESS
             | EventMask::PROPERTY_CHANGE,
     );
     let result = conn.change_window_attributes(root, &change)?.check();
 
-    if let Err(ReplyError::X11Error(ref error)) = result {
-        if error.error_kind == ErrorKind::Access {
-            log::error!("another wm is running");
-            exit(1);
-        } else {
+    match result {
+        Err(ReplyError::X11Error(ref error)) if error.error_kind == ErrorKind::Access => {
+            Err(WmError::RedirectTaken(screen_num))
+        }
+        Err(e) => Err(e.into()),
+        Ok(()) => {
+            log::info!("became window manager successfully");
+            Ok(())
         }
-    } else {
-        log::info!("became window manager successfully");
     }
-    Ok(())
 }
 
+/// scale of pixel sizes for the screen's dpi,
+/// snapped to quarter steps so panels close to 96 dpi stay unscaled
+fn get_scale<C: Connection>(conn: &C, screen: &Screen, config: &Config) -> Result<f32, ReplyError> {
+    let scale = (get_dpi(conn, screen, config)? / config::BASE_DPI * 4.0).round() / 4.0;
+    log::info!("scaling sizes by {scale}");
+    Ok(scale)
+}
+
+fn get_dpi<C: Connection>(conn: &C, screen: &Screen, config: &Config) -> Result<f32, ReplyError> {
+    if let Some(dpi) = config.dpi {
+        log::info!("using dpi {dpi} from config");
+        return Ok(dpi);
+    }
+    if let Some(dpi) = resource_manager::new_from_default(conn)?
+        .get_string("Xft.dpi", "")
+        .and_then(|d| d.trim().parse::<f32>().ok())
+    {
+        log::info!("using dpi {dpi} from Xft.dpi");
+        return Ok(dpi);
+    }
+    if screen.width_in_millimeters > 0 {
+        let dpi = screen.width_in_pixels as f32 * 25.4 / screen.width_in_millimeters as f32;
+        log::info!("using dpi {dpi} from screen size");
+        return Ok(dpi);
+    }
+    Ok(config::BASE_DPI)
+}
+
+type Rgb = (u16, u16, u16);
+
+/// pixels of the palette, with the main and secondary color filled in for unset ones
+struct BarColors {
+    background: u32,
+    foreground: u32,
+    active_tag: u32,
+    inactive_tag: u32,
+    status_text: u32,
+    indicator: u32,
+}
+
+impl BarColors {
+    fn new<C: Connection>(
+        conn: &C,
+        screen: &Screen,
+        pixels: &RefCell<HashMap<Rgb, u32>>,
+        config: &Config,
+        colors: BaseColors,
+    ) -> Result<Self, WmError> {
+        let (main, secondary) = (colors.main, colors.secondary);
+        let palette = &config.palette;
+        let pixel = |color: Option<Rgb>, fallback| {
+            get_color_id(conn, screen, pixels, color.unwrap_or(fallback))
+        };
+        Ok(BarColors {
+            background: pixel(palette.bar_background, main)?,
+            foreground: pixel(palette.bar_foreground, colors.foreground)?,
+            active_tag: pixel(palette.active_tag, secondary)?,
+            inactive_tag: pixel(palette.inactive_tag, main)?,
+            status_text: pixel(palette.status_text, secondary)?,
+            indicator: pixel(palette.indicator, secondary)?,
+        })
+    }
+}
+
+/// graphics contexts of the parts of the bar with their own color,
+/// filling with the foreground and writing text over the background
+struct BarGraphics {
+    active_tag: Gcontext,
+    active_tag_text: Gcontext,
+    inactive_tag: Gcontext,
+    inactive_tag_text: Gcontext,
+    status_text: Gcontext,
+    indicator: Gcontext,
+}
+
+impl BarGraphics {
+    fn new<C: Connection>(conn: &C) -> Result<Self, WmError> {
+        Ok(BarGraphics {
+            active_tag: conn.generate_id()?,
+            active_tag_text: conn.generate_id()?,
+            inactive_tag: conn.generate_id()?,
+            inactive_tag_text: conn.generate_id()?,
+            status_text: conn.generate_id()?,
+            indicator: conn.generate_id()?,
+        })
+    }
+
+    /// every context with its foreground and background
+    fn contexts(&self, c: &BarColors) -> [(Gcontext, u32, u32); 6] {
+        [
+            (self.active_tag, c.active_tag, c.background),
+            (self.active_tag_text, c.background, c.active_tag),
+            (self.inactive_tag, c.inactive_tag, c.background),
+            (self.inactive_tag_text, c.foreground, c.inactive_tag),
+            (self.status_text, c.status_text, c.background),
+            (self.indicator, c.indicator, c.background),
+        ]
+    }
+}
+
+/// the colors everything else falls back to, from the config or the X resources
+#[derive(Debug, Clone, Copy)]
+struct BaseColors {
+    main: Rgb,
+    secondary: Rgb,
+    /// text on the bar, the secondary color unless the X resources have one
+    foreground: Rgb,
+    urgent: Rgb,
+}
+
+/// the base colors, from the X resources if `xresources` is set.
+/// read again on every reload, so `xrdb -merge` followed by a reload restyles everything
+fn get_colors<C: Connection>(conn: &C, config: &Config) -> Result<BaseColors, ReplyError> {
+    let colors = BaseColors {
+        main: config.main_color,
+        secondary: config.secondary_color,
+        foreground: config.secondary_color,
+        urgent: config.urgent_border,
+    };
+    if !config.xresources {
+        return Ok(colors);
+    }
+    let db = resource_manager::new_from_default(conn)?;
+    let resource = |name: &str, class: &str, fallback: Rgb| {
+        let value = match db.get_string(&format!("hematite.{name}"), &format!("Hematite.{class}")) {
+            Some(v) => v,
+            None => return fallback,
+        };
+        match config::parse_hex_color(value.trim()) {
+            Some(c) => {
+                log::info!("using {name} {value} from X resources");
+                c
+            }
+            None => {
+                log::error!("X resource {name} {value:?} is not a #rrggbb color");
+                fallback
+            }
+        }
+    };
+    Ok(BaseColors {
+        main: resource("background", "Background", colors.main),
+        secondary: resource("color4", "Color4", colors.secondary),
+        foreground: resource("foreground", "Foreground", colors.foreground),
+        urgent: resource("color1", "Color1", colors.urgent),
+    })
+}
+
+/// the focused, unfocused and urgent border pixels, the first two falling back to
+/// the secondary and main color
+fn get_border_ids<C: Connection>(
+    conn: &C,
+    screen: &Screen,
+    pixels: &RefCell<HashMap<Rgb, u32>>,
+    config: &Config,
+    colors: BaseColors,
+) -> Result<(u32, u32, u32), WmError> {
+    let pixel = |color| get_color_id(conn, screen, pixels, color);
+    Ok((
+        pixel(config.focused_border.unwrap_or(colors.secondary))?,
+        pixel(config.unfocused_border.unwrap_or(colors.main))?,
+        pixel(colors.urgent)?,
+    ))
+}
+
+/// the pixel of `color`, allocating it only the first time
 fn get_color_id<C: Connection>(
     conn: &C,
     screen: &Screen,
-    color: (u16, u16, u16),
-) -> Result<u32, ReplyOrIdError> {
-    Ok(conn
+    pixels: &RefCell<HashMap<Rgb, u32>>,
+    color: Rgb,
+) -> Result<u32, WmError> {
+    if let Some(pixel) = pixels.borrow().get(&color) {
+        return Ok(*pixel);
+    }
+    let pixel = conn
         .alloc_color(screen.default_colormap, color.0, color.1, color.2)?
         .reply()?
-        .pixel)
+        .pixel;
+    pixels.borrow_mut().insert(color, pixel);
+    Ok(pixel)
 }
 
 fn set_font<C: Connection>(conn: &C, id_font: u32, config: &Config) -> Res {
@@ -756,3 +2340,67 @@ fn set_font<C: Connection>(conn: &C, id_font: u32, config: &Config) -> Res {
     };
     Ok(())
 }
+
+/// rows of a `width` by `height` rectangle with its corners rounded by `radius`
+fn rounded_rectangle(width: u16, height: u16, radius: u16) -> Vec<Rectangle> {
+    let radius = radius.min(width / 2).min(height / 2);
+    let r = radius as f32;
+    let mut rows = (0..radius)
+        .flat_map(|y| {
+            let dy = r - y as f32 - 0.5;
+            let inset = (r - (r * r - dy * dy).max(0.0).sqrt()).round() as u16;
+            let row = |y: u16| Rectangle {
+                x: inset as i16,
+                y: y as i16,
+                width: width - inset * 2,
+                height: 1,
+            };
+            [row(y), row(height - 1 - y)]
+        })
+        .collect::<Vec<_>>();
+    rows.push(Rectangle {
+        x: 0,
+        y: radius as i16,
+        width,
+        height: height - radius * 2,
+    });
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layout_command_answers() {
+        let geometries = run_layout("cat >/dev/null; echo 1 2 3 4; echo 5 6 7 8", "{}", 2);
+        assert_eq!(geometries, Ok(vec![[1, 2, 3, 4], [5, 6, 7, 8]]));
+        assert!(run_layout("echo 1 2 3", "{}", 1).is_err());
+    }
+
+    #[test]
+    fn layout_command_may_ignore_its_input() {
+        let input = "x".repeat(1 << 20);
+        assert_eq!(
+            run_layout("echo 1 2 3 4", &input, 1),
+            Ok(vec![[1, 2, 3, 4]])
+        );
+    }
+
+    #[test]
+    fn layout_command_leaving_its_stdout_open_is_killed() {
+        let start = Instant::now();
+        assert!(run_layout("sleep 5 & echo 1 2 3 4", "{}", 1).is_err());
+        assert!(start.elapsed() < Duration::from_secs(2));
+        // in the background without the pipe it doesn't hold anything up
+        let geometries = run_layout("sleep 5 >/dev/null & echo 1 2 3 4", "{}", 1);
+        assert_eq!(geometries, Ok(vec![[1, 2, 3, 4]]));
+    }
+
+    #[test]
+    fn slow_layout_command_is_killed() {
+        let start = std::time::Instant::now();
+        assert!(run_layout("sleep 5; echo 1 2 3 4", "{}", 1).is_err());
+        assert!(start.elapsed() < std::time::Duration::from_secs(2));
+    }
+}
@@ -0,0 +1,54 @@
+// sends a command to a running hematite, e.g. `rwmc switch-tag 3`,
+// or prints its events with `rwmc subscribe`
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    os::unix::net::UnixStream,
+    process::ExitCode,
+};
+
+fn main() -> ExitCode {
+    let command = std::env::args().skip(1).collect::<Vec<_>>().join(" ");
+    if command.is_empty() {
+        eprintln!("usage: rwmc <command> [argument]");
+        return ExitCode::FAILURE;
+    }
+
+    let stream = match UnixStream::connect(hematite::ipc::socket_path()) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("couldn't connect to the window manager: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    // the reply and any events after it share one buffer, so none are lost between them
+    let mut reader = BufReader::new(&stream);
+    let mut reply = String::new();
+    let result = writeln!(&stream, "{command}").and_then(|_| reader.read_line(&mut reply));
+    if let Err(e) = result {
+        eprintln!("couldn't talk to the window manager: {e}");
+        return ExitCode::FAILURE;
+    }
+
+    let reply = reply.trim_end();
+    if reply.starts_with("error") {
+        eprintln!("{reply}");
+        return ExitCode::FAILURE;
+    }
+    if command != "subscribe" {
+        println!("{reply}");
+        return ExitCode::SUCCESS;
+    }
+
+    // events keep coming until the window manager exits
+    for line in reader.lines() {
+        match line {
+            Ok(line) => println!("{line}"),
+            Err(e) => {
+                eprintln!("lost the window manager: {e}");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,246 @@
+//! `hematite --check-config`, finding the mistakes that are otherwise only logged at startup
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use x11rb::{connection::Connection, protocol::xproto::ConnectionExt};
+
+use crate::{
+    config::{self, Config, ConfigDeserialized, HotkeyConfig, Trigger},
+    keys::{self, HotkeyAction},
+};
+
+/// every problem found in the config file and the files it includes,
+/// pointing at the line it is on where possible
+pub fn check_config(path: &Path) -> Vec<String> {
+    let files = match config::read_config_files(path) {
+        Ok(f) => f,
+        Err(e) => return vec![e],
+    };
+    let mut checker = Checker {
+        sources: files
+            .iter()
+            .map(|f| (f.path.clone(), f.source.clone()))
+            .collect(),
+        problems: Vec::new(),
+    };
+    let (deserialized, problems) = ConfigDeserialized::from_files(files);
+    checker.problems.extend(problems);
+    for (value, problem) in deserialized.check_values() {
+        checker.report(&quoted(&value), problem);
+    }
+    let config = Config::from(deserialized);
+    checker.check_bindings(&config);
+    checker.check_rules(&config);
+    checker.check_font(&config.font);
+    checker.problems
+}
+
+fn quoted(value: &str) -> String {
+    format!("\"{value}\"")
+}
+
+struct Checker {
+    /// path and text of every file, in the order they are merged
+    sources: Vec<(PathBuf, String)>,
+    problems: Vec<String>,
+}
+
+impl Checker {
+    /// adds the first line containing `needle` to the problem
+    fn report(&mut self, needle: &str, problem: String) {
+        self.report_nth(needle, 0, problem);
+    }
+
+    /// adds the `nth` line containing `needle`, counting from 0, to the problem
+    fn report_nth(&mut self, needle: &str, nth: usize, problem: String) {
+        let line = self
+            .sources
+            .iter()
+            .flat_map(|(path, source)| source.lines().enumerate().map(move |l| (path, l)))
+            .filter(|(_, (_, l))| !needle.is_empty() && l.contains(needle))
+            .nth(nth);
+        self.problems.push(match line {
+            Some((path, (i, l))) if self.sources.len() > 1 => {
+                format!(
+                    "{problem}\n  {} line {}: {}",
+                    path.display(),
+                    i + 1,
+                    l.trim()
+                )
+            }
+            Some((_, (i, l))) => format!("{problem}\n  line {}: {}", i + 1, l.trim()),
+            None => problem,
+        });
+    }
+
+    fn check_bindings(&mut self, config: &Config) {
+        let names = keys::keysym_names();
+        let modes = config
+            .modes
+            .iter()
+            .map(|m| m.name.as_str())
+            .collect::<HashSet<_>>();
+        let scratchpads = config
+            .scratchpads
+            .iter()
+            .map(|s| s.name.as_str())
+            .collect::<HashSet<_>>();
+
+        let groups =
+            std::iter::once(&config.hotkeys).chain(config.modes.iter().map(|m| &m.hotkeys));
+        // hotkeys on the same key are told apart by the order they are written in
+        let mut written = HashMap::<&str, usize>::new();
+        for hotkeys in groups {
+            let mut seen = HashSet::new();
+            for hotkey in hotkeys {
+                let needle = quoted(&hotkey.key);
+                let nth = written.entry(&hotkey.key).or_default();
+                let mut problems = keys::check_keys(names, &hotkey.modifiers, Some(&hotkey.key));
+                for action in &hotkey.action {
+                    problems.extend(check_action(action, config, &modes, &scratchpads));
+                }
+                if !seen.insert(binding_keys(hotkey, config)) {
+                    problems.push(format!(
+                        "{}+{} is bound twice, only the first one runs",
+                        hotkey.modifiers, hotkey.key
+                    ));
+                }
+                for problem in problems {
+                    self.report_nth(&needle, *nth, problem);
+                }
+                *nth += 1;
+            }
+        }
+
+        for mousebind in &config.mousebinds {
+            let needle = format!("button = {}", mousebind.button);
+            for problem in keys::check_keys(names, &mousebind.modifiers, None) {
+                self.report(&needle, problem);
+            }
+            if let Some(problem) = check_action(&mousebind.action, config, &modes, &scratchpads) {
+                self.report(&needle, problem);
+            }
+        }
+    }
+
+    fn check_rules(&mut self, config: &Config) {
+        let tag_count = config.tag_count;
+        for (i, rule) in config.rules.iter().enumerate() {
+            let matchers = [&rule.class, &rule.instance, &rule.title, &rule.window_type];
+            let needle = match matchers.iter().find_map(|m| m.as_ref()) {
+                Some(m) => quoted(m),
+                None => {
+                    self.report(
+                        "",
+                        format!(
+                            "rule {} matches nothing, give it a class, instance, title or window_type",
+                            i + 1
+                        ),
+                    );
+                    continue;
+                }
+            };
+            if let Some(tag) = rule.tag.filter(|t| !(1..=tag_count).contains(t)) {
+                self.report(
+                    &needle,
+                    format!("rule tag {tag} is not between 1 and {tag_count}"),
+                );
+            }
+            if let Some(opacity) = rule.opacity.filter(|o| !(0.0..=1.0).contains(o)) {
+                self.report(
+                    &needle,
+                    format!("rule opacity {opacity} is not between 0 and 1"),
+                );
+            }
+            if let Some(color) = rule
+                .border_color
+                .as_ref()
+                .filter(|c| config::parse_hex_color(c).is_none())
+            {
+                self.report(&quoted(color), format!("{color:?} is not a #rrggbb color"));
+            }
+        }
+
+        for layout in &config.layouts {
+            for tag in layout.tags.iter().filter(|t| !(1..=tag_count).contains(*t)) {
+                self.report(
+                    &quoted(&layout.command),
+                    format!("layout tag {tag} is not between 1 and {tag_count}"),
+                );
+            }
+        }
+
+        for tag in config.tags.iter().map(|t| t.tag) {
+            if !(1..=tag_count).contains(&tag) {
+                self.report(
+                    &format!("tag = {tag}"),
+                    format!("[[tags]] tag {tag} is not between 1 and {tag_count}"),
+                );
+            }
+        }
+
+        let mut names = HashSet::new();
+        for scratchpad in &config.scratchpads {
+            if !names.insert(&scratchpad.name) {
+                self.report(
+                    &quoted(&scratchpad.name),
+                    format!("scratchpad {:?} is defined twice", scratchpad.name),
+                );
+            }
+        }
+    }
+
+    /// fonts can only be looked up on a running X server
+    fn check_font(&mut self, font: &str) {
+        let conn = match x11rb::connect(None) {
+            Ok((conn, _)) => conn,
+            Err(e) => {
+                eprintln!("not checking the font without a display: {e}");
+                return;
+            }
+        };
+        let found = conn
+            .generate_id()
+            .ok()
+            .and_then(|id| conn.open_font(id, font.as_bytes()).ok())
+            .is_some_and(|cookie| cookie.check().is_ok());
+        if !found {
+            self.report(&quoted(font), format!("font {font:?} not found"));
+        }
+    }
+}
+
+fn check_action(
+    action: &HotkeyAction,
+    config: &Config,
+    modes: &HashSet<&str>,
+    scratchpads: &HashSet<&str>,
+) -> Option<String> {
+    if let Some(tag) = action.tag().filter(|t| !(1..=config.tag_count).contains(t)) {
+        return Some(format!(
+            "tag {tag} is not between 1 and {}",
+            config.tag_count
+        ));
+    }
+    match action {
+        HotkeyAction::EnterMode(name) if !modes.contains(name.as_str()) => {
+            Some(format!("there is no mode named {name:?}"))
+        }
+        HotkeyAction::ToggleScratchpad(name) if !scratchpads.contains(name.as_str()) => {
+            Some(format!("there is no scratchpad named {name:?}"))
+        }
+        _ => None,
+    }
+}
+
+/// what makes two hotkeys collide, the key compared as written
+fn binding_keys(hotkey: &HotkeyConfig, config: &Config) -> (u16, String, bool) {
+    (
+        keys::parse_modifiers(&hotkey.modifiers, config.mod_key).bits(),
+        hotkey.key.clone(),
+        hotkey.trigger == Trigger::Press,
+    )
+}
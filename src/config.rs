@@ -1,15 +1,40 @@
-use crate::keys::HotkeyAction;
-use serde::{Deserialize, Serialize};
-use std::num::ParseIntError;
+//! the `config.toml` format, and the runtime [`Config`] built from it
+
+use crate::keys::{self, HotkeyAction, SpawnCommand};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de::DeserializeOwned};
+use std::{
+    collections::BTreeMap,
+    num::ParseIntError,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+use x11rb::protocol::xproto::KeyButMask;
 
 pub const SPACING: u32 = 10;
 pub const RATIO: f32 = 0.5;
 pub const BORDER_SIZE: u32 = 1;
+pub const FLOAT_MARGIN: u32 = 32;
 pub const MAIN_COLOR: (u16, u16, u16) = (4369, 4369, 6939); // #11111b
 pub const SECONDARY_COLOR: (u16, u16, u16) = (29812, 51143, 60652); // #74c7ec
-pub const FONT: &'static str = "fixed";
+pub const URGENT_COLOR: (u16, u16, u16) = (62451, 35723, 43176); // #f38ba8
+pub const FONT: &str = "fixed";
+/// the layout of the config file, written as `version` at its top
+pub const CONFIG_VERSION: u32 = 1;
+pub const TAG_COUNT: usize = 9;
+/// as many tags as fit on a bar of a small screen
+pub const MAX_TAG_COUNT: usize = 32;
+
+pub const BASE_DPI: f32 = 96.0;
+
+/// like `hex_color_to_rgb`, but `None` for anything that isn't `#rrggbb`
+pub fn parse_hex_color(hex: &str) -> Option<(u16, u16, u16)> {
+    if hex.len() != 7 || !hex.is_ascii() || !hex.starts_with('#') {
+        return None;
+    }
+    hex_color_to_rgb(hex).ok()
+}
 
-fn hex_color_to_rgb(hex: &str) -> Result<(u16, u16, u16), ParseIntError> {
+pub fn hex_color_to_rgb(hex: &str) -> Result<(u16, u16, u16), ParseIntError> {
     Ok((
         u16::from_str_radix(&hex[1..3], 16)? * 257,
         u16::from_str_radix(&hex[3..5], 16)? * 257,
@@ -19,41 +44,225 @@ fn hex_color_to_rgb(hex: &str) -> Result<(u16, u16, u16), ParseIntError> {
 
 #[derive(Clone)]
 pub struct Config {
-    pub spacing: u32,
+    pub spacing: Size,
     pub ratio: f32,
-    pub border_size: u32,
+    /// the ratios `CycleRatio` steps through, in increasing order
+    pub ratio_presets: Vec<f32>,
+    pub border_size: Size,
+    /// rounds the corners of window frames through the shape extension, square at 0
+    pub corner_radius: Size,
+    /// how much of a floating window is kept on its monitor
+    pub float_margin: Size,
+    pub dpi: Option<f32>,
     pub main_color: (u16, u16, u16),
     pub secondary_color: (u16, u16, u16),
+    /// take the colors from `background`, `color4`, `foreground` (bar text) and `color1`
+    /// (urgent border) in the X resources when they are set
+    pub xresources: bool,
+    /// border of the focused window, the secondary color if not set
+    pub focused_border: Option<(u16, u16, u16)>,
+    /// border of other windows, the main color if not set
+    pub unfocused_border: Option<(u16, u16, u16)>,
+    /// border of windows asking for attention
+    pub urgent_border: (u16, u16, u16),
+    pub palette: Palette,
     pub font: String,
     pub hotkeys: Vec<HotkeyConfig>,
+    pub mod_key: KeyButMask,
+    pub mousebinds: Vec<MousebindConfig>,
+    pub modes: Vec<ModeConfig>,
+    pub monitors: Vec<MonitorConfig>,
+    pub workspaces: WorkspaceModel,
+    /// tags on every monitor, counting from 1 in hotkeys, rules and commands
+    pub tag_count: usize,
+    pub warp_on_monitor_change: bool,
+    pub warp_on_focus_change: bool,
+    pub focus_follow_mouse: bool,
+    pub focus_new: FocusNew,
+    pub float_placement: FloatPlacement,
+    /// opacity of unfocused windows, for compositors to dim them
+    pub inactive_opacity: Option<f32>,
+    /// a titlebar with the title and a close area on top of every window
+    pub titlebars: bool,
+    pub rules: Vec<RuleConfig>,
+    pub hooks: Hooks,
+    pub layouts: Vec<LayoutConfig>,
+    /// spacing and border size of single tags
+    pub tags: Vec<TagConfig>,
+    pub scratchpads: Vec<ScratchpadConfig>,
+    /// programs by name, filled in for `$name` in spawned commands
+    pub apps: BTreeMap<String, String>,
 }
 
 impl From<ConfigDeserialized> for Config {
     fn from(config: ConfigDeserialized) -> Self {
-        let main_color = match hex_color_to_rgb(&config.colors.main_color) {
-            Ok(c) => c,
-            Err(_) => {
-                log::debug!("BAD COLOR VALUE");
-                MAIN_COLOR
+        let colors = match config.colors.theme.as_deref() {
+            Some(name) => match theme(name) {
+                Some(theme) => config.colors.over(theme),
+                None => {
+                    log::error!("unknown theme {name:?}");
+                    config.colors
+                }
+            },
+            None => config.colors,
+        };
+
+        let optional_color = |color: &Option<String>| {
+            let color = color.as_ref()?;
+            let parsed = parse_hex_color(color);
+            if parsed.is_none() {
+                log::error!("bad color {color:?}");
             }
+            parsed
         };
-        let secondary_color = match hex_color_to_rgb(&config.colors.secondary_color) {
-            Ok(c) => c,
-            Err(_) => {
-                log::debug!("BAD COLOR VALUE");
-                SECONDARY_COLOR
+        let main_color = optional_color(&colors.main_color).unwrap_or(MAIN_COLOR);
+        let secondary_color = optional_color(&colors.secondary_color).unwrap_or(SECONDARY_COLOR);
+        let focused_border = optional_color(&colors.focused_border);
+        let unfocused_border = optional_color(&colors.unfocused_border);
+        let urgent_border = optional_color(&colors.urgent_border).unwrap_or(URGENT_COLOR);
+        let palette = Palette {
+            bar_background: optional_color(&colors.bar_background),
+            bar_foreground: optional_color(&colors.bar_foreground),
+            active_tag: optional_color(&colors.active_tag),
+            inactive_tag: optional_color(&colors.inactive_tag),
+            status_text: optional_color(&colors.status_text),
+            indicator: optional_color(&colors.indicator),
+        };
+
+        let mod_key = match keys::parse_modifier(&config.behavior.mod_key) {
+            Some(m) => m,
+            None => {
+                log::error!("BAD MOD KEY {}", config.behavior.mod_key);
+                KeyButMask::MOD4
             }
         };
 
         Self {
             main_color,
+            mod_key,
             secondary_color,
-            spacing: config.sizing.spacing.clamp(0, 1000),
+            xresources: colors.xresources,
+            focused_border,
+            unfocused_border,
+            urgent_border,
+            palette,
+            spacing: config.sizing.spacing.clamped(),
             ratio: config.sizing.ratio.clamp(0.0, 1.0),
-            border_size: config.sizing.border_size.clamp(0, 1000),
+            ratio_presets: {
+                let mut presets = config
+                    .sizing
+                    .ratio_presets
+                    .iter()
+                    .filter(|r| r.is_finite())
+                    .map(|r| r.clamp(0.15, 0.85))
+                    .collect::<Vec<_>>();
+                presets.sort_by(f32::total_cmp);
+                presets.dedup();
+                presets
+            },
+            border_size: config.sizing.border_size.clamped(),
+            corner_radius: config.sizing.corner_radius.clamped(),
+            float_margin: config.sizing.float_margin.clamped(),
+            dpi: config.sizing.dpi.map(|d| d.clamp(24.0, 960.0)),
             font: config.font.font,
             hotkeys: config.hotkeys,
+            mousebinds: config.mousebinds,
+            modes: config.modes,
+            workspaces: config.behavior.workspaces,
+            tag_count: config.behavior.tags.clamp(1, MAX_TAG_COUNT),
+            warp_on_monitor_change: config.behavior.warp_on_monitor_change,
+            warp_on_focus_change: config.behavior.warp_on_focus_change,
+            focus_follow_mouse: config.behavior.focus_follow_mouse,
+            focus_new: config.behavior.focus_new,
+            float_placement: config.behavior.float_placement,
+            inactive_opacity: config.behavior.inactive_opacity.map(|o| o.clamp(0.0, 1.0)),
+            titlebars: config.behavior.titlebars,
+            rules: config.rules,
+            // apps left out keep their default
+            apps: default_apps().into_iter().chain(config.apps).collect(),
+            hooks: config.hooks,
+            layouts: config.layouts,
+            tags: config
+                .tags
+                .into_iter()
+                .map(|t| TagConfig {
+                    spacing: t.spacing.map(Size::clamped),
+                    border_size: t.border_size.map(Size::clamped),
+                    ..t
+                })
+                .collect(),
+            scratchpads: config
+                .scratchpads
+                .into_iter()
+                .map(|s| ScratchpadConfig {
+                    width: s.width.clamp(0.1, 1.0),
+                    height: s.height.clamp(0.1, 1.0),
+                    ..s
+                })
+                .collect(),
+            monitors: config
+                .monitors
+                .into_iter()
+                .map(|m| MonitorConfig {
+                    spacing: m.spacing.map(Size::clamped),
+                    ratio: m.ratio.map(|r| r.clamp(0.0, 1.0)),
+                    ..m
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Config {
+    /// returns a copy of the config with every pixel size multiplied by `scale`
+    pub fn scaled(&self, scale: f32) -> Self {
+        let scale_size = |s: u32| (s as f32 * scale).round() as u32;
+        Self {
+            spacing: self.spacing.scaled(scale),
+            border_size: self.border_size.scaled(scale),
+            corner_radius: self.corner_radius.scaled(scale),
+            float_margin: self.float_margin.scaled(scale),
+            font: scale_font_name(&self.font, scale),
+            monitors: self
+                .monitors
+                .iter()
+                .map(|m| MonitorConfig {
+                    spacing: m.spacing.map(|s| s.scaled(scale)),
+                    padding: m.padding.map(|p| PaddingConfig {
+                        left: scale_size(p.left as u32) as u16,
+                        right: scale_size(p.right as u32) as u16,
+                        top: scale_size(p.top as u32) as u16,
+                        bottom: scale_size(p.bottom as u32) as u16,
+                    }),
+                    ..m.clone()
+                })
+                .collect(),
+            tags: self
+                .tags
+                .iter()
+                .map(|t| TagConfig {
+                    spacing: t.spacing.map(|s| s.scaled(scale)),
+                    border_size: t.border_size.map(|s| s.scaled(scale)),
+                    ..*t
+                })
+                .collect(),
+            ..self.clone()
+        }
+    }
+}
+
+/// scales the pixel size field of an XLFD font name, leaving aliases like "fixed" untouched
+fn scale_font_name(font: &str, scale: f32) -> String {
+    let mut fields = font.split('-').map(str::to_owned).collect::<Vec<_>>();
+    if fields.len() != 15 {
+        return font.to_owned();
+    }
+    match fields[7].parse::<u32>() {
+        Ok(size) if size > 0 => {
+            fields[7] = ((size as f32 * scale).round() as u32).to_string();
+            fields.join("-")
         }
+        _ => font.to_owned(),
     }
 }
 
@@ -62,218 +271,1698 @@ pub struct ConfigDeserialized {
     sizing: Sizing,
     colors: Colors,
     font: Font,
+    #[serde(default)]
+    behavior: Behavior,
+    #[serde(default = "default_apps")]
+    apps: BTreeMap<String, String>,
     hotkeys: Vec<HotkeyConfig>,
+    #[serde(default = "default_mousebinds")]
+    mousebinds: Vec<MousebindConfig>,
+    #[serde(default)]
+    modes: Vec<ModeConfig>,
+    #[serde(default)]
+    monitors: Vec<MonitorConfig>,
+    #[serde(default)]
+    rules: Vec<RuleConfig>,
+    #[serde(default)]
+    hooks: Hooks,
+    #[serde(default)]
+    layouts: Vec<LayoutConfig>,
+    #[serde(default)]
+    tags: Vec<TagConfig>,
+    #[serde(default)]
+    scratchpads: Vec<ScratchpadConfig>,
 }
 
+/// a partial `[sizing]` takes the rest from the defaults, like every other section
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
 struct Sizing {
-    spacing: u32,
+    spacing: Size,
     ratio: f32,
-    border_size: u32,
+    ratio_presets: Vec<f32>,
+    border_size: Size,
+    corner_radius: Size,
+    float_margin: Size,
+    dpi: Option<f32>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl Default for Sizing {
+    fn default() -> Self {
+        Sizing {
+            spacing: Size::Pixels(SPACING),
+            ratio: RATIO,
+            ratio_presets: vec![0.5, 0.62, 0.7],
+            border_size: Size::Pixels(BORDER_SIZE),
+            corner_radius: Size::Pixels(0),
+            float_margin: Size::Pixels(FLOAT_MARGIN),
+            dpi: None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
 struct Colors {
-    main_color: String,
-    secondary_color: String,
+    /// one of [`THEMES`], giving the colors that aren't set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    theme: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    main_color: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    secondary_color: Option<String>,
+    #[serde(default)]
+    xresources: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    focused_border: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    unfocused_border: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    urgent_border: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bar_background: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bar_foreground: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    active_tag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    inactive_tag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    status_text: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    indicator: Option<String>,
+}
+
+impl Colors {
+    /// these colors, with the ones not set taken from `base`
+    fn over(self, base: Colors) -> Colors {
+        Colors {
+            theme: self.theme,
+            main_color: self.main_color.or(base.main_color),
+            secondary_color: self.secondary_color.or(base.secondary_color),
+            xresources: self.xresources || base.xresources,
+            focused_border: self.focused_border.or(base.focused_border),
+            unfocused_border: self.unfocused_border.or(base.unfocused_border),
+            urgent_border: self.urgent_border.or(base.urgent_border),
+            bar_background: self.bar_background.or(base.bar_background),
+            bar_foreground: self.bar_foreground.or(base.bar_foreground),
+            active_tag: self.active_tag.or(base.active_tag),
+            inactive_tag: self.inactive_tag.or(base.inactive_tag),
+            status_text: self.status_text.or(base.status_text),
+            indicator: self.indicator.or(base.indicator),
+        }
+    }
+}
+
+/// the names of the bundled color themes
+pub const THEMES: [&str; 3] = ["catppuccin", "gruvbox", "nord"];
+
+fn theme(name: &str) -> Option<Colors> {
+    // main, secondary, text, unfocused border, urgent, indicator, status text
+    let [main, secondary, text, unfocused, urgent, indicator, status] = match name {
+        "catppuccin" => [
+            "#1e1e2e", "#89b4fa", "#cdd6f4", "#313244", "#f38ba8", "#f9e2af", "#a6adc8",
+        ],
+        "gruvbox" => [
+            "#282828", "#d79921", "#ebdbb2", "#504945", "#cc241d", "#689d6a", "#a89984",
+        ],
+        "nord" => [
+            "#2e3440", "#88c0d0", "#d8dee9", "#4c566a", "#bf616a", "#ebcb8b", "#e5e9f0",
+        ],
+        _ => return None,
+    }
+    .map(|c| Some(c.to_owned()));
+    Some(Colors {
+        main_color: main,
+        secondary_color: secondary,
+        bar_foreground: text,
+        unfocused_border: unfocused,
+        urgent_border: urgent,
+        indicator,
+        status_text: status,
+        ..Colors::default()
+    })
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
 struct Font {
     font: String,
 }
 
+impl Default for Font {
+    fn default() -> Self {
+        Font {
+            font: FONT.to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Behavior {
+    #[serde(default)]
+    workspaces: WorkspaceModel,
+    #[serde(default = "default_tags")]
+    tags: usize,
+    #[serde(default)]
+    warp_on_monitor_change: bool,
+    #[serde(default)]
+    warp_on_focus_change: bool,
+    #[serde(default = "default_true")]
+    focus_follow_mouse: bool,
+    #[serde(default)]
+    focus_new: FocusNew,
+    #[serde(default)]
+    float_placement: FloatPlacement,
+    #[serde(default = "default_mod_key")]
+    mod_key: String,
+    #[serde(default)]
+    inactive_opacity: Option<f32>,
+    #[serde(default)]
+    titlebars: bool,
+}
+
+impl Default for Behavior {
+    fn default() -> Self {
+        Self {
+            workspaces: WorkspaceModel::default(),
+            tags: TAG_COUNT,
+            warp_on_monitor_change: false,
+            warp_on_focus_change: false,
+            focus_follow_mouse: true,
+            focus_new: FocusNew::default(),
+            float_placement: FloatPlacement::default(),
+            mod_key: default_mod_key(),
+            inactive_opacity: None,
+            titlebars: false,
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_apps() -> BTreeMap<String, String> {
+    BTreeMap::from([
+        ("terminal".to_owned(), "alacritty".to_owned()),
+        ("browser".to_owned(), "librewolf".to_owned()),
+    ])
+}
+
+fn default_tags() -> usize {
+    TAG_COUNT
+}
+
+/// the digit keys of the tags, `0` being the tenth
+fn tag_key(tag: usize) -> String {
+    (tag % 10).to_string()
+}
+
+fn default_mod_key() -> String {
+    "MOD4".to_string()
+}
+
+/// `global` shares one pool of tags between all monitors (xmonad),
+/// `per_monitor` gives every monitor its own set of tags (dwm)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WorkspaceModel {
+    #[default]
+    Global,
+    PerMonitor,
+}
+
+/// whether a newly mapped window takes the focus of its tag, a tag without focus always
+/// focusing it. `only_on_active_tag` leaves windows sent elsewhere by a rule unfocused
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FocusNew {
+    #[default]
+    Always,
+    Never,
+    OnlyOnActiveTag,
+}
+
+/// where a new floating window appears: centered on its monitor, centered under the pointer,
+/// or down and to the right of the last one placed
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FloatPlacement {
+    #[default]
+    Center,
+    Pointer,
+    Cascade,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HotkeyConfig {
+    #[serde(default)]
     pub modifiers: String,
     pub key: String,
+    #[serde(default)]
+    pub trigger: Trigger,
+    /// either a single action or a list run in order
+    #[serde(deserialize_with = "one_or_many")]
+    pub action: Vec<HotkeyAction>,
+}
+
+/// a size written as pixels (`10`) or as a percentage of the monitor width (`"0.5%"`)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Size {
+    Pixels(u32),
+    Percent(f32),
+}
+
+impl Size {
+    pub fn pixels(self, width: u16) -> u32 {
+        match self {
+            Size::Pixels(p) => p,
+            Size::Percent(p) => (width as f32 * p / 100.0).round() as u32,
+        }
+    }
+
+    /// percentages already follow the screen, only pixels are scaled by dpi
+    fn scaled(self, scale: f32) -> Self {
+        match self {
+            Size::Pixels(p) => Size::Pixels((p as f32 * scale).round() as u32),
+            percent => percent,
+        }
+    }
+
+    fn clamped(self) -> Self {
+        match self {
+            Size::Pixels(p) => Size::Pixels(p.clamp(0, 1000)),
+            Size::Percent(p) => Size::Percent(p.clamp(0.0, 25.0)),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Size {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Written {
+            Pixels(u32),
+            Text(String),
+        }
+        match Written::deserialize(deserializer)? {
+            Written::Pixels(p) => Ok(Size::Pixels(p)),
+            Written::Text(text) => text
+                .strip_suffix('%')
+                .and_then(|p| p.trim().parse().ok())
+                .map(Size::Percent)
+                .ok_or_else(|| {
+                    serde::de::Error::custom(format!(
+                        "{text:?} is neither pixels nor a percentage like \"0.5%\""
+                    ))
+                }),
+        }
+    }
+}
+
+impl Serialize for Size {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Size::Pixels(p) => serializer.serialize_u32(*p),
+            Size::Percent(p) => serializer.serialize_str(&format!("{p}%")),
+        }
+    }
+}
+
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<HotkeyAction>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(HotkeyAction),
+        Many(Vec<HotkeyAction>),
+    }
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(action) => vec![action],
+        OneOrMany::Many(actions) => actions,
+    })
+}
+
+/// a named set of hotkeys that replaces the normal ones while it is active
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModeConfig {
+    pub name: String,
+    pub hotkeys: Vec<HotkeyConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MousebindConfig {
+    #[serde(default)]
+    pub modifiers: String,
+    pub button: u8,
+    #[serde(default)]
+    pub context: MouseContext,
     pub action: HotkeyAction,
 }
 
+/// where a mouse binding applies: on client windows, the empty root window or a bar
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MouseContext {
+    #[default]
+    Client,
+    Root,
+    Bar,
+}
+
+fn default_mousebinds() -> Vec<MousebindConfig> {
+    vec![
+        MousebindConfig {
+            modifiers: "".to_string(),
+            button: 1,
+            context: MouseContext::Client,
+            action: HotkeyAction::FocusClicked,
+        },
+        MousebindConfig {
+            modifiers: "MOD".to_string(),
+            button: 1,
+            context: MouseContext::Client,
+            action: HotkeyAction::DragMove,
+        },
+        MousebindConfig {
+            modifiers: "MOD".to_string(),
+            button: 3,
+            context: MouseContext::Client,
+            action: HotkeyAction::DragResize,
+        },
+    ]
+}
+
+/// whether a hotkey runs when its key is pressed or released
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Trigger {
+    #[default]
+    Press,
+    Release,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MonitorConfig {
+    pub index: Option<usize>,
+    pub name: Option<String>,
+    pub spacing: Option<Size>,
+    pub ratio: Option<f32>,
+    pub show_bar: Option<bool>,
+    pub padding: Option<PaddingConfig>,
+}
+
+/// settings of a single tag, replacing those of the monitor it is shown on
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct TagConfig {
+    /// counting from 1, the same tag on every monitor with per-monitor tags
+    pub tag: usize,
+    pub spacing: Option<Size>,
+    pub border_size: Option<Size>,
+    /// no tiling, every window floats where it asks to be
+    #[serde(default)]
+    pub floating: bool,
+}
+
+/// colors of the parts of the bar, the main color standing in for `bar_background` and
+/// `inactive_tag` and the secondary color for the rest when not set
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Palette {
+    pub bar_background: Option<(u16, u16, u16)>,
+    /// window title and tag numbers
+    pub bar_foreground: Option<(u16, u16, u16)>,
+    pub active_tag: Option<(u16, u16, u16)>,
+    pub inactive_tag: Option<(u16, u16, u16)>,
+    /// the root window name, as set by status scripts
+    pub status_text: Option<(u16, u16, u16)>,
+    /// the mark on tags that have windows
+    pub indicator: Option<(u16, u16, u16)>,
+}
+
+/// margin kept free on each edge of a monitor, in pixels
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct PaddingConfig {
+    #[serde(default)]
+    pub left: u16,
+    #[serde(default)]
+    pub right: u16,
+    #[serde(default)]
+    pub top: u16,
+    #[serde(default)]
+    pub bottom: u16,
+}
+
+/// what a rule matches on, all given parts have to match, and what it does to the window
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuleConfig {
+    /// either part of WM_CLASS
+    pub class: Option<String>,
+    /// the first part of WM_CLASS only
+    pub instance: Option<String>,
+    /// part of the title
+    pub title: Option<String>,
+    /// `_NET_WM_WINDOW_TYPE` without its prefix, e.g. `dialog`
+    pub window_type: Option<String>,
+    /// counting from 1
+    pub tag: Option<usize>,
+    pub monitor: Option<MonitorRef>,
+    pub floating: Option<bool>,
+    pub fullscreen: Option<bool>,
+    /// `false` maps the window as it is, without a frame, tiling or focus
+    pub manage: Option<bool>,
+    #[serde(default)]
+    pub no_border: bool,
+    pub border_width: Option<u32>,
+    /// the unfocused border color, e.g. `"#ff0000"`
+    pub border_color: Option<String>,
+    /// from 0.0 to 1.0, set as `_NET_WM_WINDOW_OPACITY` for compositors
+    pub opacity: Option<f32>,
+    /// windows started from this one take its place until they close
+    #[serde(default)]
+    pub swallow: bool,
+}
+
+/// the properties rules are matched against
+#[derive(Debug, Clone, Default)]
+pub struct WindowProperties {
+    pub instance: String,
+    pub class: String,
+    pub title: String,
+    pub window_type: String,
+}
+
+/// the combined effect of every rule matching a window, later rules winning
+#[derive(Debug, Clone, Default)]
+pub struct RuleEffects {
+    pub tag: Option<usize>,
+    pub monitor: Option<MonitorRef>,
+    pub floating: Option<bool>,
+    pub fullscreen: Option<bool>,
+    pub manage: Option<bool>,
+    pub border_width: Option<u32>,
+    pub border_color: Option<String>,
+    pub opacity: Option<f32>,
+    pub swallow: bool,
+}
+
+/// shell commands run on window manager events,
+/// with `WINDOW_ID`, `WM_CLASS` and `TAG` set where they apply
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Hooks {
+    pub startup: Option<String>,
+    pub window_mapped: Option<String>,
+    pub window_unmapped: Option<String>,
+    pub focus_changed: Option<String>,
+    pub tag_changed: Option<String>,
+}
+
+/// tiles the given tags (all of them if left empty) with an external program
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LayoutConfig {
+    #[serde(deserialize_with = "deserialize_expanded")]
+    pub command: String,
+    #[serde(default)]
+    pub tags: Vec<usize>,
+}
+
+/// a window summoned over any tag by `ToggleScratchpad`, started by `command` on first use
+/// and recognized by either part of its WM_CLASS
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScratchpadConfig {
+    pub name: String,
+    pub command: SpawnCommand,
+    pub class: String,
+    /// part of the workarea it covers
+    #[serde(default = "default_scratchpad_size")]
+    pub width: f32,
+    #[serde(default = "default_scratchpad_size")]
+    pub height: f32,
+}
+
+fn default_scratchpad_size() -> f32 {
+    0.6
+}
+
+/// a monitor given either by index or by output name, e.g. `1` or `"DP-1"`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MonitorRef {
+    Index(usize),
+    Name(String),
+}
+
+impl MonitorRef {
+    pub fn matches(&self, index: usize, name: &str) -> bool {
+        match self {
+            MonitorRef::Index(i) => *i == index,
+            MonitorRef::Name(n) => n == name,
+        }
+    }
+}
+
+impl MonitorConfig {
+    /// a name takes precedence over an index
+    pub fn matches(&self, index: usize, name: &str) -> bool {
+        match (&self.name, self.index) {
+            (Some(n), _) => n == name,
+            (None, Some(i)) => i == index,
+            (None, None) => false,
+        }
+    }
+}
+
+impl RuleConfig {
+    /// a rule without anything to match on matches nothing
+    pub fn matches(&self, window: &WindowProperties) -> bool {
+        let checks = [
+            self.class
+                .as_ref()
+                .map(|c| *c == window.instance || *c == window.class),
+            self.instance.as_ref().map(|i| *i == window.instance),
+            self.title
+                .as_ref()
+                .map(|t| window.title.contains(t.as_str())),
+            self.window_type
+                .as_ref()
+                .map(|t| t.eq_ignore_ascii_case(&window.window_type)),
+        ];
+        checks.iter().any(Option::is_some) && checks.iter().flatten().all(|c| *c)
+    }
+}
+
+impl Config {
+    /// the settings of the tag at index `tag`
+    pub fn get_tag_config(&self, tag: usize) -> Option<&TagConfig> {
+        self.tags.iter().find(|t| t.tag == tag % self.tag_count + 1)
+    }
+
+    pub fn get_rule_effects(&self, window: &WindowProperties) -> RuleEffects {
+        self.rules.iter().filter(|r| r.matches(window)).fold(
+            RuleEffects::default(),
+            |effects, r| RuleEffects {
+                tag: r.tag.or(effects.tag),
+                monitor: r.monitor.clone().or(effects.monitor),
+                floating: r.floating.or(effects.floating),
+                fullscreen: r.fullscreen.or(effects.fullscreen),
+                manage: r.manage.or(effects.manage),
+                border_width: match r.no_border {
+                    true => Some(0),
+                    false => r.border_width.or(effects.border_width),
+                },
+                border_color: r.border_color.clone().or(effects.border_color),
+                opacity: r.opacity.or(effects.opacity),
+                swallow: r.swallow || effects.swallow,
+            },
+        )
+    }
+
+    /// the external layout command for `tag`, counting from 0, the same tag on every monitor
+    /// with per-monitor tags
+    pub fn get_layout_command(&self, tag: usize) -> Option<&str> {
+        let tag = tag % self.tag_count.max(1) + 1;
+        self.layouts
+            .iter()
+            .find(|l| l.tags.is_empty() || l.tags.contains(&tag))
+            .map(|l| l.command.as_str())
+    }
+
+    /// what tiles `tag`, counting from 0: its layout command, `floating` or `master-stack`
+    pub fn get_layout_name(&self, tag: usize) -> &str {
+        let floating = self
+            .tags
+            .iter()
+            .any(|t| t.floating && t.tag == tag % self.tag_count.max(1) + 1);
+        match self.get_layout_command(tag) {
+            Some(command) => command,
+            None if floating => "floating",
+            None => "master-stack",
+        }
+    }
+}
+
+/// the optional sections, commented out, at the end of the default config
+const DEFAULT_EXAMPLES: &str = r##"
+# everything below is optional, remove the leading # to use it
+
+# settings for a single monitor, by `index` (from 0) or output `name`
+# [[monitors]]
+# name = "HDMI-1"
+# spacing = 20
+# ratio = 0.6
+# show_bar = false
+# padding = { top = 40 }
+
+# rules change windows as they are opened, see the readme for everything they can do
+# [[rules]]
+# class = "mpv"
+# floating = true
+#
+# [[rules]]
+# class = "Alacritty"
+# swallow = true
+# opacity = 0.9
+
+# a window summoned over any tag with `action = { ToggleScratchpad = "term" }`
+# [[scratchpads]]
+# name = "term"
+# command = "alacritty --class scratch"
+# class = "scratch"
+
+# shell commands run when something happens
+# [hooks]
+# startup = "dunst & feh --bg-fill ~/bg.png"
+# tag_changed = "echo $TAG on $MONITOR"
+
+# spacing and border size of a single tag, e.g. for watching videos
+# [[tags]]
+# tag = 9
+# spacing = 0
+# border_size = 0
+#
+# [[tags]]
+# tag = 8
+# floating = true
+
+# tiling some tags with a program of your own
+# [[layouts]]
+# command = "~/.config/hematite/columns.py"
+# tags = [3, 4]
+
+# hotkeys that replace the normal ones while `action = { EnterMode = "resize" }` is active
+# [[modes]]
+# name = "resize"
+#
+# [[modes.hotkeys]]
+# key = "h"
+# action = { ChangeRatio = -0.05 }
+"##;
+
 impl ConfigDeserialized {
-    pub fn new() -> Self {
-        let path =
-            match xdg::BaseDirectories::with_prefix("hematite").place_config_file("config.toml") {
-                Ok(p) => p,
-                Err(e) => {
-                    log::error!("cant create config file with error {e:?}, using default");
-                    return Self::default();
-                }
+    /// the default config as a commented toml document, with the optional sections as examples
+    pub fn default_document() -> String {
+        let config = Self::default();
+        let mut document = format!(
+            "# hematite config, `hematite --check-config` checks it for mistakes\n\
+                          # `include = [\"keys.toml\"]` up here merges in other files,\n\
+                          # `strict = true` leaves out whatever has a misspelled key\n\
+                          version = {CONFIG_VERSION}\n\n",
+        );
+        document += &toml_table(
+            "sizing",
+            "sizes in pixels at 96 dpi, scaled to the screen. `dpi = 120` overrides the detected dpi.\n\
+             # `spacing` and `border_size` can also be a percentage of the width, like \"0.5%\".\n\
+             # `action = { CycleRatio = 1 }` steps through `ratio_presets`, `corner_radius` rounds windows\n\
+             # `float_margin` is how much of a floating window stays on screen",
+            &config.sizing,
+        );
+        document += &toml_table(
+            "colors",
+            "#rrggbb colors of the bar and borders. with `xresources = true` the `background`,\n\
+             # `color4`, `foreground` and `color1` X resources (as set by xrdb or pywal) replace the\n\
+             # main and secondary color, the bar text and the urgent border when set.\n\
+             # borders are colored by `focused_border` (secondary by default), `unfocused_border`\n\
+             # (main by default) and `urgent_border` (#f38ba8 by default) if given. the bar can be\n\
+             # themed further with `bar_background`, `bar_foreground`, `active_tag`,\n\
+             # `inactive_tag`, `status_text` and `indicator`.\n\
+             # `theme = \"catppuccin\"`, \"gruvbox\" or \"nord\" fills in every color not set here",
+            &config.colors,
+        );
+        document += &toml_table("font", "an X font name, see xlsfonts", &config.font);
+        document += &toml_table(
+            "behavior",
+            "`workspaces` is \"global\" or \"per_monitor\", `tags` is how many each monitor has (up to 32).\n\
+             # `MOD` in hotkeys stands for `mod_key`. `focus_new` is \"always\", \"never\" or\n\
+             # \"only_on_active_tag\", whether new windows take the focus.\n\
+             # `float_placement` is \"center\", \"pointer\" or \"cascade\", where new floating windows go.\n\
+             # `inactive_opacity = 0.8` dims unfocused windows under a compositor",
+            &config.behavior,
+        );
+        document += &toml_table(
+            "apps",
+            "programs filled in for `$terminal`, `$browser` or any other name in spawned commands",
+            &config.apps,
+        );
+
+        document += "# modifiers are CONTROL, SHIFT, MOD1, MOD3 to MOD5 and MOD, joined with | (MOD2 is NumLock)\n";
+        for hotkey in &config.hotkeys {
+            document += "[[hotkeys]]\n";
+            if !hotkey.modifiers.is_empty() {
+                document += &format!("modifiers = {:?}\n", hotkey.modifiers);
+            }
+            document += &format!("key = {:?}\n", hotkey.key);
+            if hotkey.trigger == Trigger::Release {
+                document += "trigger = \"release\"\n";
+            }
+            let action = match hotkey.action.as_slice() {
+                [action] => inline_toml(action),
+                actions => inline_toml(&actions),
             };
+            document += &format!("action = {action}\n\n");
+        }
 
-        log::info!("loading config from {path:?}");
+        document += "# context is \"client\", \"root\" or \"bar\"\n";
+        for mousebind in &config.mousebinds {
+            document += "[[mousebinds]]\n";
+            if !mousebind.modifiers.is_empty() {
+                document += &format!("modifiers = {:?}\n", mousebind.modifiers);
+            }
+            document += &format!(
+                "button = {}\ncontext = {}\naction = {}\n\n",
+                mousebind.button,
+                inline_toml(&mousebind.context),
+                inline_toml(&mousebind.action)
+            );
+        }
+        document + DEFAULT_EXAMPLES
+    }
+}
 
-        let config_str = match std::fs::read_to_string(&path) {
-            Ok(s) => s,
-            Err(e) => {
-                log::info!("config not found {e:?}, serializing default");
+fn toml_table<T: Serialize>(name: &str, comment: &str, value: &T) -> String {
+    match toml::to_string(value) {
+        Ok(v) => format!("# {comment}\n[{name}]\n{v}\n"),
+        Err(e) => {
+            log::error!("couldn't write [{name}] {e}");
+            String::new()
+        }
+    }
+}
 
-                let serialized = match toml::to_string(&Self::default()) {
-                    Ok(s) => s,
-                    Err(_) => {
-                        log::error!("couldn't serialize config into file, using default");
-                        return Self::default();
-                    }
-                };
+/// a value written on one line, e.g. `{ Spawn = "alacritty" }`
+fn inline_toml<T: Serialize + ?Sized>(value: &T) -> String {
+    match toml::Value::try_from(value) {
+        Ok(v) => v.to_string(),
+        Err(e) => {
+            log::error!("couldn't write {e}");
+            String::new()
+        }
+    }
+}
+
+static CONFIG_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// makes [`config_path`] return `path`, for `--config`. only the first call counts
+pub fn set_config_path(path: PathBuf) {
+    if CONFIG_PATH.set(path).is_err() {
+        log::warn!("config path already set");
+    }
+}
+
+/// the path given to [`set_config_path`], otherwise
+/// `$XDG_CONFIG_HOME/hematite/config.toml` if its directory exists or can be created
+pub fn config_path() -> Option<PathBuf> {
+    if let Some(path) = CONFIG_PATH.get() {
+        return Some(path.clone());
+    }
+    match xdg::BaseDirectories::with_prefix("hematite").place_config_file("config.toml") {
+        Ok(p) => Some(p),
+        Err(e) => {
+            log::error!("cant create config file with error {e:?}");
+            None
+        }
+    }
+}
+
+/// `s` with a leading `~/` and every `$NAME` or `${NAME}` replaced by the environment,
+/// leaving variables that aren't set as written
+pub fn expand_env(s: &str) -> String {
+    match (s.strip_prefix("~/"), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) => format!(
+            "{}/{}",
+            home.to_string_lossy(),
+            expand_with(rest, |name| std::env::var(name).ok())
+        ),
+        _ => expand_with(s, |name| std::env::var(name).ok()),
+    }
+}
+
+/// `s` with every `$NAME` or `${NAME}` that `lookup` knows replaced
+pub fn expand_with(s: &str, lookup: impl Fn(&str) -> Option<String>) -> String {
+    let mut expanded = String::new();
+    let mut rest = s;
+    while let Some(start) = rest.find('$') {
+        expanded += &rest[..start];
+        let after = &rest[start + 1..];
+        let (name, len) = match after.strip_prefix('{').and_then(|a| a.split_once('}')) {
+            Some((name, _)) => (name, name.len() + 2),
+            None => {
+                let len = after
+                    .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                    .unwrap_or(after.len());
+                (&after[..len], len)
+            }
+        };
+        match lookup(name) {
+            Some(value) if !name.is_empty() => expanded += &value,
+            _ => expanded += &rest[start..start + 1 + len],
+        }
+        rest = &after[len..];
+    }
+    expanded + rest
+}
+
+fn deserialize_expanded<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    Ok(expand_env(&String::deserialize(deserializer)?))
+}
+
+/// a config file as read, for pointing at its lines
+pub struct ConfigFile {
+    pub path: PathBuf,
+    pub source: String,
+    table: toml::Table,
+}
+
+/// reads `path` and every file its `include` lists, included files first and each file once.
+/// include paths are relative to the file including them, with environment variables expanded
+pub fn read_config_files(path: &Path) -> Result<Vec<ConfigFile>, String> {
+    let mut files = Vec::new();
+    read_with_includes(path, &mut Vec::new(), &mut files)?;
+    Ok(files)
+}
+
+fn read_with_includes(
+    path: &Path,
+    including: &mut Vec<PathBuf>,
+    files: &mut Vec<ConfigFile>,
+) -> Result<(), String> {
+    // the same file reached through links or `..` is still the same file
+    let path = &path
+        .canonicalize()
+        .map_err(|e| format!("couldn't read {}: {e}", path.display()))?;
+    if including.iter().any(|p| p == path) {
+        return Err(format!("{} includes itself", path.display()));
+    }
+    // included again by another file, its settings are in place already
+    if files.iter().any(|f| f.path == *path) {
+        return Ok(());
+    }
+    let source = std::fs::read_to_string(path)
+        .map_err(|e| format!("couldn't read {}: {e}", path.display()))?;
+    let mut table = source
+        .parse::<toml::Table>()
+        .map_err(|e| format!("{}: {e}", path.display()))?;
+    let includes = match table.remove("include") {
+        None => Vec::new(),
+        Some(toml::Value::String(s)) => vec![s],
+        Some(toml::Value::Array(a)) => a
+            .into_iter()
+            .map(|v| v.as_str().map(str::to_owned))
+            .collect::<Option<_>>()
+            .ok_or(format!("{}: include takes file names", path.display()))?,
+        Some(_) => return Err(format!("{}: include takes file names", path.display())),
+    };
+
+    including.push(path.to_owned());
+    let dir = path.parent().unwrap_or(Path::new("."));
+    for include in includes {
+        read_with_includes(&dir.join(expand_env(&include)), including, files)?;
+    }
+    including.pop();
+
+    files.push(ConfigFile {
+        path: path.to_owned(),
+        source,
+        table,
+    });
+    Ok(())
+}
+
+/// a key that moved, as dotted paths like `"font.font"`
+struct Renamed {
+    /// the first config version with the new name
+    version: u32,
+    old: &'static str,
+    new: &'static str,
+}
 
-                match std::fs::write(&path, serialized) {
-                    Ok(_) => log::info!("created default config at {path:?}"),
-                    Err(_) => {
-                        log::error!("couldn't write to file, using default");
+/// every rename since version 1, oldest first
+const RENAMED: &[Renamed] = &[];
+
+/// moves renamed keys of a file written for an older config version to their new names,
+/// warning about each one
+fn migrate(table: &mut toml::Table, problems: &mut Vec<String>) {
+    migrate_renamed(table, RENAMED, problems);
+}
+
+fn migrate_renamed(table: &mut toml::Table, renamed: &[Renamed], problems: &mut Vec<String>) {
+    let version = match table.remove("version") {
+        None => 1,
+        Some(toml::Value::Integer(v)) if v >= 1 => v as u32,
+        Some(v) => {
+            problems.push(format!("version takes a number from 1, not {v}"));
+            return;
+        }
+    };
+    if version > CONFIG_VERSION {
+        problems.push(format!(
+            "config version {version} is newer than this hematite knows ({CONFIG_VERSION}), \
+             newer settings are ignored"
+        ));
+    }
+    for renamed in renamed.iter().filter(|r| version < r.version) {
+        if rename(table, renamed.old, renamed.new) {
+            problems.push(format!(
+                "`{}` is called `{}` since config version {}, rename it and set version = {CONFIG_VERSION}",
+                renamed.old, renamed.new, renamed.version
+            ));
+        }
+    }
+}
+
+/// moves the key at dotted path `old` to `new` within the same table, in every entry of lists
+/// like `[[hotkeys]]`. whether anything moved
+fn rename(table: &mut toml::Table, old: &str, new: &str) -> bool {
+    let (old_key, new_key) = match (old.split_once('.'), new.split_once('.')) {
+        (Some((section, old)), Some((same, new))) if section == same => {
+            return match table.get_mut(section) {
+                Some(toml::Value::Table(t)) => rename(t, old, new),
+                Some(toml::Value::Array(entries)) => {
+                    let mut moved = false;
+                    for t in entries.iter_mut().filter_map(toml::Value::as_table_mut) {
+                        moved |= rename(t, old, new);
                     }
+                    moved
                 }
+                _ => false,
+            };
+        }
+        _ => (old, new),
+    };
+    match table.remove(old_key) {
+        Some(value) => {
+            table.entry(new_key).or_insert(value);
+            true
+        }
+        None => false,
+    }
+}
+
+/// reads the sections of a config one by one, collecting what went wrong
+struct SectionReader {
+    /// unknown keys make their section or list entry unreadable instead of being ignored
+    strict: bool,
+    problems: Vec<String>,
+}
+
+impl SectionReader {
+    fn read<T: DeserializeOwned>(&mut self, value: toml::Value, place: &str) -> Option<T> {
+        let mut unknown = Vec::new();
+        let result = serde_ignored::deserialize(value, |path| unknown.push(path.to_string()));
+        for key in &unknown {
+            self.problems
+                .push(format!("unknown key `{key}` in {place}"));
+        }
+        match result {
+            Ok(_) if self.strict && !unknown.is_empty() => {
+                self.problems.push(format!(
+                    "leaving out {place}, strict mode allows no unknown keys"
+                ));
+                None
+            }
+            Ok(v) => Some(v),
+            Err(e) => {
+                let e: toml::de::Error = e;
+                let message = e.to_string().trim().replace('\n', " ");
+                self.problems
+                    .push(format!("leaving out {place}: {message}"));
+                None
+            }
+        }
+    }
+
+    /// a table like `[sizing]`, `default` when missing or unreadable
+    fn section<T: DeserializeOwned>(
+        &mut self,
+        table: &mut toml::Table,
+        name: &str,
+        default: impl FnOnce() -> T,
+    ) -> T {
+        match table.remove(name) {
+            Some(value) => self
+                .read(value, &format!("[{name}]"))
+                .unwrap_or_else(default),
+            None => default(),
+        }
+    }
+
+    /// a list like `[[hotkeys]]`, keeping the entries that can be read
+    fn list<T: DeserializeOwned>(
+        &mut self,
+        table: &mut toml::Table,
+        name: &str,
+        default: impl FnOnce() -> Vec<T>,
+    ) -> Vec<T> {
+        match table.remove(name) {
+            Some(toml::Value::Array(entries)) => entries
+                .into_iter()
+                .enumerate()
+                .filter_map(|(i, entry)| self.read(entry, &format!("[[{name}]] number {}", i + 1)))
+                .collect(),
+            Some(_) => {
+                self.problems.push(format!(
+                    "leaving out {name}, it should be a list like [[{name}]]"
+                ));
+                default()
+            }
+            None => default(),
+        }
+    }
+}
 
+/// lays `over` onto `base`: tables are merged, lists of tables like `[[hotkeys]]` are joined
+/// and anything else is replaced
+fn merge_tables(base: &mut toml::Table, over: toml::Table) {
+    for (key, value) in over {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(b)), toml::Value::Table(o)) => merge_tables(b, o),
+            (Some(toml::Value::Array(b)), toml::Value::Array(o))
+                if b.iter().chain(&o).all(toml::Value::is_table) =>
+            {
+                b.extend(o)
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+impl ConfigDeserialized {
+    pub fn new() -> Self {
+        let path = match config_path() {
+            Some(p) => p,
+            None => {
+                log::info!("no config directory, using default config");
                 return Self::default();
             }
         };
-        
-        match toml::from_str(&config_str) {
-            Ok(d) => d,
+
+        log::info!("loading config from {path:?}");
+
+        if !path.exists() {
+            log::info!("config not found, writing default");
+
+            match std::fs::write(&path, Self::default_document()) {
+                Ok(_) => log::info!("created default config at {path:?}"),
+                Err(_) => {
+                    log::error!("couldn't write to file, using default");
+                }
+            }
+
+            return Self::default();
+        }
+
+        let files = match read_config_files(&path) {
+            Ok(f) => f,
             Err(e) => {
-                log::error!("error parsing config {e:?}, using default");
-                Self::default()
+                log::error!("error parsing config {e}, using default");
+                return Self::default();
             }
+        };
+        let (config, problems) = Self::from_files(files);
+        for problem in problems {
+            log::warn!("config: {problem}");
+        }
+        config
+    }
+
+    /// the config made of files read by [`read_config_files`], later files taking precedence,
+    /// and the problems found on the way. a section or list entry that can't be read is left
+    /// out with a warning instead of losing the whole config
+    pub fn from_files(files: Vec<ConfigFile>) -> (Self, Vec<String>) {
+        let mut merged = toml::Table::new();
+        let mut problems = Vec::new();
+        for mut file in files {
+            log::debug!("merging {:?}", file.path);
+            migrate(&mut file.table, &mut problems);
+            merge_tables(&mut merged, file.table);
+        }
+        let mut reader = SectionReader {
+            strict: false,
+            problems,
+        };
+        reader.strict = match merged.remove("strict") {
+            None => false,
+            Some(toml::Value::Boolean(b)) => b,
+            Some(v) => {
+                reader
+                    .problems
+                    .push(format!("strict takes true or false, not {v}"));
+                false
+            }
+        };
+
+        let config = Self {
+            sizing: reader.section(&mut merged, "sizing", Sizing::default),
+            colors: reader.section(&mut merged, "colors", || Self::default().colors),
+            font: reader.section(&mut merged, "font", Font::default),
+            behavior: reader.section(&mut merged, "behavior", Behavior::default),
+            apps: reader.section(&mut merged, "apps", default_apps),
+            hotkeys: reader.list(&mut merged, "hotkeys", || Self::default().hotkeys),
+            mousebinds: reader.list(&mut merged, "mousebinds", default_mousebinds),
+            modes: reader.list(&mut merged, "modes", Vec::new),
+            monitors: reader.list(&mut merged, "monitors", Vec::new),
+            rules: reader.list(&mut merged, "rules", Vec::new),
+            hooks: reader.section(&mut merged, "hooks", Hooks::default),
+            layouts: reader.list(&mut merged, "layouts", Vec::new),
+            tags: reader.list(&mut merged, "tags", Vec::new),
+            scratchpads: reader.list(&mut merged, "scratchpads", Vec::new),
+        };
+        for key in merged.keys() {
+            reader.problems.push(format!("unknown section `{key}`"));
         }
+        (config, reader.problems)
     }
+
+    /// problems with the values that are only kept as text, as `(value, problem)`
+    pub fn check_values(&self) -> Vec<(String, String)> {
+        let mut problems = Vec::new();
+        let colors = &self.colors;
+        let optional = [
+            &colors.focused_border,
+            &colors.unfocused_border,
+            &colors.urgent_border,
+            &colors.bar_background,
+            &colors.bar_foreground,
+            &colors.active_tag,
+            &colors.inactive_tag,
+            &colors.status_text,
+            &colors.indicator,
+        ];
+        for color in [&colors.main_color, &colors.secondary_color]
+            .into_iter()
+            .chain(optional)
+            .flatten()
+        {
+            if parse_hex_color(color).is_none() {
+                problems.push((color.clone(), format!("{color:?} is not a #rrggbb color")));
+            }
+        }
+        if keys::parse_modifier(&self.behavior.mod_key).is_none() {
+            let mod_key = &self.behavior.mod_key;
+            let problem = match mod_key.as_str() {
+                "MOD2" => "MOD2 is NumLock, which bindings ignore".to_owned(),
+                m => format!("unknown mod key {m:?}"),
+            };
+            problems.push((mod_key.clone(), problem));
+        }
+        if let Some(theme) = colors
+            .theme
+            .as_ref()
+            .filter(|t| !THEMES.contains(&t.as_str()))
+        {
+            problems.push((
+                theme.clone(),
+                format!("unknown theme {theme:?}, there are {}", THEMES.join(", ")),
+            ));
+        }
+        problems
+    }
+}
+
+impl Default for ConfigDeserialized {
     fn default() -> Self {
-        log::info!("using default config");
         let mut hotkeys = vec![
             // terminal
             HotkeyConfig {
                 modifiers: "CONTROL|MOD".to_string(),
                 key: "XK_Return".to_string(),
-                action: HotkeyAction::Spawn("alacritty".to_string()),
+                trigger: Trigger::Press,
+                action: vec![HotkeyAction::Spawn(SpawnCommand::Shell(
+                    "$terminal".to_string(),
+                ))],
             },
             // browser
             HotkeyConfig {
                 modifiers: "CONTROL|MOD".to_string(),
                 key: "l".to_string(),
-                action: HotkeyAction::Spawn("librewolf".to_string()),
+                trigger: Trigger::Press,
+                action: vec![HotkeyAction::Spawn(SpawnCommand::Shell(
+                    "$browser".to_string(),
+                ))],
             },
             // quit window
             HotkeyConfig {
                 modifiers: "MOD".to_string(),
                 key: "q".to_string(),
-                action: HotkeyAction::ExitFocusedWindow,
+                trigger: Trigger::Press,
+                action: vec![HotkeyAction::ExitFocusedWindow],
             },
             // shutdown
             HotkeyConfig {
                 modifiers: "CONTROL|MOD".to_string(),
                 key: "q".to_string(),
-                action: HotkeyAction::Spawn("killall hematite".to_string()),
+                trigger: Trigger::Press,
+                action: vec![HotkeyAction::Quit],
             },
             // app starter
             HotkeyConfig {
                 modifiers: "MOD".to_string(),
                 key: "c".to_string(),
-                action: HotkeyAction::Spawn("rofi -show drun".to_string()),
+                trigger: Trigger::Press,
+                action: vec![HotkeyAction::Spawn(SpawnCommand::Shell(
+                    "rofi -show drun".to_string(),
+                ))],
             },
             // screenshot
             HotkeyConfig {
                 modifiers: "MOD".to_string(),
                 key: "u".to_string(),
-                action: HotkeyAction::Spawn(
+                trigger: Trigger::Press,
+                action: vec![HotkeyAction::Spawn(SpawnCommand::Shell(
                     "maim --select | xclip -selection clipboard -t image/png".to_string(),
-                ),
+                ))],
             },
             // change ratio
             HotkeyConfig {
                 modifiers: "MOD".to_string(),
                 key: "h".to_string(),
-                action: HotkeyAction::ChangeRatio(-0.05),
+                trigger: Trigger::Press,
+                action: vec![HotkeyAction::ChangeRatio(-0.05)],
             },
             HotkeyConfig {
                 modifiers: "MOD".to_string(),
                 key: "j".to_string(),
-                action: HotkeyAction::ChangeRatio(0.05),
+                trigger: Trigger::Press,
+                action: vec![HotkeyAction::ChangeRatio(0.05)],
             },
             // change focus
             HotkeyConfig {
                 modifiers: "MOD".to_string(),
                 key: "k".to_string(),
-                action: HotkeyAction::NextFocus(1),
+                trigger: Trigger::Press,
+                action: vec![HotkeyAction::NextFocus(1)],
             },
             HotkeyConfig {
                 modifiers: "MOD".to_string(),
                 key: "l".to_string(),
-                action: HotkeyAction::NextFocus(-1),
+                trigger: Trigger::Press,
+                action: vec![HotkeyAction::NextFocus(-1)],
             },
             // change tag
             HotkeyConfig {
                 modifiers: "MOD".to_string(),
                 key: "XK_Left".to_string(),
-                action: HotkeyAction::NextTag(-1),
+                trigger: Trigger::Press,
+                action: vec![HotkeyAction::NextTag(-1)],
             },
             HotkeyConfig {
                 modifiers: "MOD".to_string(),
                 key: "XK_Right".to_string(),
-                action: HotkeyAction::NextTag(1),
+                trigger: Trigger::Press,
+                action: vec![HotkeyAction::NextTag(1)],
             },
             // swap master
             HotkeyConfig {
                 modifiers: "MOD".to_string(),
                 key: "XK_Return".to_string(),
-                action: HotkeyAction::SwapMaster,
+                trigger: Trigger::Press,
+                action: vec![HotkeyAction::SwapMaster],
+            },
+            // move tag to monitor
+            HotkeyConfig {
+                modifiers: "MOD|SHIFT".to_string(),
+                key: "XK_Left".to_string(),
+                trigger: Trigger::Press,
+                action: vec![HotkeyAction::MoveTagToMonitor(-1)],
+            },
+            HotkeyConfig {
+                modifiers: "MOD|SHIFT".to_string(),
+                key: "XK_Right".to_string(),
+                trigger: Trigger::Press,
+                action: vec![HotkeyAction::MoveTagToMonitor(1)],
+            },
+            // change monitor
+            HotkeyConfig {
+                modifiers: "MOD|CONTROL".to_string(),
+                key: "XK_Left".to_string(),
+                trigger: Trigger::Press,
+                action: vec![HotkeyAction::FocusMonitor(-1)],
+            },
+            HotkeyConfig {
+                modifiers: "MOD|CONTROL".to_string(),
+                key: "XK_Right".to_string(),
+                trigger: Trigger::Press,
+                action: vec![HotkeyAction::FocusMonitor(1)],
+            },
+            // fullscreen across all monitors
+            HotkeyConfig {
+                modifiers: "MOD|SHIFT".to_string(),
+                key: "f".to_string(),
+                trigger: Trigger::Press,
+                action: vec![HotkeyAction::ToggleSpanFullscreen],
+            },
+            // float or tile the focused window
+            HotkeyConfig {
+                modifiers: "MOD".to_string(),
+                key: "space".to_string(),
+                trigger: Trigger::Press,
+                action: vec![HotkeyAction::ToggleFloating],
+            },
+            // move window to an empty tag
+            HotkeyConfig {
+                modifiers: "MOD|SHIFT".to_string(),
+                key: "e".to_string(),
+                trigger: Trigger::Press,
+                action: vec![HotkeyAction::MoveWindowToEmptyTag(true)],
+            },
+            // cheat sheet
+            HotkeyConfig {
+                modifiers: "MOD".to_string(),
+                key: "slash".to_string(),
+                trigger: Trigger::Press,
+                action: vec![HotkeyAction::ShowHotkeys],
+            },
+            // banish pointer
+            HotkeyConfig {
+                modifiers: "MOD".to_string(),
+                key: "b".to_string(),
+                trigger: Trigger::Press,
+                action: vec![HotkeyAction::BanishPointer],
+            },
+            // marks
+            HotkeyConfig {
+                modifiers: "MOD".to_string(),
+                key: "m".to_string(),
+                trigger: Trigger::Press,
+                action: vec![HotkeyAction::SetMark],
+            },
+            HotkeyConfig {
+                modifiers: "MOD".to_string(),
+                key: "apostrophe".to_string(),
+                trigger: Trigger::Press,
+                action: vec![HotkeyAction::JumpToMark],
+            },
+            // alt-tab
+            HotkeyConfig {
+                modifiers: "MOD1".to_string(),
+                key: "Tab".to_string(),
+                trigger: Trigger::Press,
+                action: vec![HotkeyAction::CycleWindows(1)],
+            },
+            HotkeyConfig {
+                modifiers: "MOD1|SHIFT".to_string(),
+                key: "Tab".to_string(),
+                trigger: Trigger::Press,
+                action: vec![HotkeyAction::CycleWindows(-1)],
             },
             //media
             HotkeyConfig {
                 modifiers: "".to_string(),
                 key: "XF86_AudioRaiseVolume".to_string(),
-                action: HotkeyAction::Spawn("/usr/bin/pactl set-sink-volume 0 +5%".to_string())
+                trigger: Trigger::Press,
+                action: vec![HotkeyAction::Spawn(SpawnCommand::Shell(
+                    "/usr/bin/pactl set-sink-volume 0 +5%".to_string(),
+                ))],
             },
             HotkeyConfig {
                 modifiers: "".to_string(),
                 key: "XF86_AudioLowerVolume".to_string(),
-                action: HotkeyAction::Spawn("/usr/bin/pactl set-sink-volume 0 -5%".to_string())
+                trigger: Trigger::Press,
+                action: vec![HotkeyAction::Spawn(SpawnCommand::Shell(
+                    "/usr/bin/pactl set-sink-volume 0 -5%".to_string(),
+                ))],
             },
             HotkeyConfig {
                 modifiers: "".to_string(),
                 key: "XF86_AudioMute".to_string(),
-                action: HotkeyAction::Spawn("/usr/bin/pactl set-sink-mute 0 toggle".to_string())
+                trigger: Trigger::Press,
+                action: vec![HotkeyAction::Spawn(SpawnCommand::Shell(
+                    "/usr/bin/pactl set-sink-mute 0 toggle".to_string(),
+                ))],
             },
             HotkeyConfig {
                 modifiers: "".to_string(),
                 key: "XF86_MonBrightnessUp".to_string(),
-                action: HotkeyAction::Spawn("sudo light -A 5".to_string())
+                trigger: Trigger::Press,
+                action: vec![HotkeyAction::Spawn(SpawnCommand::Shell(
+                    "sudo light -A 5".to_string(),
+                ))],
             },
             HotkeyConfig {
                 modifiers: "".to_string(),
                 key: "XF86_MonBrightnessDown".to_string(),
-                action: HotkeyAction::Spawn("sudo light -U 5".to_string())
+                trigger: Trigger::Press,
+                action: vec![HotkeyAction::Spawn(SpawnCommand::Shell(
+                    "sudo light -U 5".to_string(),
+                ))],
             },
-
         ];
+        // one digit key per tag, as far as the digits go
+        let tags = 1..=Behavior::default().tags.min(10);
         hotkeys.extend(
             // switch to tag
-            (1..=9)
+            tags.clone()
                 .map(|x| HotkeyConfig {
                     modifiers: "MOD".to_string(),
-                    key: x.to_string(),
-                    action: HotkeyAction::SwitchTag(x),
+                    key: tag_key(x),
+                    trigger: Trigger::Press,
+                    action: vec![HotkeyAction::SwitchTag(x)],
                 })
                 // move window to tag
-                .chain((1..=9).map(|x| HotkeyConfig {
+                .chain(tags.clone().map(|x| HotkeyConfig {
                     modifiers: "MOD|SHIFT".to_string(),
-                    key: x.to_string(),
-                    action: HotkeyAction::MoveWindow(x),
+                    key: tag_key(x),
+                    trigger: Trigger::Press,
+                    action: vec![HotkeyAction::MoveWindow(x)],
+                }))
+                // move window to tag and follow it
+                .chain(tags.map(|x| HotkeyConfig {
+                    modifiers: "MOD|CONTROL".to_string(),
+                    key: tag_key(x),
+                    trigger: Trigger::Press,
+                    action: vec![HotkeyAction::MoveWindowAndFollow(x)],
                 }))
                 .collect::<Vec<_>>(),
         );
 
         ConfigDeserialized {
-            sizing: Sizing {
-                spacing: SPACING,
-                ratio: RATIO,
-                border_size: BORDER_SIZE,
-            },
+            sizing: Sizing::default(),
             colors: Colors {
-                main_color: String::from("#11111b"),
-                secondary_color: String::from("#74c7ec"),
-            },
-            font: Font {
-                font: FONT.to_owned(),
+                main_color: Some(String::from("#11111b")),
+                secondary_color: Some(String::from("#74c7ec")),
+                ..Colors::default()
             },
+            font: Font::default(),
+            behavior: Behavior::default(),
+            apps: default_apps(),
             hotkeys,
+            mousebinds: default_mousebinds(),
+            modes: Vec::new(),
+            monitors: Vec::new(),
+            rules: Vec::new(),
+            hooks: Hooks::default(),
+            layouts: Vec::new(),
+            tags: Vec::new(),
+            scratchpads: Vec::new(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a fresh directory for the files of one test
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("hematite-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn table(source: &str) -> toml::Table {
+        source.parse().unwrap()
+    }
+
+    fn from_source(source: &str) -> (ConfigDeserialized, Vec<String>) {
+        ConfigDeserialized::from_files(vec![ConfigFile {
+            path: PathBuf::from("config.toml"),
+            source: source.to_owned(),
+            table: table(source),
+        }])
+    }
+
+    #[test]
+    fn partial_sections_take_the_rest_from_the_defaults() {
+        let (config, problems) = from_source("[sizing]\nratio = 0.6\n[font]\n[behavior]\ntags = 5");
+        assert!(problems.is_empty(), "{problems:?}");
+        assert_eq!(config.sizing.ratio, 0.6);
+        assert_eq!(config.sizing.spacing, Size::Pixels(SPACING));
+        assert_eq!(config.sizing.ratio_presets, [0.5, 0.62, 0.7]);
+        assert_eq!(config.font.font, FONT);
+        assert_eq!(config.behavior.tags, 5);
+    }
+
+    #[test]
+    fn unreadable_section_falls_back_and_keeps_the_rest() {
+        let (config, problems) = from_source("[sizing]\nratio = \"wide\"\n[behavior]\ntags = 5");
+        assert_eq!(problems.len(), 1, "{problems:?}");
+        assert!(problems[0].starts_with("leaving out [sizing]"));
+        assert_eq!(config.sizing.ratio, RATIO);
+        assert_eq!(config.behavior.tags, 5);
+    }
+
+    #[test]
+    fn renamed_keys_move_with_a_warning() {
+        let renamed = [
+            Renamed {
+                version: 2,
+                old: "sizing.gap",
+                new: "sizing.spacing",
+            },
+            Renamed {
+                version: 2,
+                old: "hotkeys.mods",
+                new: "hotkeys.modifiers",
+            },
+        ];
+        let mut problems = Vec::new();
+        let mut old =
+            table("[sizing]\ngap = 4\n[[hotkeys]]\nmods = \"MOD\"\n[[hotkeys]]\nkey = \"a\"");
+        migrate_renamed(&mut old, &renamed, &mut problems);
+        assert_eq!(
+            old,
+            table(
+                "[sizing]\nspacing = 4\n[[hotkeys]]\nmodifiers = \"MOD\"\n[[hotkeys]]\nkey = \"a\""
+            )
+        );
+        assert_eq!(problems.len(), 2);
+        assert!(
+            problems[0]
+                .starts_with("`sizing.gap` is called `sizing.spacing` since config version 2")
+        );
+
+        // a file written for the version with the new names is left alone
+        let mut problems = Vec::new();
+        let mut new = table("version = 2\n[sizing]\ngap = 4");
+        migrate_renamed(&mut new, &renamed, &mut problems);
+        assert_eq!(new, table("[sizing]\ngap = 4"));
+        assert_eq!(problems.len(), 1, "{problems:?}");
+        assert!(problems[0].starts_with("config version 2 is newer"));
+    }
+
+    #[test]
+    fn merge_tables_merges_tables_and_joins_lists_of_tables() {
+        let mut base = table(
+            "ratio = 0.5\ntags = [1]\n[sizing]\ngap = 4\nborder = 2\n[[hotkeys]]\nkey = \"a\"",
+        );
+        merge_tables(
+            &mut base,
+            table("ratio = 0.6\ntags = [2]\n[sizing]\ngap = 8\n[[hotkeys]]\nkey = \"b\""),
+        );
+        assert_eq!(
+            base,
+            table(
+                "ratio = 0.6\ntags = [2]\n[sizing]\ngap = 8\nborder = 2\n\
+                 [[hotkeys]]\nkey = \"a\"\n[[hotkeys]]\nkey = \"b\""
+            )
+        );
+    }
+
+    #[test]
+    fn includes_come_first_and_each_file_once() {
+        let dir = temp_dir("includes");
+        std::fs::create_dir(dir.join("sub")).unwrap();
+        std::fs::write(
+            dir.join("config.toml"),
+            "include = [\"a.toml\", \"sub/b.toml\"]",
+        )
+        .unwrap();
+        std::fs::write(dir.join("a.toml"), "include = \"common.toml\"").unwrap();
+        std::fs::write(dir.join("sub/b.toml"), "include = \"../common.toml\"").unwrap();
+        std::fs::write(dir.join("common.toml"), "ratio = 0.6").unwrap();
+        let names = read_config_files(&dir.join("config.toml"))
+            .unwrap()
+            .iter()
+            .map(|f| {
+                f.path
+                    .strip_prefix(dir.canonicalize().unwrap())
+                    .unwrap()
+                    .to_owned()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            ["common.toml", "a.toml", "sub/b.toml", "config.toml"].map(PathBuf::from)
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn include_cycles_are_errors() {
+        let dir = temp_dir("cycle");
+        std::fs::create_dir(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("config.toml"), "include = \"sub/a.toml\"").unwrap();
+        std::fs::write(
+            dir.join("sub/a.toml"),
+            "include = \"../sub/../config.toml\"",
+        )
+        .unwrap();
+        let error = read_config_files(&dir.join("config.toml")).err().unwrap();
+        assert!(error.ends_with("config.toml includes itself"), "{error}");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn variables_expand_with_or_without_braces() {
+        let lookup = |name: &str| match name {
+            "TERM" => Some("alacritty".to_owned()),
+            "DIR" => Some("/opt".to_owned()),
+            _ => None,
+        };
+        assert_eq!(expand_with("$TERM -e top", lookup), "alacritty -e top");
+        assert_eq!(
+            expand_with("${DIR}/bin/$TERM.sh", lookup),
+            "/opt/bin/alacritty.sh"
+        );
+        // unknown and empty names, and a lone dollar, stay as written
+        assert_eq!(
+            expand_with("$UNSET ${} $ end$", lookup),
+            "$UNSET ${} $ end$"
+        );
+        assert_eq!(expand_with("${DIR", lookup), "${DIR");
+    }
+
+    #[test]
+    fn home_expands_only_at_the_start() {
+        let home = std::env::var("HOME").unwrap();
+        assert_eq!(expand_env("~/bin/x"), format!("{home}/bin/x"));
+        assert_eq!(expand_env("a ~/b"), "a ~/b");
+    }
+
+    #[test]
+    fn sizes_are_pixels_or_percentages() {
+        let size = |value: &str| {
+            toml::from_str::<toml::Table>(&format!("size = {value}")).unwrap()["size"]
+                .clone()
+                .try_into::<Size>()
+        };
+        assert_eq!(size("12").unwrap(), Size::Pixels(12));
+        assert_eq!(size("\"0.5%\"").unwrap(), Size::Percent(0.5));
+        assert_eq!(size("\" 2 %\"").unwrap(), Size::Percent(2.0));
+        assert!(size("\"0.5\"").is_err());
+        assert!(size("\"half%\"").is_err());
+        assert!(size("-3").is_err());
+    }
+
+    #[test]
+    fn percentages_follow_the_monitor_width() {
+        assert_eq!(Size::Percent(0.5).pixels(1920), 10);
+        assert_eq!(Size::Pixels(7).pixels(1920), 7);
+        assert_eq!(Size::Percent(50.0).clamped(), Size::Percent(25.0));
+        assert_eq!(Size::Percent(1.0).scaled(2.0), Size::Percent(1.0));
+        assert_eq!(Size::Pixels(5).scaled(1.5), Size::Pixels(8));
+    }
+
+    #[test]
+    fn layouts_apply_to_the_same_tag_on_every_monitor() {
+        let (config, _) = from_source(
+            "[behavior]\ntags = 9\nworkspaces = \"per_monitor\"\n\
+             [[layouts]]\ncommand = \"columns\"\ntags = [3]",
+        );
+        let config = Config::from(config);
+        assert_eq!(config.get_layout_command(2), Some("columns"));
+        assert_eq!(config.get_layout_command(11), Some("columns"));
+        assert_eq!(config.get_layout_command(12), None);
+        assert_eq!(config.get_layout_name(12), "master-stack");
+    }
+}
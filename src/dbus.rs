@@ -0,0 +1,75 @@
+//! the control commands on the session bus, as org.hematite.WindowManager
+
+use std::{
+    sync::{Arc, mpsc::Sender},
+    thread,
+};
+
+use zbus::{fdo, interface};
+
+use crate::{
+    ipc::{self, Command, Query, Request},
+    poll::EventFd,
+};
+
+struct WindowManager {
+    tx: Sender<Request>,
+    waker: Arc<EventFd>,
+}
+
+impl WindowManager {
+    fn send(&self, command: Command) -> fdo::Result<String> {
+        match ipc::send(&self.tx, &self.waker, command) {
+            Ok(reply) => match reply.strip_prefix("error: ") {
+                Some(e) => Err(fdo::Error::Failed(e.to_owned())),
+                None => Ok(reply),
+            },
+            Err(e) => Err(fdo::Error::Failed(e.to_string())),
+        }
+    }
+
+    fn run(&self, line: &str) -> fdo::Result<String> {
+        self.send(ipc::parse_command(line).map_err(fdo::Error::InvalidArgs)?)
+    }
+}
+
+#[interface(name = "org.hematite.WindowManager")]
+impl WindowManager {
+    fn switch_tag(&self, tag: u32) -> fdo::Result<()> {
+        self.run(&format!("switch-tag {tag}")).map(|_| ())
+    }
+
+    fn move_window(&self, tag: u32) -> fdo::Result<()> {
+        self.run(&format!("move-window {tag}")).map(|_| ())
+    }
+
+    /// any command `rwmc` takes, answered the same way
+    fn command(&self, command: &str) -> fdo::Result<String> {
+        self.run(command)
+    }
+
+    fn focused_title(&self) -> fdo::Result<String> {
+        self.send(Command::Query(Query::Title))
+    }
+
+    /// the `query tags` json
+    fn tags(&self) -> fdo::Result<String> {
+        self.send(Command::Query(Query::Tags))
+    }
+}
+
+pub(crate) fn serve(tx: Sender<Request>, waker: Arc<EventFd>) -> zbus::Result<()> {
+    let conn = zbus::blocking::connection::Builder::session()?
+        .name("org.hematite.WindowManager")?
+        .serve_at("/org/hematite/WindowManager", WindowManager { tx, waker })?
+        .build()?;
+    log::info!("registered org.hematite.WindowManager on the session bus");
+    // the bus is served for as long as the connection lives
+    thread::spawn(move || {
+        let _conn = conn;
+        loop {
+            thread::park();
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,69 @@
+//! the error type of everything that talks to the X server
+
+use x11rb::{
+    errors::{ConnectError, ConnectionError, ReplyError, ReplyOrIdError},
+    protocol::{Event, xproto::Window},
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum WmError {
+    #[error("couldn't connect to the X server: {0}")]
+    Connect(#[from] ConnectError),
+    #[error("lost the X connection: {0}")]
+    Connection(#[from] ConnectionError),
+    #[error(transparent)]
+    Reply(#[from] ReplyError),
+    #[error(transparent)]
+    ReplyOrId(#[from] ReplyOrIdError),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("another window manager is running, start with --replace to take over")]
+    WmRunning,
+    #[error("another client redirects the root window, it may be a window manager without WM_S{0}")]
+    RedirectTaken(usize),
+    #[error("couldn't take the WM_S{0} selection")]
+    Selection(usize),
+    /// what was being done when `source` happened
+    #[error("{context}: {source}")]
+    Context {
+        context: String,
+        source: Box<WmError>,
+    },
+}
+
+impl WmError {
+    /// wraps the error with what was being done, like "handling map request of 0x400001"
+    pub fn context(self, context: impl Into<String>) -> Self {
+        WmError::Context {
+            context: context.into(),
+            source: Box::new(self),
+        }
+    }
+}
+
+/// the event and the window it is about, for error messages
+pub fn describe_event(event: &Event) -> String {
+    let (name, window): (&str, Window) = match event {
+        Event::MapRequest(e) => ("map request", e.window),
+        Event::UnmapNotify(e) => ("unmap", e.window),
+        Event::DestroyNotify(e) => ("destroy", e.window),
+        Event::ConfigureRequest(e) => ("configure request", e.window),
+        Event::ClientMessage(e) => ("client message", e.window),
+        Event::PropertyNotify(e) => ("property change", e.window),
+        Event::EnterNotify(e) => ("enter", e.event),
+        Event::ButtonPress(e) => ("button press", e.event),
+        Event::ButtonRelease(e) => ("button release", e.event),
+        Event::MotionNotify(e) => ("motion", e.event),
+        Event::KeyPress(e) => ("key press", e.event),
+        Event::KeyRelease(e) => ("key release", e.event),
+        Event::Expose(e) => ("expose", e.window),
+        event => {
+            return format!("{event:?}")
+                .split(['(', ' '])
+                .next()
+                .unwrap_or_default()
+                .to_owned();
+        }
+    };
+    format!("{name} of window {window}")
+}
@@ -1,18 +1,45 @@
+//! X events and control commands, handled by [`EventHandler`]
+
+use serde::Serialize;
 use x11rb::{
     connection::Connection,
-    protocol::{Event, xproto::*},
+    properties::WmHints,
+    protocol::{ErrorKind, Event, xproto::*},
 };
 
 use crate::{
-    actions::{ConnectionHandler, Res},
-    keys::{HotkeyAction, KeyHandler},
-    state::{StateHandler, WindowGroup, WindowState},
+    actions::{self, ConnectionHandler, Res},
+    config::{
+        Config, ConfigDeserialized, FocusNew, MouseContext, ScratchpadConfig, Trigger,
+        WorkspaceModel,
+    },
+    error::WmError,
+    i3ipc,
+    ipc::{
+        self, Command, Con, Node, Output, Query, Rect, Request, Subscribers, TagInfo, WindowInfo,
+        Workspace, WorkspaceEvent, to_json,
+    },
+    keys::{HotkeyAction, KeyHandler, Snap, clean_mask},
+    session::Session,
+    signal,
+    state::{Cycle, Drag, DragKind, StateHandler, WindowGroup, WindowState},
 };
 
+/// set by the quit and restart actions, the main loop stops once it is set
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shutdown {
+    Quit,
+    Restart,
+}
+
 pub struct EventHandler<'a, C: Connection> {
-    pub conn: &'a ConnectionHandler<'a, C>,
+    pub conn: ConnectionHandler<'a, C>,
     pub man: StateHandler,
     pub key: KeyHandler,
+    pub shutdown: Option<Shutdown>,
+    pub subscribers: Subscribers,
+    /// saved arrangements of windows not managed again yet
+    pub session: Session,
 }
 
 impl<'a, C: Connection> EventHandler<'a, C> {
@@ -24,25 +51,109 @@ impl<'a, C: Connection> EventHandler<'a, C> {
             Event::UnmapNotify(e) => {
                 self.handle_unmap_notify(e)?;
             }
+            Event::DestroyNotify(e) => {
+                self.handle_destroy_notify(e)?;
+            }
             Event::KeyPress(e) => {
-                self.handle_keypress(e)?;
+                self.handle_keypress(e, Trigger::Press)?;
+            }
+            Event::KeyRelease(e) => {
+                self.handle_keypress(e, Trigger::Release)?;
+                if self.man.cycle.is_some() {
+                    self.handle_cycle_release()?;
+                }
             }
             Event::EnterNotify(e) => {
                 self.handle_enter(e)?;
             }
+            Event::ButtonPress(e) => {
+                self.handle_button_press(e)?;
+            }
+            Event::MotionNotify(e) if self.man.drag.is_some() => {
+                self.handle_drag_motion(e)?;
+            }
+            Event::ButtonRelease(e) if self.man.drag.is_some() => {
+                self.handle_drag_end(e)?;
+            }
+            Event::MotionNotify(e) if e.event == self.conn.screen.root => {
+                self.handle_root_motion(e)?;
+            }
             Event::ConfigureRequest(e) => {
                 self.handle_config(e)?;
             }
             Event::ClientMessage(e) => {
                 self.handle_client_message(e)?;
             }
+            Event::ConfigureNotify(e) if e.window == self.conn.screen.root => {
+                self.handle_screen_change()?;
+            }
+            Event::RandrScreenChangeNotify(_) => {
+                self.handle_screen_change()?;
+            }
+            Event::PropertyNotify(e) => {
+                self.handle_property_notify(e)?;
+            }
+            Event::Expose(e) if e.count == 0 => {
+                self.handle_expose(e)?;
+            }
+            // requests aren't checked one by one, their errors come back here
+            Event::Error(e) => {
+                let request = e.request_name.unwrap_or("unknown request");
+                match e.error_kind {
+                    // windows are often destroyed before the requests about them are handled
+                    ErrorKind::Window => {
+                        log::debug!("X error {:?} in {request} on {}", e.error_kind, e.bad_value)
+                    }
+                    _ => log::error!("X error {:?} in {request} on {}", e.error_kind, e.bad_value),
+                }
+            }
+            // the only selection held is WM_Sn, lost to a window manager started with --replace
+            Event::SelectionClear(e) => {
+                log::info!("EVENT SELECTION CLEAR of {}, another wm took over", e.owner);
+                self.shutdown = Some(Shutdown::Quit);
+            }
             _ => (),
         };
         Ok(())
     }
 
+    /// saves where every window is, to be restored after a restart
+    pub fn save_session(&self) {
+        Session::capture(
+            &self.man,
+            |w| self.conn.get_wm_class(w).map(|c| c.1).unwrap_or_default(),
+            |t| self.conn.config.get_layout_name(t),
+        )
+        .save();
+    }
+
+    /// manages the windows that were already mapped on startup, as if they had just been mapped
+    pub fn adopt_windows(&mut self) -> Res {
+        let bars = self
+            .man
+            .monitors
+            .iter()
+            .map(|m| m.bar.frame_window)
+            .collect::<Vec<_>>();
+        let windows = self.conn.get_existing_windows(&bars)?;
+        self.session.start_adoption(&windows);
+        for window in windows {
+            log::debug!("adopting window {window}");
+            self.handle_map_request(MapRequestEvent {
+                response_type: MAP_REQUEST_EVENT,
+                sequence: 0,
+                parent: self.conn.screen.root,
+                window,
+            })?;
+        }
+        self.session.end_adoption();
+        Ok(())
+    }
+
     fn handle_map_request(&mut self, event: MapRequestEvent) -> Res {
-        if let Some(_) = self.man.get_window_state(event.window) {
+        if self.man.find_window_state(event.window).is_some()
+            || self.man.scratch.iter().any(|w| w.window == event.window)
+        {
             return Ok(());
         };
 
@@ -53,18 +164,193 @@ impl<'a, C: Connection> EventHandler<'a, C> {
             event.response_type
         );
 
-        let window = WindowState::new(event.window, self.conn.conn.generate_id()?)?;
+        if self.conn.is_dock(event.window)? {
+            self.man.add_dock(self.conn.get_dock(event.window)?);
+            self.conn.conn.map_window(event.window)?;
+            return self.refresh();
+        }
+
+        // a new window ends showing the desktop
+        self.show_desktop(false)?;
+
+        let properties = self.conn.get_window_properties(event.window)?;
+        let rules = self.conn.config.get_rule_effects(&properties);
+        log::debug!("window {properties:?} gets {rules:?}");
+
+        if rules.manage == Some(false) {
+            log::debug!("leaving window {} unmanaged", event.window);
+            self.man.unmanaged.retain(|w| *w != event.window);
+            self.man.unmanaged.push(event.window);
+            self.conn.conn.map_window(event.window)?;
+            return Ok(());
+        }
+
+        let border_color = match &rules.border_color {
+            Some(hex) => {
+                let color = self.conn.alloc_hex_color(hex)?;
+                if color.is_none() {
+                    log::error!("bad border color {hex:?} in rule");
+                }
+                color
+            }
+            None => None,
+        };
+        let mut window = WindowState {
+            border_width: rules.border_width,
+            border_color,
+            opacity: rules.opacity,
+            swallow: rules.swallow,
+            titlebar: self.conn.config.titlebars,
+            ..WindowState::new(event.window, self.conn.conn.generate_id()?)?
+        };
+
+        // the first window of a scratchpad's class becomes the scratchpad, shown where it was summoned
+        let scratchpad = self.conn.config.scratchpads.iter().find(|s| {
+            (s.class == properties.class || s.class == properties.instance)
+                && !self.man.scratchpads.contains_key(&s.name)
+        });
+        let monitor = rules
+            .monitor
+            .as_ref()
+            .and_then(|m| self.man.find_monitor(m))
+            .filter(|_| scratchpad.is_none())
+            .unwrap_or(self.man.active_monitor);
+        let saved = match scratchpad {
+            Some(_) => None,
+            None => self.session.take_window(event.window, &properties.class),
+        };
+        let tag = match (rules.tag, &saved) {
+            _ if scratchpad.is_some() => self.man.active_tag(),
+            (_, Some(s)) if (1..=self.man.tags.len()).contains(&s.tag) => s.tag - 1,
+            (Some(t), _) if (1..=self.man.tag_count).contains(&t) => t - 1,
+            _ => self.man.monitors[monitor].active_tag,
+        };
+        if let Some(scratchpad) = scratchpad {
+            log::debug!("window {} is scratchpad {}", event.window, scratchpad.name);
+            self.man
+                .scratchpads
+                .insert(scratchpad.name.clone(), event.window);
+            window.group = WindowGroup::Floating;
+            (window.x, window.y, window.width, window.height) =
+                self.scratchpad_geometry(scratchpad);
+        } else if let Some(saved) = saved.as_ref().filter(|s| s.floating) {
+            log::debug!("restoring window {} as saved", event.window);
+            window.group = WindowGroup::Floating;
+            (window.x, window.y, window.width, window.height) =
+                (saved.x, saved.y, saved.width, saved.height);
+        } else if rules.floating == Some(true) || self.man.is_floating_tag(tag) {
+            let geometry = self.conn.conn.get_geometry(event.window)?.reply()?;
+            let hints = self.conn.get_size_hints(event.window)?;
+            let monitor = self.man.get_monitor_of_tag(tag).unwrap_or(monitor);
+            // a floating tag only floats its windows while they are on it
+            if rules.floating == Some(true) {
+                window.group = WindowGroup::Floating;
+            }
+            (window.x, window.y, window.width, window.height) =
+                match self.man.is_floating_tag(tag) && (geometry.x, geometry.y) != (0, 0) {
+                    // windows on a floating tag go where they ask to be
+                    true => (geometry.x, geometry.y, geometry.width, geometry.height),
+                    false => {
+                        let pointer = self.conn.get_pointer_position()?;
+                        self.man.get_float_geometry(
+                            monitor,
+                            &hints,
+                            (geometry.width, geometry.height),
+                            pointer,
+                        )
+                    }
+                };
+        }
+
+        self.conn
+            .create_frame_of_window(&window, MouseContext::Client)?;
+        if let Some(opacity) = rules.opacity {
+            self.conn.set_opacity(&window, opacity)?;
+        }
+
+        let swallowed = match scratchpad {
+            Some(_) => None,
+            None => self.find_swallowed(event.window)?,
+        };
+        match swallowed {
+            Some(swallowed) => {
+                log::debug!("window {} swallows {swallowed}", event.window);
+                if let Some(old) = self.man.swallow(swallowed, window) {
+                    self.conn.unmap(&old)?;
+                }
+            }
+            // a summoned scratchpad is always focused
+            None if scratchpad.is_some() => self.man.add_window_to_tag(window, tag),
+            None => match self.conn.config.focus_new {
+                FocusNew::Always => self.man.add_window_to_tag(window, tag),
+                FocusNew::OnlyOnActiveTag if tag == self.man.active_tag() => {
+                    self.man.add_window_to_tag(window, tag)
+                }
+                _ => self.man.add_window_to_tag_unfocused(window, tag),
+            },
+        };
+        if let Some(saved) = &saved {
+            let count = self.man.tags.len();
+            saved
+                .also_on
+                .iter()
+                .filter(|t| (1..=count).contains(*t) && **t != tag + 1)
+                .for_each(|t| self.man.set_also_on(event.window, t - 1, true));
+        }
+        if self.man.get_monitor_of_window(event.window).is_none() {
+            self.conn.unmap(&window)?;
+        }
+        if rules.fullscreen == Some(true) || saved.is_some_and(|s| s.fullscreen) {
+            self.set_fullscreen(event.window, true)?;
+        }
 
-        self.conn.create_frame_of_window(&window)?;
-        self.man.add_window(window);
+        let class = properties.class;
+        actions::run_hook(
+            "window_mapped",
+            &self.conn.config.hooks.window_mapped,
+            &[
+                ("WINDOW_ID", event.window.to_string()),
+                ("WM_CLASS", class),
+                ("TAG", self.man.tag_number(tag).to_string()),
+            ],
+        );
+        if !self.subscribers.is_empty() {
+            self.subscribers.publish(ipc::Event::Map {
+                window: event.window,
+                tag: self.man.tag_number(tag),
+                title: self.conn.get_window_name(event.window)?,
+            });
+        }
         self.refresh()
     }
 
     fn handle_unmap_notify(&mut self, event: UnmapNotifyEvent) -> Res {
-        let window = match self.man.get_window_state(event.window) {
-            Some(w) => w,
+        if self.man.remove_dock(event.window) {
+            log::debug!("EVENT UNMAP dock {}", event.window);
+            return self.refresh();
+        }
+        if self.man.unmanaged.contains(&event.window) {
+            log::debug!("EVENT UNMAP unmanaged {}", event.window);
+            self.man.unmanaged.retain(|w| *w != event.window);
+            return Ok(());
+        }
+        let (tag, window) = match self.man.locate_window(event.window) {
+            Some((tag, index)) => (tag, self.man.tags[tag].windows[index]),
             None => return Ok(()),
         };
+        // windows on hidden tags or hidden to show the desktop were unmapped by the wm,
+        // only the synthetic unmap a client sends to withdraw a window that isn't shown
+        // takes them away
+        let synthetic = event.response_type & 0x80 != 0;
+        let hidden = self.man.get_monitor_of_window(event.window).is_none();
+        if (hidden || self.man.showing_desktop) && !synthetic {
+            return Ok(());
+        }
+        // an adopted window is unmapped from the root while it is put into its frame
+        if event.event == self.conn.screen.root && window.window == event.window && !synthetic {
+            log::debug!("EVENT UNMAP reparented {}", event.window);
+            return Ok(());
+        }
         log::debug!(
             "EVENT UNMAP window {} event {} from config {} response {}",
             event.window,
@@ -73,37 +359,465 @@ impl<'a, C: Connection> EventHandler<'a, C> {
             event.response_type
         );
 
-        self.conn.destroy_window(window)?;
+        self.conn.destroy_window(&window)?;
 
-        self.man
-            .get_mut_active_tag_windows()
-            .retain(|w| w.window != event.window);
-        self.man.set_tag_focus_to_master();
+        if let Some(restored) = self.man.unswallow(event.window) {
+            log::debug!(
+                "window {} gives its place back to {}",
+                event.window,
+                restored.window
+            );
+            if self.man.get_monitor_of_window(restored.window).is_some() {
+                self.conn.map(&restored)?;
+            }
+        }
+        if self.conn.config.hooks.window_unmapped.is_some() {
+            let (_, class) = self.conn.get_wm_class(event.window).unwrap_or_default();
+            actions::run_hook(
+                "window_unmapped",
+                &self.conn.config.hooks.window_unmapped,
+                &[
+                    ("WINDOW_ID", event.window.to_string()),
+                    ("WM_CLASS", class),
+                    ("TAG", self.man.tag_number(tag).to_string()),
+                ],
+            );
+        }
+        self.man.remove_window(event.window);
+        self.man.scratchpads.retain(|_, w| *w != event.window);
+        self.subscribers.publish(ipc::Event::Unmap {
+            window: event.window,
+        });
+        self.refresh()
+    }
+
+    /// forgets a client destroyed without being unmapped first, after a crash, `xkill`
+    /// or while hidden on another tag, destroying the frame it leaves behind
+    fn handle_destroy_notify(&mut self, event: DestroyNotifyEvent) -> Res {
+        if self.man.remove_dock(event.window) {
+            log::debug!("EVENT DESTROY dock {}", event.window);
+            return self.refresh();
+        }
+        self.man.unmanaged.retain(|w| *w != event.window);
+
+        // a swallowed window dies while hidden
+        if let Some(swallower) = self
+            .man
+            .swallowed
+            .iter()
+            .find(|(_, s)| s.window == event.window)
+            .map(|(w, _)| *w)
+        {
+            log::debug!("EVENT DESTROY swallowed {}", event.window);
+            if let Some(state) = self.man.swallowed.remove(&swallower) {
+                self.conn.destroy_frame(&state)?;
+            }
+            return Ok(());
+        }
+
+        // frames are destroyed too, only clients count
+        let state = match self
+            .man
+            .tags
+            .iter()
+            .flat_map(|t| t.windows.iter())
+            .chain(self.man.scratch.iter())
+            .find(|w| w.window == event.window)
+        {
+            Some(s) => *s,
+            None => return Ok(()),
+        };
+        log::debug!("EVENT DESTROY window {}", event.window);
+        self.conn.destroy_frame(&state)?;
+
+        match self.man.unswallow(event.window) {
+            Some(restored) => {
+                if self.man.get_window_state(restored.window).is_some() {
+                    self.conn.map(&restored)?;
+                }
+            }
+            None => {
+                self.man.take_window(event.window);
+            }
+        }
+        self.man.marks.retain(|_, w| *w != event.window);
+        self.man.also_on.remove(&event.window);
+        self.man.scratchpads.retain(|_, w| *w != event.window);
+        self.subscribers.publish(ipc::Event::Unmap {
+            window: event.window,
+        });
         self.refresh()
     }
 
-    fn handle_keypress(&mut self, event: KeyPressEvent) -> Res {
-        let action = match self.key.get_action(event) {
+    /// a visible window with a swallow rule that `window`'s process was started from
+    fn find_swallowed(&self, window: Window) -> Result<Option<Window>, WmError> {
+        let candidates = self
+            .man
+            .get_visible_windows()
+            .filter(|w| w.swallow)
+            .map(|w| w.window)
+            .collect::<Vec<_>>();
+        if candidates.is_empty() {
+            return Ok(None);
+        }
+        let pid = match self.conn.get_pid(window)? {
+            Some(p) => p,
+            None => return Ok(None),
+        };
+        for candidate in candidates {
+            if let Some(ancestor) = self.conn.get_pid(candidate)?
+                && ancestor != pid
+                && actions::is_descendant(pid, ancestor)
+            {
+                return Ok(Some(candidate));
+            }
+        }
+        Ok(None)
+    }
+
+    fn handle_property_notify(&mut self, event: PropertyNotifyEvent) -> Res {
+        if event.atom == u32::from(AtomEnum::WM_HINTS) {
+            let urgent = WmHints::get(self.conn.conn, event.window)?
+                .reply()?
+                .is_some_and(|h| h.urgent);
+            return self.set_urgent(event.window, urgent);
+        }
+        if event.atom != u32::from(AtomEnum::WM_NAME) && event.atom != self.conn.atoms._NET_WM_NAME
+        {
+            return Ok(());
+        }
+        if let Some(state) = self.man.get_window_state(event.window) {
+            let focused = self.man.get_focus() == Some(state.window);
+            self.conn.draw_titlebar(state, focused)?;
+        }
+        if self.subscribers.is_empty() || self.man.find_window_state(event.window).is_none() {
+            return Ok(());
+        }
+        log::debug!("EVENT PROPERTY title of {}", event.window);
+        let title = self.conn.get_window_name(event.window)?;
+        self.subscribers.publish(ipc::Event::Title {
+            window: event.window,
+            title,
+        });
+        Ok(())
+    }
+
+    fn handle_expose(&mut self, event: ExposeEvent) -> Res {
+        let state = match self.man.get_window_state(event.window) {
+            Some(s) if s.frame_window == event.window => s,
+            _ => return Ok(()),
+        };
+        let focused = self.man.get_focus() == Some(state.window);
+        self.conn.draw_titlebar(state, focused)
+    }
+
+    fn handle_keypress(&mut self, event: KeyPressEvent, trigger: Trigger) -> Res {
+        if self.key.pending_mark.is_some() && trigger == Trigger::Press {
+            return self.handle_mark_key(event);
+        }
+        if self.man.cheat_sheet.is_some() && trigger == Trigger::Press {
+            return self.hide_cheat_sheet(event);
+        }
+        let actions = match self.key.get_actions(event, trigger) {
             Some(a) => a,
             None => return Ok(()),
         };
 
         log::debug!(
-            "EVENT KEYPRESS code {} sym {:?} action {:?}",
+            "EVENT KEY {trigger:?} code {} sym {:?} actions {:?}",
             event.detail,
             event.state,
-            action
+            actions
         );
+        let (previous_monitor, previous_focus) = (self.man.active_monitor, self.man.get_focus());
+        actions.into_iter().try_for_each(|a| self.run_action(a))?;
+
+        let config = &self.conn.config;
+        let monitor_changed = self.man.active_monitor != previous_monitor;
+        let focus_changed = self
+            .man
+            .get_focus()
+            .is_some_and(|f| Some(f) != previous_focus);
+        if (monitor_changed && config.warp_on_monitor_change)
+            || (focus_changed && config.warp_on_focus_change)
+        {
+            self.warp_to_focus()?;
+        }
+        Ok(())
+    }
+
+    /// reloads the config on SIGUSR1 and SIGHUP, quits on SIGTERM and SIGINT
+    /// and reaps children on SIGCHLD
+    pub fn handle_signal(&mut self, signal: libc::c_int) -> Res {
+        match signal {
+            libc::SIGUSR1 | libc::SIGHUP => {
+                log::info!("got signal {signal}, reloading the config");
+                self.reload_config()
+            }
+            libc::SIGTERM | libc::SIGINT => {
+                log::info!("got signal {signal}, quitting");
+                self.shutdown = Some(Shutdown::Quit);
+                Ok(())
+            }
+            libc::SIGCHLD => {
+                signal::reap_children();
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// runs a command from the control socket and answers it
+    pub fn handle_ipc(&mut self, request: Request) {
+        let reply = match request.command {
+            Command::Action(action) => {
+                log::debug!("EVENT IPC action {action:?}");
+                match action.tag().filter(|t| *t > self.man.tag_count) {
+                    Some(tag) => Ok(format!(
+                        "error: tag {tag} is not between 1 and {}",
+                        self.man.tag_count
+                    )),
+                    None => self.run_action(action).map(|_| "ok".to_owned()),
+                }
+            }
+            // the one answer that isn't json, for scripts that only want the title
+            Command::Query(Query::Title) => match self.man.get_focus() {
+                Some(window) => self.conn.get_window_name(window),
+                None => Ok(String::new()),
+            },
+            Command::Query(query) => {
+                log::debug!("EVENT IPC query {query:?}");
+                self.query(query)
+            }
+            Command::Subscribe(stream) => {
+                log::debug!("EVENT IPC subscribe");
+                self.subscribers.add(stream, false);
+                Ok("ok".to_owned())
+            }
+            Command::Bind(hotkey) => {
+                log::debug!("EVENT IPC bind {hotkey:?}");
+                match self.key.bind(hotkey) {
+                    Ok(()) => self.conn.regrab_keys(&self.key).map(|_| "ok".to_owned()),
+                    Err(e) => Ok(format!("error: {e}")),
+                }
+            }
+            Command::Unbind { modifiers, key } => {
+                log::debug!("EVENT IPC unbind {modifiers}+{key}");
+                match self.key.unbind(&modifiers, &key) {
+                    Ok(()) => self.conn.regrab_keys(&self.key).map(|_| "ok".to_owned()),
+                    Err(e) => Ok(format!("error: {e}")),
+                }
+            }
+            Command::SubscribeI3(stream) => {
+                log::debug!("EVENT IPC i3 subscribe");
+                self.subscribers.add(stream, true);
+                Ok("ok".to_owned())
+            }
+        };
+        let reply = reply.unwrap_or_else(|e| format!("error: {e}"));
+        let _ = request.reply.send(reply);
+    }
+
+    fn query(&self, query: Query) -> Result<String, WmError> {
+        Ok(match query {
+            Query::Tags => to_json(
+                &self
+                    .man
+                    .tags
+                    .iter()
+                    .enumerate()
+                    .map(|(i, tag)| {
+                        let shown = self.man.get_monitor_of_tag(i);
+                        let owner = (self.man.workspaces == WorkspaceModel::PerMonitor)
+                            .then(|| i / self.man.tag_count.max(1));
+                        TagInfo {
+                            tag: self.man.tag_number(i),
+                            windows: tag.windows.len(),
+                            visible: shown.is_some(),
+                            monitor: shown
+                                .or(owner)
+                                .and_then(|m| self.man.monitors.get(m))
+                                .map(|m| m.name.clone()),
+                            focused: i == self.man.active_tag(),
+                        }
+                    })
+                    .collect::<Vec<_>>(),
+            ),
+            Query::Windows => to_json(
+                &self
+                    .man
+                    .tags
+                    .iter()
+                    .enumerate()
+                    .flat_map(|(i, tag)| tag.windows.iter().map(move |w| (i, w)))
+                    .map(|(i, w)| self.window_info(i, w))
+                    .collect::<Result<Vec<_>, _>>()?,
+            ),
+            Query::Workspaces => to_json(
+                &(0..self.man.tags.len())
+                    .filter(|&tag| self.is_workspace(tag))
+                    .map(|tag| self.workspace(tag))
+                    .collect::<Vec<_>>(),
+            ),
+            Query::Outputs => to_json(
+                &self
+                    .man
+                    .monitors
+                    .iter()
+                    .enumerate()
+                    .map(|(i, m)| Output {
+                        name: m.name.clone(),
+                        active: true,
+                        primary: i == 0,
+                        rect: rect(m.x, m.y, m.width, m.height),
+                        current_workspace: (m.active_tag + 1).to_string(),
+                    })
+                    .collect::<Vec<_>>(),
+            ),
+            Query::Tree => {
+                let outputs = self
+                    .man
+                    .monitors
+                    .iter()
+                    .enumerate()
+                    .map(|(i, m)| {
+                        let workspaces = (0..self.man.tags.len())
+                            .filter(|&tag| {
+                                self.is_workspace(tag)
+                                    && self.man.get_monitor_of_tag(tag).unwrap_or_default() == i
+                            })
+                            .map(|tag| {
+                                let (floating, tiled) = self.man.tags[tag]
+                                    .windows
+                                    .iter()
+                                    .map(|w| Ok((w.is_floating(), self.con(w)?)))
+                                    .collect::<Result<Vec<_>, WmError>>()?
+                                    .into_iter()
+                                    .partition::<Vec<_>, _>(|(floating, _)| *floating);
+                                let strip = |v: Vec<(bool, Con)>| {
+                                    Some(v.into_iter().map(|(_, c)| c).collect())
+                                };
+                                Ok(Workspace {
+                                    nodes: strip(tiled),
+                                    floating_nodes: strip(floating),
+                                    ..self.workspace(tag)
+                                })
+                            })
+                            .collect::<Result<_, WmError>>()?;
+                        Ok(Node {
+                            id: m.bar.window,
+                            kind: "output",
+                            name: m.name.clone(),
+                            rect: rect(m.x, m.y, m.width, m.height),
+                            focused: false,
+                            nodes: workspaces,
+                        })
+                    })
+                    .collect::<Result<_, WmError>>()?;
+                let screen = self.conn.screen;
+                to_json(&Node {
+                    id: screen.root,
+                    kind: "root",
+                    name: "root".to_owned(),
+                    rect: rect(0, 0, screen.width_in_pixels, screen.height_in_pixels),
+                    focused: false,
+                    nodes: outputs,
+                })
+            }
+            Query::Title => to_json(&match self.man.get_focus() {
+                Some(window) => Some(self.conn.get_window_name(window)?),
+                None => None,
+            }),
+            Query::Focused => to_json(&match self
+                .man
+                .get_focus()
+                .and_then(|f| self.man.get_window_state(f))
+            {
+                Some(w) => Some(
+                    self.window_info(self.man.get_tag_of_window(w.window).unwrap_or_default(), w)?,
+                ),
+                None => None,
+            }),
+        })
+    }
+
+    /// i3 only lists workspaces that are shown or have windows
+    fn is_workspace(&self, tag: usize) -> bool {
+        self.man.is_tag_occupied(tag) || self.man.get_monitor_of_tag(tag).is_some()
+    }
 
+    fn workspace(&self, tag: usize) -> Workspace {
+        let monitor = self
+            .man
+            .get_monitor_of_tag(tag)
+            .map(|m| &self.man.monitors[m]);
+        Workspace {
+            id: tag,
+            kind: "workspace",
+            num: tag + 1,
+            name: (tag + 1).to_string(),
+            visible: monitor.is_some(),
+            focused: tag == self.man.active_tag(),
+            urgent: self.man.tags[tag].windows.iter().any(|w| w.urgent),
+            rect: monitor.map_or(rect(0, 0, 0, 0), |m| rect(m.x, m.y, m.width, m.height)),
+            output: monitor.map(|m| m.name.clone()),
+            nodes: None,
+            floating_nodes: None,
+        }
+    }
+
+    fn con(&self, window: &WindowState) -> Result<Con, WmError> {
+        Ok(Con {
+            id: window.window,
+            kind: "con",
+            name: self.conn.get_window_name(window.window)?,
+            window: window.window,
+            rect: rect(window.x, window.y, window.width, window.height),
+            focused: self.man.get_focus() == Some(window.window),
+            fullscreen_mode: u16::from(window.fullscreen),
+            nodes: Vec::new(),
+        })
+    }
+
+    fn window_info(&self, tag: usize, window: &WindowState) -> Result<WindowInfo, WmError> {
+        Ok(WindowInfo {
+            id: window.window,
+            title: self.conn.get_window_name(window.window)?,
+            tag: self.man.tag_number(tag),
+            x: window.x,
+            y: window.y,
+            width: window.width,
+            height: window.height,
+            floating: window.is_floating(),
+            fullscreen: window.fullscreen,
+            focused: self.man.get_focus() == Some(window.window),
+        })
+    }
+
+    fn run_action(&mut self, action: HotkeyAction) -> Res {
+        if let Some(tag) = action
+            .tag()
+            .filter(|t| !(1..=self.man.tag_count).contains(t))
+        {
+            log::error!("tag {tag} is not between 1 and {}", self.man.tag_count);
+            return Ok(());
+        }
         match action {
             HotkeyAction::SwitchTag(n) => {
-                self.change_active_tag(n - 1)?;
+                self.change_active_tag(self.man.get_tag_index(n - 1))?;
             }
             HotkeyAction::MoveWindow(n) => {
-                self.move_window(n - 1)?;
+                self.move_window(self.man.get_tag_index(n - 1), false)?;
+            }
+            HotkeyAction::MoveWindowToEmptyTag(follow) => match self.man.get_empty_tag() {
+                Some(tag) => self.move_window(tag, follow)?,
+                None => log::debug!("no empty tag to move window to"),
+            },
+            HotkeyAction::MoveWindowAndFollow(n) => {
+                self.move_window(self.man.get_tag_index(n - 1), true)?;
             }
             HotkeyAction::Spawn(command) => {
-                crate::actions::spawn_command(&command);
+                crate::actions::spawn(&command.with_apps(&self.conn.config.apps));
             }
             HotkeyAction::ExitFocusedWindow => {
                 let focus = match self.man.get_focus() {
@@ -112,123 +826,1138 @@ impl<'a, C: Connection> EventHandler<'a, C> {
                 };
                 self.conn.kill_focus(focus)?;
             }
+            HotkeyAction::SetRatio(ratio) => {
+                self.man.monitors[self.man.active_monitor].tiling.ratio = ratio.clamp(0.15, 0.85);
+            }
             HotkeyAction::ChangeRatio(change) => {
-                self.man.tiling.ratio = (self.man.tiling.ratio + change).clamp(0.15, 0.85);
+                let tiling = &mut self.man.monitors[self.man.active_monitor].tiling;
+                tiling.ratio = (tiling.ratio + change).clamp(0.15, 0.85);
+            }
+            HotkeyAction::CycleRatio(change) => {
+                let presets = &self.conn.config.ratio_presets;
+                let tiling = &mut self.man.monitors[self.man.active_monitor].tiling;
+                if let Some(ratio) = next_preset(presets, tiling.ratio, change) {
+                    tiling.ratio = ratio;
+                }
             }
             HotkeyAction::NextFocus(change) => {
                 self.man.switch_focus_next(change);
             }
+            HotkeyAction::ToggleViewTag(n) => {
+                self.toggle_view_tag(self.man.get_tag_index(n - 1))?;
+            }
+            HotkeyAction::ToggleTag(n) => {
+                self.toggle_tag(self.man.get_tag_index(n - 1))?;
+            }
             HotkeyAction::NextTag(change) => {
-                self.change_active_tag(
-                    (self.man.active_tag as i16 + change).rem_euclid(9) as usize
-                )?;
+                self.change_active_tag(self.man.get_relative_tag(change))?;
             }
             HotkeyAction::SwapMaster => {
                 self.man.swap_master();
             }
+            HotkeyAction::MoveTagToMonitor(change) => {
+                self.move_tag_to_monitor(self.man.get_relative_monitor(change));
+            }
+            HotkeyAction::SwapTagWithMonitor(change) => {
+                self.swap_tag_with_monitor(self.man.get_relative_monitor(change));
+            }
+            HotkeyAction::ToggleSpanFullscreen => {
+                let state = match self
+                    .man
+                    .get_focus()
+                    .and_then(|f| self.man.get_window_state(f))
+                {
+                    Some(s) => *s,
+                    None => return Ok(()),
+                };
+                // the monitors change while not fullscreen, so the geometry is set once
+                if state.fullscreen {
+                    self.set_fullscreen(state.window, false)?;
+                    self.set_fullscreen_monitors(state.window, None)?;
+                } else {
+                    let monitors = self.man.get_spanning_monitors();
+                    self.set_fullscreen_monitors(state.window, Some(monitors))?;
+                    self.set_fullscreen(state.window, true)?;
+                }
+            }
+            HotkeyAction::FocusMonitor(change) => {
+                self.man.active_monitor = self.man.get_relative_monitor(change);
+            }
+            HotkeyAction::EnterMode(name) => {
+                if self.key.enter_mode(&name) && !self.conn.grab_keyboard()? {
+                    self.key.exit_mode();
+                }
+            }
+            HotkeyAction::ExitMode => {
+                if self.key.exit_mode() {
+                    log::debug!("leaving modes");
+                    self.conn.ungrab_keyboard()?;
+                }
+            }
+            HotkeyAction::Quit => {
+                self.shutdown = Some(Shutdown::Quit);
+            }
+            HotkeyAction::Restart => {
+                self.shutdown = Some(Shutdown::Restart);
+            }
+            HotkeyAction::ReloadConfig => {
+                self.reload_config()?;
+            }
+            HotkeyAction::ToggleFloating => {
+                self.toggle_floating()?;
+            }
+            HotkeyAction::TogglePin => {
+                self.toggle_pin()?;
+            }
+            HotkeyAction::ToggleBorder => {
+                self.toggle_border()?;
+            }
+            HotkeyAction::Raise => {
+                self.raise_focus();
+            }
+            HotkeyAction::Lower => {
+                self.lower_focus();
+            }
+            HotkeyAction::ToggleFullscreen => {
+                let state = match self
+                    .man
+                    .get_focus()
+                    .and_then(|f| self.man.get_window_state(f))
+                {
+                    Some(s) => *s,
+                    None => return Ok(()),
+                };
+                self.set_fullscreen(state.window, !state.fullscreen)?;
+            }
+            HotkeyAction::MoveFloat(dx, dy) => {
+                self.change_float_geometry(dx, dy, 0, 0)?;
+            }
+            HotkeyAction::ResizeFloat(dw, dh) => {
+                self.change_float_geometry(0, 0, dw, dh)?;
+            }
+            HotkeyAction::ShowHotkeys => {
+                if self.man.cheat_sheet.is_some() || !self.conn.grab_keyboard()? {
+                    return Ok(());
+                }
+                let overlay = self.conn.create_overlay()?;
+                self.conn.draw_overlay(
+                    overlay,
+                    &self.man.monitors[self.man.active_monitor],
+                    &self.key.describe_hotkeys(),
+                    None,
+                )?;
+                self.man.cheat_sheet = Some(overlay);
+            }
+            HotkeyAction::BanishPointer => {
+                let m = &self.man.monitors[self.man.active_monitor];
+                self.conn.warp_pointer(
+                    m.x + m.width.saturating_sub(1) as i16,
+                    m.y + m.height.saturating_sub(1) as i16,
+                )?;
+            }
+            HotkeyAction::SnapFloat(snap) => {
+                self.snap_float(snap)?;
+            }
+            HotkeyAction::SetMark | HotkeyAction::JumpToMark => {
+                if self.conn.grab_keyboard()? {
+                    self.key.pending_mark = Some(action);
+                }
+            }
+            HotkeyAction::CycleWindows(change) => {
+                self.cycle_windows(change)?;
+            }
+            HotkeyAction::ToggleShowDesktop => {
+                self.show_desktop(!self.man.showing_desktop)?;
+            }
+            HotkeyAction::ToggleBar => {
+                let monitor = &mut self.man.monitors[self.man.active_monitor];
+                monitor.tiling.show_bar = !monitor.tiling.show_bar;
+                match monitor.tiling.show_bar {
+                    true => self.conn.map(&monitor.bar)?,
+                    false => self.conn.unmap(&monitor.bar)?,
+                };
+            }
+            HotkeyAction::ToggleScratchpad(name) => {
+                self.toggle_scratchpad(&name)?;
+            }
+            HotkeyAction::DragMove | HotkeyAction::DragResize | HotkeyAction::FocusClicked => {
+                log::debug!("{action:?} only works as a mouse binding");
+            }
         };
-        self.refresh()?;
-        Ok(())
+        self.refresh()
     }
 
-    fn handle_enter(&mut self, event: EnterNotifyEvent) -> Res {
-        log::debug!(
-            "EVENT ENTER child {} detail {:?} event {}",
-            event.child,
-            event.detail,
-            event.event
-        );
+    /// re-reads `config.toml`, taking over its hotkeys and rules and restyling the bars and windows
+    fn reload_config(&mut self) -> Res {
+        log::info!("reloading config");
+        let config = Config::from(ConfigDeserialized::new());
+        if config.tag_count != self.man.tag_count {
+            log::warn!("the number of tags only changes on restart");
+        }
+        self.key.reload(self.conn.conn, &config)?;
+        self.conn.ungrab_keyboard()?;
+        self.conn.regrab_keys(&self.key)?;
+        self.conn.apply_config(&config)?;
+        self.man
+            .monitors
+            .iter_mut()
+            .enumerate()
+            .for_each(|(i, m)| self.conn.update_monitor_tiling(i, m));
+        self.man
+            .monitors
+            .iter()
+            .try_for_each(|m| self.conn.restyle_bar(&m.bar))?;
+        self.conn.update_tag_settings(&mut self.man);
+        let titlebars = self.conn.config.titlebars;
+        self.man
+            .tags
+            .iter_mut()
+            .flat_map(|t| t.windows.iter_mut())
+            .chain(self.man.scratch.iter_mut())
+            .for_each(|w| w.titlebar = titlebars);
+        // the focused border and the titlebars are redrawn by the refresh after every action
+        self.man
+            .tags
+            .iter()
+            .enumerate()
+            .flat_map(|(i, t)| t.windows.iter().map(move |w| (i, w)))
+            .filter(|(_, w)| !w.is_floating() && !w.fullscreen)
+            .try_for_each(|(i, w)| self.conn.restyle_border(w, i))
+    }
 
-        if let Some(w) = self.man.get_window_state(event.child) {
-            self.man.tags[self.man.active_tag].focus = Some(w.window);
+    /// hides the scratchpad if it is on the active tag, otherwise brings it there,
+    /// starting its command when it has no window yet
+    fn toggle_scratchpad(&mut self, name: &str) -> Res {
+        let config = match self.conn.config.scratchpads.iter().find(|s| s.name == name) {
+            Some(c) => c,
+            None => {
+                log::error!("no scratchpad named {name:?}");
+                return Ok(());
+            }
         };
-        if let Some(w) = self.man.get_window_state(event.event) {
-            self.man.tags[self.man.active_tag].focus = Some(w.window);
+        let window = match self.man.scratchpads.get(name) {
+            Some(w) => *w,
+            None => {
+                log::debug!("starting scratchpad {name}");
+                actions::spawn(&config.command.with_apps(&self.conn.config.apps));
+                return Ok(());
+            }
         };
-        self.refresh()?;
-        Ok(())
+        let shown = self.man.get_monitor_of_window(window) == Some(self.man.active_monitor);
+        let mut state = match self.man.take_window(window) {
+            Some(s) => s,
+            None => return Ok(()),
+        };
+        if shown {
+            log::debug!("hiding scratchpad {name}");
+            self.conn.unmap(&state)?;
+            self.man.scratch.push(state);
+            return Ok(());
+        }
+        log::debug!("showing scratchpad {name}");
+        state.group = WindowGroup::Floating;
+        (state.x, state.y, state.width, state.height) = self.scratchpad_geometry(config);
+        self.man.add_window_to_tag(state, self.man.active_tag());
+        self.man.raise_window(state.window);
+        self.conn.map(&state)
+    }
+
+    /// centered on the active monitor, covering the configured part of its workarea
+    fn scratchpad_geometry(&self, scratchpad: &ScratchpadConfig) -> (i16, i16, u16, u16) {
+        let monitor = self.man.active_monitor;
+        let (_, _, width, height) = self.man.get_workarea(monitor);
+        self.man.get_snap_geometry(
+            monitor,
+            Snap::Center,
+            (width as f32 * scratchpad.width) as u16,
+            (height as f32 * scratchpad.height) as u16,
+        )
     }
 
-    fn handle_config(&self, event: ConfigureRequestEvent) -> Res {
-        match self.man.get_window_state(event.window) {
-            Some(_) => self.conn.handle_config(event)?,
-            None => (),
+    /// tiles the focused floating window, or floats it where it last floated,
+    /// the first time centered at the size it asks for
+    fn toggle_floating(&mut self) -> Res {
+        let state = match self
+            .man
+            .get_focus()
+            .and_then(|f| self.man.get_window_state(f))
+        {
+            Some(s) if !s.fullscreen => *s,
+            _ => return Ok(()),
+        };
+        let geometry = match (state.group, state.float_geometry) {
+            (WindowGroup::Floating, _) => None,
+            (_, Some(g)) => Some(g),
+            (_, None) => {
+                let hints = self.conn.get_size_hints(state.window)?;
+                let monitor = self.man.active_monitor;
+                let (_, _, width, height) = self.man.get_workarea(monitor);
+                let pointer = self.conn.get_pointer_position()?;
+                Some(
+                    self.man
+                        .get_float_geometry(monitor, &hints, (width / 2, height / 2), pointer),
+                )
+            }
+        };
+        let state = match self.man.get_mut_window_state(state.window) {
+            Some(s) => s,
+            None => return Ok(()),
         };
+        match geometry {
+            None => {
+                state.float_geometry = Some((state.x, state.y, state.width, state.height));
+                state.group = WindowGroup::Stack;
+            }
+            Some((x, y, width, height)) => {
+                state.group = WindowGroup::Floating;
+                (state.x, state.y, state.width, state.height) = (x, y, width, height);
+                let window = state.window;
+                self.man.raise_window(window);
+            }
+        }
         Ok(())
     }
 
-    fn handle_client_message(&mut self, event: ClientMessageEvent) -> Res {
-        let data = event.data.as_data32();
-
-        if data[1] == 0 {
-            return Ok(());
+    /// pins the focused window, floating it first, or unpins it
+    fn toggle_pin(&mut self) -> Res {
+        let state = match self
+            .man
+            .get_focus()
+            .and_then(|f| self.man.get_window_state(f))
+        {
+            Some(s) if !s.fullscreen => *s,
+            _ => return Ok(()),
+        };
+        if !state.pinned && state.group != WindowGroup::Floating {
+            self.toggle_floating()?;
         }
+        self.set_pinned(state.window, !state.pinned);
+        Ok(())
+    }
 
-        let event_type = self.conn.get_atom_name(event.type_)?;
+    fn toggle_border(&mut self) -> Res {
+        let window = match self.man.get_focus() {
+            Some(w) => w,
+            None => return Ok(()),
+        };
+        let tag = match self.man.get_tag_of_window(window) {
+            Some(t) => t,
+            None => return Ok(()),
+        };
+        let state = match self.man.get_mut_window_state(window) {
+            Some(s) if !s.fullscreen => s,
+            _ => return Ok(()),
+        };
+        state.borderless = !state.borderless;
+        log::debug!("setting borderless of {window} to {}", state.borderless);
+        let state = *state;
+        self.conn.conn.configure_window(
+            state.frame_window,
+            &ConfigureWindowAux::new().border_width(self.conn.window_border_size(&state, tag)),
+        )?;
+        Ok(())
+    }
 
-        let first_property = self.conn.get_atom_name(data[1])?;
+    fn set_pinned(&mut self, window: Window, pinned: bool) {
+        if let Some(state) = self.man.find_mut_window_state(window) {
+            log::debug!("setting pinned of {window} to {pinned}");
+            state.pinned = pinned;
+        }
+    }
 
-        log::debug!("got client data {data:?}");
-        log::debug!(
-            "GOT CLIENT EVENT window {} atom {:?} first prop {:?}",
-            event.window,
-            event_type,
-            first_property
+    fn change_float_geometry(&mut self, dx: i16, dy: i16, dw: i16, dh: i16) -> Res {
+        let window = match self
+            .man
+            .get_focus()
+            .and_then(|f| self.man.get_window_state(f))
+        {
+            Some(s) if s.is_floating() && !s.fullscreen => s.window,
+            _ => return Ok(()),
+        };
+        let hints = self.conn.get_size_hints(window)?;
+        let state = match self.man.get_mut_window_state(window) {
+            Some(s) => s,
+            None => return Ok(()),
+        };
+        state.x = state.x.saturating_add(dx);
+        state.y = state.y.saturating_add(dy);
+        (state.width, state.height) = hints.apply(
+            state.width as i32 + dw as i32,
+            state.height as i32 + dh as i32,
         );
+        self.man.keep_on_screen(window);
+        if let Some(state) = self.man.get_window_state(window) {
+            self.conn.config_window_from_state(state)?;
+        }
+        self.man.move_window_to_monitor_under(window);
+        self.man.set_active_monitor_of_window(window);
+        Ok(())
+    }
 
-        match event_type.as_str() {
-            "_NET_WM_STATE" => match first_property.as_str() {
-                "_NET_WM_STATE_FULLSCREEN" => {
-                    let state = match self.man.get_mut_window_state(event.window) {
-                        Some(s) => s,
-                        None => return Ok(()),
-                    };
-                    let window = state.window;
-                    match data[0] {
-                        0 => {
-                            state.group = WindowGroup::Stack;
-                            self.conn.remove_atom_prop(window, "_NET_WM_STATE")?;
-                            self.refresh()?;
+    fn snap_float(&mut self, snap: Snap) -> Res {
+        let state = match self
+            .man
+            .get_focus()
+            .and_then(|f| self.man.get_window_state(f))
+        {
+            Some(s) if s.is_floating() && !s.fullscreen => *s,
+            _ => return Ok(()),
+        };
+        let hints = self.conn.get_size_hints(state.window)?;
+        let (x, y, width, height) =
+            self.man
+                .get_snap_geometry(self.man.active_monitor, snap, state.width, state.height);
+        let state = match self.man.get_mut_window_state(state.window) {
+            Some(s) => s,
+            None => return Ok(()),
+        };
+        (state.x, state.y) = (x, y);
+        (state.width, state.height) = hints.apply(width as i32, height as i32);
+        self.conn.config_window_from_state(state)
+    }
+
+    /// closes the hotkey list on any key that is not a modifier
+    fn hide_cheat_sheet(&mut self, event: KeyPressEvent) -> Res {
+        if self
+            .key
+            .get_keysym(event.detail)
+            .is_some_and(|s| s.is_modifier_key())
+        {
+            return Ok(());
+        }
+        if let Some(overlay) = self.man.cheat_sheet.take() {
+            self.conn.destroy_overlay(overlay)?;
+        }
+        if self.key.mode_stack.is_empty() {
+            self.conn.ungrab_keyboard()?;
+        }
+        Ok(())
+    }
+
+    /// completes a pending mark action with the letter of the pressed key
+    fn handle_mark_key(&mut self, event: KeyPressEvent) -> Res {
+        let sym = match self.key.get_keysym(event.detail) {
+            Some(s) if !s.is_modifier_key() => s,
+            _ => return Ok(()),
+        };
+        let action = self.key.pending_mark.take();
+        if self.key.mode_stack.is_empty() {
+            self.conn.ungrab_keyboard()?;
+        }
+        let mark = match sym.key_char() {
+            Some(c) if c.is_alphanumeric() => c,
+            _ => return Ok(()),
+        };
+        log::debug!("EVENT MARK {action:?} {mark}");
+        match action {
+            Some(HotkeyAction::SetMark) => {
+                if let Some(focus) = self.man.get_focus() {
+                    self.man.marks.insert(mark, focus);
+                }
+                Ok(())
+            }
+            Some(HotkeyAction::JumpToMark) => {
+                let window = match self.man.marks.get(&mark) {
+                    Some(w) => *w,
+                    None => return Ok(()),
+                };
+                let tag = match self.man.find_tag_of_window(window) {
+                    Some(t) => t,
+                    None => return Ok(()),
+                };
+                match self.man.get_monitor_of_window(window) {
+                    Some(m) => self.man.active_monitor = m,
+                    None => self.change_active_tag(tag)?,
+                };
+                let tag = self.man.active_tag();
+                self.man.tags[tag].focus = Some(window);
+                self.refresh()
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// focuses the next window in mru order and shows the list, starting a cycle if needed
+    fn cycle_windows(&mut self, change: i16) -> Res {
+        if self.man.cycle.is_none() {
+            let tag = self.man.active_tag();
+            let windows = self.man.get_mru_windows(tag);
+            if windows.len() < 2 || !self.conn.grab_keyboard()? {
+                return Ok(());
+            }
+            self.man.cycle = Some(Cycle {
+                overlay: self.conn.create_overlay()?,
+                tag,
+                windows,
+                index: 0,
+            });
+        }
+        let mut cycle = match self.man.cycle.take() {
+            Some(c) => c,
+            None => return Ok(()),
+        };
+        cycle
+            .windows
+            .retain(|w| self.man.get_window_state(*w).is_some());
+        if !cycle.windows.is_empty() {
+            cycle.index =
+                (cycle.index as i16 + change).rem_euclid(cycle.windows.len() as i16) as usize;
+            self.man.tags[cycle.tag].focus = Some(cycle.windows[cycle.index]);
+            let names = cycle
+                .windows
+                .iter()
+                .map(|w| self.conn.get_window_name(*w))
+                .collect::<Result<Vec<_>, _>>()?;
+            self.conn.draw_overlay(
+                cycle.overlay,
+                &self.man.monitors[self.man.active_monitor],
+                &names,
+                Some(cycle.index),
+            )?;
+        }
+        self.man.cycle = Some(cycle);
+        Ok(())
+    }
+
+    /// commits the cycle once all modifiers are released
+    fn handle_cycle_release(&mut self) -> Res {
+        if u16::from(clean_mask(self.conn.get_modifier_state()?)) != 0 {
+            return Ok(());
+        }
+        let cycle = match self.man.cycle.take() {
+            Some(c) => c,
+            None => return Ok(()),
+        };
+        log::debug!(
+            "EVENT CYCLE END window {:?}",
+            cycle.windows.get(cycle.index)
+        );
+        self.conn.destroy_overlay(cycle.overlay)?;
+        if self.key.mode_stack.is_empty() {
+            self.conn.ungrab_keyboard()?;
+        }
+        self.refresh()
+    }
+
+    /// moves the pointer to the center of the focused window, or of the active monitor
+    fn warp_to_focus(&self) -> Res {
+        let (x, y, width, height) = match self
+            .man
+            .get_focus()
+            .and_then(|f| self.man.get_window_state(f))
+        {
+            Some(w) => (w.x, w.y, w.width, w.height),
+            None => {
+                let m = &self.man.monitors[self.man.active_monitor];
+                (m.x, m.y, m.width, m.height)
+            }
+        };
+        self.conn
+            .warp_pointer(x + (width / 2) as i16, y + (height / 2) as i16)
+    }
+
+    fn handle_enter(&mut self, event: EnterNotifyEvent) -> Res {
+        log::debug!(
+            "EVENT ENTER child {} detail {:?} event {}",
+            event.child,
+            event.detail,
+            event.event
+        );
+        if !self.conn.config.focus_follow_mouse {
+            return Ok(());
+        }
+
+        [event.child, event.event].iter().for_each(|w| {
+            if let Some(w) = self.man.get_window_state(*w).map(|s| s.window) {
+                self.man.set_active_monitor_of_window(w);
+                let tag = self.man.active_tag();
+                self.man.tags[tag].focus = Some(w);
+            };
+        });
+        self.refresh()?;
+        Ok(())
+    }
+
+    fn handle_button_press(&mut self, event: ButtonPressEvent) -> Res {
+        let bar_monitor = self
+            .man
+            .monitors
+            .iter()
+            .position(|m| m.bar.frame_window == event.event || m.bar.window == event.event);
+        let context = match bar_monitor {
+            Some(_) => MouseContext::Bar,
+            None if event.event == self.conn.screen.root => {
+                // clicks on clients that propagated up to the root
+                if event.child != x11rb::NONE {
+                    return Ok(());
+                }
+                MouseContext::Root
+            }
+            None => MouseContext::Client,
+        };
+        // the close area at the right end of a titlebar
+        if let Some(state) = self.man.get_window_state(event.event)
+            && context == MouseContext::Client
+            && event.detail == 1
+            && event.child == x11rb::NONE
+            && self.conn.in_close_area(state, event.event_x, event.event_y)
+        {
+            log::debug!("EVENT CLOSE CLICK window {}", state.window);
+            self.conn.kill_focus(state.window)?;
+            return self.conn.replay_pointer(event.time);
+        }
+        let action = match self.key.get_mouse_action(context, event) {
+            Some(a) => a,
+            None => return self.conn.replay_pointer(event.time),
+        };
+        log::debug!(
+            "EVENT BUTTON PRESS window {} button {} x {} y {} action {:?}",
+            event.event,
+            event.detail,
+            event.root_x,
+            event.root_y,
+            action
+        );
+        match action {
+            HotkeyAction::FocusClicked => self.handle_click_focus(event),
+            HotkeyAction::DragMove => self.start_drag(event, false),
+            HotkeyAction::DragResize => self.start_drag(event, true),
+            action => {
+                match (context, bar_monitor) {
+                    (MouseContext::Bar, Some(m)) => self.man.active_monitor = m,
+                    (MouseContext::Root, _) => {
+                        if let Some(m) = self.man.get_monitor_at(event.root_x, event.root_y) {
+                            self.man.active_monitor = m;
                         }
-                        1 => {
-                            state.group = WindowGroup::Floating;
-                            state.x = 0;
-                            state.y = 0;
-                            state.width = self.conn.screen.width_in_pixels;
-                            state.height = self.conn.screen.height_in_pixels;
-                            self.conn.set_fullscreen(state)?;
-                            self.refresh()?;
+                    }
+                    _ => {
+                        if let Some(w) = self.man.get_window_state(event.event).map(|s| s.window) {
+                            self.man.set_active_monitor_of_window(w);
                         }
-                        2 => {}
-                        _ => {}
+                    }
+                };
+                self.run_action(action)
+            }
+        }
+    }
+
+    fn start_drag(&mut self, event: ButtonPressEvent, resize: bool) -> Res {
+        let state = match self.man.get_window_state(event.event) {
+            Some(s) => *s,
+            None => return Ok(()),
+        };
+        if !state.is_floating() || state.fullscreen {
+            return Ok(());
+        }
+        let kind = match resize {
+            false => DragKind::Move,
+            true => DragKind::Resize {
+                left: event.root_x < state.x + (state.width / 2) as i16,
+                top: event.root_y < state.y + (state.height / 2) as i16,
+                hints: self.conn.get_size_hints(state.window)?,
+            },
+        };
+        self.man.drag = Some(Drag {
+            window: state.window,
+            kind,
+            pointer_x: event.root_x,
+            pointer_y: event.root_y,
+            x: state.x,
+            y: state.y,
+            width: state.width,
+            height: state.height,
+        });
+        Ok(())
+    }
+
+    fn handle_click_focus(&mut self, event: ButtonPressEvent) -> Res {
+        let window = self.man.get_window_state(event.event).map(|s| s.window);
+        log::debug!("EVENT CLICK window {:?}", window);
+        if let Some(w) = window {
+            self.man.set_active_monitor_of_window(w);
+            let tag = self.man.active_tag();
+            self.man.tags[tag].focus = Some(w);
+            self.refresh()?;
+        }
+        self.conn.replay_pointer(event.time)
+    }
+
+    fn handle_drag_motion(&mut self, event: MotionNotifyEvent) -> Res {
+        let drag = match self.man.drag {
+            Some(d) => d,
+            None => return Ok(()),
+        };
+        let state = match self.man.get_mut_window_state(drag.window) {
+            Some(s) => s,
+            None => return Ok(()),
+        };
+        let (dx, dy) = (
+            (event.root_x - drag.pointer_x) as i32,
+            (event.root_y - drag.pointer_y) as i32,
+        );
+        match drag.kind {
+            DragKind::Move => {
+                state.x = drag.x + dx as i16;
+                state.y = drag.y + dy as i16;
+            }
+            DragKind::Resize { left, top, hints } => {
+                let (width, height) = hints.apply(
+                    drag.width as i32 + if left { -dx } else { dx },
+                    drag.height as i32 + if top { -dy } else { dy },
+                );
+                // the corner opposite the pointer stays in place
+                if left {
+                    state.x = drag.x + drag.width as i16 - width as i16;
+                }
+                if top {
+                    state.y = drag.y + drag.height as i16 - height as i16;
+                }
+                (state.width, state.height) = (width, height);
+            }
+        }
+        self.man.keep_on_screen(drag.window);
+        match self.man.get_window_state(drag.window) {
+            Some(state) => self.conn.config_window_from_state(state),
+            None => Ok(()),
+        }
+    }
+
+    fn handle_drag_end(&mut self, event: ButtonReleaseEvent) -> Res {
+        let drag = match self.man.drag.take() {
+            Some(d) => d,
+            None => return Ok(()),
+        };
+        log::debug!(
+            "EVENT BUTTON RELEASE window {} x {} y {}",
+            drag.window,
+            event.root_x,
+            event.root_y
+        );
+        self.man.move_window_to_monitor_under(drag.window);
+        self.man.set_active_monitor_of_window(drag.window);
+        self.refresh()
+    }
+
+    fn handle_root_motion(&mut self, event: MotionNotifyEvent) -> Res {
+        let monitor = match self.man.get_monitor_at(event.root_x, event.root_y) {
+            Some(m) => m,
+            None => return Ok(()),
+        };
+        if monitor == self.man.active_monitor {
+            return Ok(());
+        }
+        log::debug!(
+            "EVENT MOTION x {} y {} entered monitor {monitor}",
+            event.root_x,
+            event.root_y
+        );
+        self.man.active_monitor = monitor;
+        self.refresh()
+    }
+
+    fn handle_config(&mut self, event: ConfigureRequestEvent) -> Res {
+        // windows on a floating tag are moved and resized as they ask
+        let floating = self
+            .man
+            .find_tag_of_window(event.window)
+            .is_some_and(|t| self.man.is_floating_tag(t));
+        if floating && let Some(state) = self.man.find_mut_window_state(event.window) {
+            if state.fullscreen {
+                return Ok(());
+            }
+            let mask = event.value_mask;
+            if mask.contains(ConfigWindow::X) {
+                state.x = event.x;
+            }
+            if mask.contains(ConfigWindow::Y) {
+                state.y = event.y;
+            }
+            if mask.contains(ConfigWindow::WIDTH) {
+                state.width = event.width;
+            }
+            if mask.contains(ConfigWindow::HEIGHT) {
+                state.height = event.height;
+            }
+            self.man.keep_on_screen(event.window);
+            return match self.man.find_window_state(event.window) {
+                Some(state) => self.conn.config_window_from_state(state),
+                None => Ok(()),
+            };
+        }
+        if self.man.find_window_state(event.window).is_some()
+            || self.man.unmanaged.contains(&event.window)
+        {
+            // a tiled window is put back in place by the next refresh
+            self.conn.forget_configured(event.window);
+            self.conn.handle_config(event)?
+        };
+        Ok(())
+    }
+
+    fn handle_client_message(&mut self, event: ClientMessageEvent) -> Res {
+        let data = event.data.as_data32();
+        let atoms = self.conn.atoms;
+
+        log::debug!("got client data {data:?}");
+        log::debug!(
+            "GOT CLIENT EVENT window {} atom {}",
+            event.window,
+            event.type_,
+        );
+
+        match event.type_ {
+            t if t == atoms._NET_WM_STATE => {
+                if data[1] == 0 {
+                    return Ok(());
+                }
+                let first_property = data[1];
+                log::debug!("first prop {first_property}");
+                if first_property == atoms._NET_WM_STATE_DEMANDS_ATTENTION {
+                    let urgent = match self.man.find_window_state(event.window) {
+                        Some(s) => s.urgent,
+                        None => return Ok(()),
+                    };
+                    return match data[0] {
+                        0 => self.set_urgent(event.window, false),
+                        1 => self.set_urgent(event.window, true),
+                        2 => self.set_urgent(event.window, !urgent),
+                        _ => Ok(()),
                     };
                 }
-                _ => {}
-            },
+                if first_property == atoms._NET_WM_STATE_ABOVE {
+                    let pinned = match self.man.find_window_state(event.window) {
+                        Some(s) => s.pinned,
+                        None => return Ok(()),
+                    };
+                    match data[0] {
+                        0 => self.set_pinned(event.window, false),
+                        1 => self.set_pinned(event.window, true),
+                        2 => self.set_pinned(event.window, !pinned),
+                        _ => (),
+                    };
+                    return self.refresh();
+                }
+                if first_property != atoms._NET_WM_STATE_FULLSCREEN {
+                    return Ok(());
+                }
+                let is_fullscreen = match self.man.get_window_state(event.window) {
+                    Some(s) => s.fullscreen,
+                    None => return Ok(()),
+                };
+                match data[0] {
+                    0 => self.set_fullscreen(event.window, false)?,
+                    1 => self.set_fullscreen(event.window, true)?,
+                    2 => self.set_fullscreen(event.window, !is_fullscreen)?,
+                    _ => return Ok(()),
+                };
+                return self.refresh();
+            }
+            t if t == atoms._NET_SHOWING_DESKTOP => self.show_desktop(data[0] != 0)?,
+            t if t == atoms._NET_WM_FULLSCREEN_MONITORS => {
+                let monitors = [data[0], data[1], data[2], data[3]].map(|m| m as usize);
+                self.set_fullscreen_monitors(event.window, Some(monitors))?;
+                return self.refresh();
+            }
             _ => {}
         };
 
         Ok(())
     }
 
+    /// colors the border of an unfocused window asking for attention
+    fn set_urgent(&mut self, window: u32, urgent: bool) -> Res {
+        let focused = self.man.get_focus() == Some(window);
+        let state = match self.man.find_mut_window_state(window) {
+            Some(s) => s,
+            None => return Ok(()),
+        };
+        if state.urgent == urgent || (urgent && focused) {
+            return Ok(());
+        }
+        log::debug!("EVENT URGENT {window} {urgent}");
+        state.urgent = urgent;
+        if state.is_floating() || state.fullscreen {
+            return Ok(());
+        }
+        let state = *state;
+        match self.man.find_tag_of_window(window) {
+            Some(tag) => self.conn.restyle_border(&state, tag),
+            None => Ok(()),
+        }
+    }
+
+    /// the caller refreshes afterwards, so the windows are only tiled once
+    fn set_fullscreen(&mut self, window: u32, fullscreen: bool) -> Res {
+        let (x, y, width, height) = match self.man.get_fullscreen_geometry(window) {
+            Some(g) => g,
+            None => return Ok(()),
+        };
+        let state = match self.man.get_mut_window_state(window) {
+            Some(s) => s,
+            None => return Ok(()),
+        };
+        log::debug!("setting fullscreen of {} to {fullscreen}", state.window);
+        if fullscreen {
+            if !state.fullscreen {
+                state.group_before_fullscreen = Some(state.group);
+            }
+            state.group = WindowGroup::Floating;
+            (state.x, state.y, state.width, state.height) = (x, y, width, height);
+            state.fullscreen = true;
+            self.conn.set_fullscreen(state)?;
+        } else if state.fullscreen {
+            state.group = state
+                .group_before_fullscreen
+                .take()
+                .unwrap_or(WindowGroup::Stack);
+            state.fullscreen = false;
+            self.conn
+                .remove_atom_prop(state.window, self.conn.atoms._NET_WM_STATE)?;
+        }
+        Ok(())
+    }
+
+    /// `monitors` is the top, bottom, left and right monitor the window spans when fullscreen
+    fn set_fullscreen_monitors(&mut self, window: u32, monitors: Option<[usize; 4]>) -> Res {
+        let state = match self.man.get_mut_window_state(window) {
+            Some(s) => s,
+            None => return Ok(()),
+        };
+        log::debug!(
+            "setting fullscreen monitors of {} to {monitors:?}",
+            state.window
+        );
+        state.fullscreen_monitors = monitors;
+        let (window, fullscreen) = (state.window, state.fullscreen);
+        self.conn.set_fullscreen_monitors(window, monitors)?;
+        if fullscreen {
+            self.set_fullscreen(window, true)?;
+        }
+        Ok(())
+    }
+
+    fn handle_screen_change(&mut self) -> Res {
+        let geometries = self.conn.get_monitor_geometries()?;
+        log::debug!("EVENT SCREEN CHANGE monitors {}", geometries.len());
+        let active_name = self.man.monitors[self.man.active_monitor].name.clone();
+
+        // disconnected monitors remember their tag so it is shown again on reconnect
+        let (kept, removed): (Vec<_>, Vec<_>) = std::mem::take(&mut self.man.monitors)
+            .into_iter()
+            .partition(|m| geometries.iter().any(|(name, _)| *name == m.name));
+        self.man.monitors = kept;
+        for monitor in removed {
+            log::debug!("monitor {} disconnected", monitor.name);
+            self.conn.destroy_bar_window(&monitor.bar)?;
+            monitor
+                .viewed_tags()
+                .flat_map(|t| self.man.tags[t].windows.iter())
+                .try_for_each(|w| self.conn.unmap(w))?;
+            self.man
+                .monitor_history
+                .insert(monitor.name, monitor.active_tag);
+        }
+
+        for (i, (name, g)) in geometries.iter().enumerate() {
+            match self.man.monitors.iter_mut().find(|m| m.name == *name) {
+                Some(m) => {
+                    (m.x, m.y, m.width, m.height) = (g.x, g.y, g.width, g.height);
+                    (m.bar.x, m.bar.y, m.bar.width) = (g.x, g.y, g.width);
+                    self.conn.config_frame(&m.bar)?;
+                }
+                None => {
+                    let tag = match self.man.get_free_tag_for_monitor(i, name) {
+                        Some(t) => t,
+                        None => break,
+                    };
+                    let monitor = self.conn.create_monitor(i, name, g, tag)?;
+                    self.man.tags[tag]
+                        .windows
+                        .iter()
+                        .try_for_each(|w| self.conn.map(w))?;
+                    self.man.monitor_history.remove(name);
+                    self.man.monitors.push(monitor);
+                }
+            }
+        }
+
+        self.man.monitors.sort_by_key(|m| {
+            geometries
+                .iter()
+                .position(|(name, _)| *name == m.name)
+                .unwrap_or_default()
+        });
+        self.man.active_monitor = self
+            .man
+            .monitors
+            .iter()
+            .position(|m| m.name == active_name)
+            .unwrap_or_default();
+        self.man.update_struts();
+        self.refresh()
+    }
+
     fn refresh(&mut self) -> Res {
         self.refresh_focus()?;
-        self.man.refresh();
+        self.man.record_focus();
+        self.man.settle_focus();
+        // nothing to tile again when only the focus moved since the last time
+        if self.man.tiled.as_ref() != Some(&self.man.get_layout()) {
+            self.man.refresh();
+            (0..self.man.monitors.len()).try_for_each(|m| self.apply_external_layout(m))?;
+            self.man.tiled = Some(self.man.get_layout());
+        }
         self.config_tag()?;
+        let focus = self.man.get_focus();
+        self.man
+            .get_visible_windows()
+            .try_for_each(|w| self.conn.draw_titlebar(w, Some(w.window) == focus))?;
+        self.conn.set_workarea(&self.man)?;
+        self.conn.set_state_properties(&self.man)?;
         self.conn.refresh(&self.man)?;
         self.man.print_state();
+        self.publish_changes()
+    }
+
+    /// hands the tiled windows of the monitor's tags to an external layout program, if the active
+    /// tag has one, keeping the built-in tiling when it fails
+    fn apply_external_layout(&mut self, monitor: usize) -> Res {
+        let tag = self.man.monitors[monitor].active_tag;
+        let command = match self.conn.config.get_layout_command(tag) {
+            Some(c) => c,
+            None => return Ok(()),
+        };
+        // master first, like the list `query windows` gives
+        let windows = self
+            .man
+            .get_shown_windows(monitor)
+            .chunk_by(|a, b| a.0 == b.0)
+            .flat_map(|c| c.iter().rev())
+            .map(|(t, i)| &self.man.tags[*t].windows[*i])
+            .filter(|w| !w.is_floating() && !w.fullscreen)
+            .map(|w| w.window)
+            .collect::<Vec<_>>();
+        if windows.is_empty() {
+            return Ok(());
+        }
+        let (x, y, width, height) = self.man.get_workarea(monitor);
+        let tiling = self.man.monitors[monitor].tiling;
+        let gap = self.man.get_gap(monitor);
+        let input = LayoutInput {
+            tag: self.man.tag_number(tag),
+            area: rect(x, y, width, height),
+            gap,
+            ratio: tiling.ratio,
+            windows: windows
+                .iter()
+                .map(|&w| {
+                    Ok(LayoutWindow {
+                        id: w,
+                        class: self.conn.get_wm_class(w)?.1,
+                        focused: self.man.tags[tag].focus == Some(w),
+                    })
+                })
+                .collect::<Result<_, WmError>>()?,
+        };
+        let geometries = match actions::run_layout(command, &to_json(&input), windows.len()) {
+            Ok(g) => g,
+            Err(e) => {
+                log::error!("layout command {command:?} failed: {e}");
+                return Ok(());
+            }
+        };
+        windows
+            .iter()
+            .zip(geometries)
+            .for_each(|(&window, [x, y, width, height])| {
+                if let Some(w) = self.man.get_mut_window_state(window) {
+                    w.x = x as i16;
+                    w.y = y as i16;
+                    w.width = width.max(1) as u16;
+                    w.height = height.max(1) as u16;
+                }
+            });
+        Ok(())
+    }
+
+    /// tells subscribers and hooks about focus and tag changes since the last refresh
+    fn publish_changes(&mut self) -> Res {
+        let focus = self.man.get_focus();
+        if focus != self.subscribers.focus {
+            self.subscribers.focus = focus;
+            self.subscribers
+                .publish(ipc::Event::Focus { window: focus });
+            if self.conn.config.hooks.focus_changed.is_some() {
+                let class = match focus {
+                    Some(w) => self.conn.get_wm_class(w)?.1,
+                    None => String::new(),
+                };
+                actions::run_hook(
+                    "focus_changed",
+                    &self.conn.config.hooks.focus_changed,
+                    &[
+                        (
+                            "WINDOW_ID",
+                            focus.map(|w| w.to_string()).unwrap_or_default(),
+                        ),
+                        ("WM_CLASS", class),
+                        (
+                            "TAG",
+                            self.man.tag_number(self.man.active_tag()).to_string(),
+                        ),
+                    ],
+                );
+            }
+        }
+        let tags = self.man.monitors.iter().map(|m| m.active_tag).collect();
+        let old = std::mem::replace(&mut self.subscribers.tags, tags);
+        let changed = (0..self.man.monitors.len())
+            .filter(|&i| old.get(i) != Some(&self.man.monitors[i].active_tag))
+            .collect::<Vec<_>>();
+        for i in changed {
+            let monitor = &self.man.monitors[i];
+            let i3_event = WorkspaceEvent {
+                change: "focus",
+                current: self.workspace(monitor.active_tag),
+                old: old.get(i).map(|&tag| self.workspace(tag)),
+            };
+            actions::run_hook(
+                "tag_changed",
+                &self.conn.config.hooks.tag_changed,
+                &[
+                    ("TAG", self.man.tag_number(monitor.active_tag).to_string()),
+                    ("MONITOR", monitor.name.clone()),
+                ],
+            );
+            self.subscribers.publish(ipc::Event::Tag {
+                monitor: monitor.name.clone(),
+                tag: self.man.tag_number(monitor.active_tag),
+            });
+            self.subscribers
+                .publish_i3(i3ipc::WORKSPACE_EVENT, &i3_event);
+        }
         Ok(())
     }
 
-    fn refresh_focus(&self) -> Res {
-        match self.man.tags[self.man.active_tag].focus {
+    fn refresh_focus(&mut self) -> Res {
+        // hidden windows can't take the focus
+        if self.man.showing_desktop {
+            return self.conn.set_focus_to_root();
+        }
+        match self.man.get_focus() {
             Some(w) => {
-                let window = match self.man.get_window_state(w) {
+                let window = match self.man.get_mut_window_state(w) {
                     Some(w) => w,
                     None => return Ok(()),
                 };
-                self.conn
-                    .set_focus_window(self.man.get_active_tag_windows(), window)?;
+                // focusing a window answers its call for attention
+                window.urgent = false;
+                let window = *window;
+                let visible = (0..self.man.monitors.len())
+                    .flat_map(|m| self.man.get_shown_windows(m))
+                    .map(|(t, i)| (t, self.man.tags[t].windows[i]))
+                    .collect::<Vec<_>>();
+                self.conn.set_focus_window(&visible, &window)?;
             }
             None => {
                 self.conn.set_focus_to_root()?;
@@ -237,41 +1966,222 @@ impl<'a, C: Connection> EventHandler<'a, C> {
         Ok(())
     }
 
+    /// hides the windows of the shown tags to uncover the desktop, or brings them back
+    fn show_desktop(&mut self, show: bool) -> Res {
+        if self.man.showing_desktop == show {
+            return Ok(());
+        }
+        log::debug!("showing desktop {show}");
+        self.man.showing_desktop = show;
+        self.man
+            .get_visible_windows()
+            .try_for_each(|w| match show {
+                true => self.conn.unmap(w),
+                false => self.conn.map(w),
+            })?;
+        self.conn.set_showing_desktop(show)
+    }
+
     fn change_active_tag(&mut self, tag: usize) -> Res {
-        if self.man.active_tag == tag {
+        // the hidden windows come back before others are shown
+        self.show_desktop(false)?;
+        let before = self.man.get_visible_windows().copied().collect::<Vec<_>>();
+        let active_monitor = self.man.active_monitor;
+        // switching shows the one tag only, like dwm's view
+        self.man.monitors[active_monitor].extra_tags.clear();
+        if self.man.active_tag() == tag {
             log::error!("tried switching to already active tag");
-            return Ok(());
+            return self.map_changes(&before);
         }
         log::debug!("changing tag to {tag}");
-        self.unmap_tag()?;
-        self.man.active_tag = tag;
-        self.map_tag()?;
-        Ok(())
+        // a tag toggled into view on another monitor is taken from it as it is
+        if let Some(other) = self.man.get_monitor_of_tag(tag)
+            && self.man.monitors[other].active_tag != tag
+        {
+            self.man.monitors[other].extra_tags.retain(|t| *t != tag);
+        }
+        // a tag shown on another monitor swaps places with the active one
+        if let Some(other) = self.man.get_monitor_of_tag(tag) {
+            self.man.monitors[other].active_tag = self.man.active_tag();
+        }
+        self.man.monitors[active_monitor].active_tag = tag;
+        self.map_changes(&before)
     }
 
-    fn map_tag(&mut self) -> Res {
-        self.man
-            .get_active_tag_windows()
-            .iter()
-            .try_for_each(|w| self.conn.map(w))
+    /// shows the windows of `tag` next to the active tag's, or hides them again
+    fn toggle_view_tag(&mut self, tag: usize) -> Res {
+        self.show_desktop(false)?;
+        let before = self.man.get_visible_windows().copied().collect::<Vec<_>>();
+        let active_monitor = self.man.active_monitor;
+        if self.man.active_tag() == tag {
+            log::error!("tried toggling the active tag out of view");
+            return Ok(());
+        }
+        let monitor = &mut self.man.monitors[active_monitor];
+        if monitor.extra_tags.contains(&tag) {
+            log::debug!("toggling tag {tag} out of view");
+            monitor.extra_tags.retain(|t| *t != tag);
+            return self.map_changes(&before);
+        }
+        if self.man.get_monitor_of_tag(tag).is_some() {
+            log::error!("tried toggling tag {tag} shown on another monitor into view");
+            return Ok(());
+        }
+        log::debug!("toggling tag {tag} into view");
+        self.man.monitors[active_monitor].extra_tags.push(tag);
+        self.map_changes(&before)
     }
 
-    fn unmap_tag(&mut self) -> Res {
-        self.man
-            .get_active_tag_windows()
+    /// puts the focused window on `tag` as well, or takes it off again.
+    /// taken off the tag holding it, it moves to another of its tags
+    fn toggle_tag(&mut self, tag: usize) -> Res {
+        let before = self.man.get_visible_windows().copied().collect::<Vec<_>>();
+        let (held, index) = match self.man.get_focus().and_then(|f| self.man.locate_window(f)) {
+            Some(l) => l,
+            None => return Ok(()),
+        };
+        let window = self.man.tags[held].windows[index];
+        if held != tag {
+            log::debug!("toggling window {} on tag {tag}", window.window);
+            let on = self.man.is_also_on(window.window, tag);
+            self.man.set_also_on(window.window, tag, !on);
+            return self.map_changes(&before);
+        }
+        let other = match self
+            .man
+            .also_on
+            .get(&window.window)
+            .and_then(|s| s.iter().next())
+        {
+            Some(t) => t,
+            None => {
+                log::error!("tried taking window off the only tag it is on");
+                return Ok(());
+            }
+        };
+        log::debug!(
+            "moving window {} from tag {tag} to tag {other}",
+            window.window
+        );
+        if let Some(state) = self.man.take_window(window.window) {
+            self.man.add_window_to_tag_unfocused(state, other);
+        }
+        self.map_changes(&before)
+    }
+
+    /// maps the windows shown since `before` and unmaps the ones hidden since
+    fn map_changes(&self, before: &[WindowState]) -> Res {
+        let after = self.man.get_visible_windows().copied().collect::<Vec<_>>();
+        let shown =
+            |windows: &[WindowState], w: &WindowState| windows.iter().any(|s| s.window == w.window);
+        before
             .iter()
-            .try_for_each(|w| self.conn.unmap(w))
+            .filter(|w| !shown(&after, w))
+            .try_for_each(|w| self.conn.unmap(w))?;
+        after
+            .iter()
+            .filter(|w| !shown(before, w))
+            .try_for_each(|w| self.conn.map(w))
     }
 
     fn config_tag(&mut self) -> Res {
         self.man
-            .get_active_tag_windows()
+            .get_visible_windows()
+            .try_for_each(|w| self.conn.config_window_from_state(w))?;
+        self.restack()
+    }
+
+    /// stacks tiled windows at the bottom, floating and pinned ones above them, then the bars and
+    /// docks, and fullscreen windows above everything, leaving it alone while nothing changed
+    fn restack(&mut self) -> Res {
+        self.man.raise_focus();
+        let (fullscreen, windows): (Vec<&WindowState>, Vec<_>) = self
+            .man
+            .get_stacking_order()
+            .into_iter()
+            .partition(|w| w.fullscreen);
+        let frames = windows
             .iter()
-            .try_for_each(|w| self.conn.config_window_from_state(w))
+            .map(|w| w.frame_window)
+            .chain(
+                self.man
+                    .monitors
+                    .iter()
+                    .filter(|m| m.tiling.show_bar)
+                    .map(|m| m.bar.frame_window),
+            )
+            .chain(self.man.docks.iter().map(|d| d.window))
+            .chain(fullscreen.iter().map(|w| w.frame_window))
+            .collect::<Vec<_>>();
+        if frames == self.man.stacked {
+            return Ok(());
+        }
+        log::debug!("restacking {} windows", frames.len());
+        frames
+            .windows(2)
+            .try_for_each(|pair| self.conn.stack_above(pair[1], pair[0]))?;
+        self.man.stacked = frames;
+        Ok(())
+    }
+
+    /// puts the focused window on top of the others of its layer
+    fn raise_focus(&mut self) {
+        if let Some(focus) = self.man.get_focus() {
+            log::debug!("raising {focus}");
+            self.man.raise_window(focus);
+        }
+    }
+
+    /// puts the focused window below the others of its layer
+    fn lower_focus(&mut self) {
+        if let Some(focus) = self.man.get_focus() {
+            log::debug!("lowering {focus}");
+            self.man.lower_window(focus);
+        }
+    }
+
+    fn move_tag_to_monitor(&mut self, monitor: usize) {
+        if self.man.active_monitor == monitor {
+            log::error!("tried moving tag to already active monitor");
+            return;
+        }
+        let (from, to) = (self.man.active_tag(), self.man.monitors[monitor].active_tag);
+        log::debug!("moving windows of tag {from} to tag {to} on monitor {monitor}");
+
+        let windows = std::mem::take(&mut self.man.tags[from].windows);
+        self.man.tags[to].windows.extend(windows);
+        self.man.set_tag_focus_to_master(from);
+        self.man.set_tag_focus_to_master(to);
+        self.man.active_monitor = monitor;
     }
 
-    fn move_window(&mut self, tag: usize) -> Res {
-        if self.man.active_tag == tag {
+    fn swap_tag_with_monitor(&mut self, monitor: usize) {
+        if self.man.active_monitor == monitor {
+            log::error!("tried swapping tag with already active monitor");
+            return;
+        }
+        log::debug!("swapping tags with monitor {monitor}");
+        let active_monitor = self.man.active_monitor;
+        let (from, to) = (self.man.active_tag(), self.man.monitors[monitor].active_tag);
+        match self.man.workspaces {
+            WorkspaceModel::Global => {
+                self.man.monitors[monitor].active_tag = from;
+                self.man.monitors[active_monitor].active_tag = to;
+            }
+            // tags can't leave their monitor, so their contents are swapped instead
+            WorkspaceModel::PerMonitor => {
+                let (a, b) = (from.min(to), from.max(to));
+                let (left, right) = self.man.tags.split_at_mut(b);
+                std::mem::swap(&mut left[a].windows, &mut right[0].windows);
+                std::mem::swap(&mut left[a].focus, &mut right[0].focus);
+            }
+        }
+        self.man.active_monitor = monitor;
+    }
+
+    /// with `follow` the target tag is shown afterwards, so the window is never unmapped
+    fn move_window(&mut self, tag: usize, follow: bool) -> Res {
+        if self.man.active_tag() == tag {
             log::error!("tried moving window to already active tag");
             return Ok(());
         }
@@ -284,13 +2194,100 @@ impl<'a, C: Connection> EventHandler<'a, C> {
         } else {
             return Ok(());
         };
-        self.conn.unmap(&state)?;
 
+        self.man.detach_window(state.window);
         self.man.tags[tag].windows.push(state);
-        self.man.tags[self.man.active_tag]
-            .windows
-            .retain(|w| w.window != focus_window);
-        self.man.set_tag_focus_to_master();
+        if follow {
+            self.change_active_tag(tag)?;
+            self.man.tags[tag].focus = Some(state.window);
+        } else if self.man.get_monitor_of_window(state.window).is_none() {
+            self.conn.unmap(&state)?;
+        }
         Ok(())
     }
 }
+
+fn rect(x: i16, y: i16, width: u16, height: u16) -> Rect {
+    Rect {
+        x,
+        y,
+        width,
+        height,
+    }
+}
+
+/// what a layout command is given on its stdin
+#[derive(Serialize)]
+struct LayoutInput {
+    /// counting from 1 within the tags of the monitor
+    tag: usize,
+    area: Rect,
+    gap: u16,
+    ratio: f32,
+    /// master first
+    windows: Vec<LayoutWindow>,
+}
+
+#[derive(Serialize)]
+struct LayoutWindow {
+    id: u32,
+    class: String,
+    focused: bool,
+}
+
+/// the preset `change` steps away from `ratio`, wrapping around. a ratio between two presets
+/// steps to the nearer one in that direction first
+fn next_preset(presets: &[f32], ratio: f32, change: i16) -> Option<f32> {
+    if presets.is_empty() || change == 0 {
+        return None;
+    }
+    let len = presets.len() as i64;
+    let change = change as i64;
+    let index = match presets.iter().position(|p| (p - ratio).abs() < 0.005) {
+        Some(i) => i as i64 + change,
+        None => {
+            let below = presets.iter().filter(|p| **p < ratio).count() as i64;
+            if change > 0 {
+                below + change - 1
+            } else {
+                below + change
+            }
+        }
+    };
+    Some(presets[index.rem_euclid(len) as usize])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRESETS: [f32; 3] = [0.3, 0.5, 0.7];
+
+    #[test]
+    fn presets_wrap_around() {
+        assert_eq!(next_preset(&PRESETS, 0.5, 1), Some(0.7));
+        assert_eq!(next_preset(&PRESETS, 0.7, 1), Some(0.3));
+        assert_eq!(next_preset(&PRESETS, 0.3, 4), Some(0.5));
+    }
+
+    #[test]
+    fn presets_step_back_with_negative_changes() {
+        assert_eq!(next_preset(&PRESETS, 0.5, -1), Some(0.3));
+        assert_eq!(next_preset(&PRESETS, 0.3, -1), Some(0.7));
+        assert_eq!(next_preset(&PRESETS, 0.3, -5), Some(0.5));
+    }
+
+    #[test]
+    fn ratio_between_presets_steps_to_the_nearer_one_first() {
+        assert_eq!(next_preset(&PRESETS, 0.6, 1), Some(0.7));
+        assert_eq!(next_preset(&PRESETS, 0.6, -1), Some(0.5));
+        assert_eq!(next_preset(&PRESETS, 0.8, 1), Some(0.3));
+        assert_eq!(next_preset(&PRESETS, 0.2, -1), Some(0.7));
+    }
+
+    #[test]
+    fn no_presets_or_no_change_keeps_the_ratio() {
+        assert_eq!(next_preset(&[], 0.5, 1), None);
+        assert_eq!(next_preset(&PRESETS, 0.5, 0), None);
+    }
+}
@@ -0,0 +1,282 @@
+//! enough of the i3 ipc protocol for i3bar, polybar's i3 module and i3-msg scripts
+//! <https://i3wm.org/docs/ipc.html>
+
+use std::{
+    io::{Read, Write},
+    os::unix::net::UnixStream,
+    path::PathBuf,
+    sync::{Arc, mpsc::Sender},
+    thread,
+};
+
+use serde::Serialize;
+
+use crate::{
+    ipc::{self, Command, Query, Request, to_json},
+    poll::EventFd,
+};
+
+const MAGIC: &[u8; 6] = b"i3-ipc";
+
+const RUN_COMMAND: u32 = 0;
+const GET_WORKSPACES: u32 = 1;
+const SUBSCRIBE: u32 = 2;
+const GET_OUTPUTS: u32 = 3;
+const GET_TREE: u32 = 4;
+const GET_MARKS: u32 = 5;
+const GET_BAR_CONFIG: u32 = 6;
+const GET_VERSION: u32 = 7;
+const GET_BINDING_MODES: u32 = 8;
+const SEND_TICK: u32 = 10;
+
+pub const WORKSPACE_EVENT: u32 = 0x8000_0000;
+
+/// the reply to a command, or to a message that failed
+#[derive(Debug, Serialize)]
+struct Outcome {
+    success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl Outcome {
+    const SUCCESS: Outcome = Outcome {
+        success: true,
+        error: None,
+    };
+
+    fn error(error: impl Into<String>) -> Self {
+        Outcome {
+            success: false,
+            error: Some(error.into()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Version {
+    major: u32,
+    minor: u32,
+    patch: u32,
+    human_readable: &'static str,
+    loaded_config_file_name: &'static str,
+}
+
+/// next to our own socket, advertised through the `I3_SOCKET_PATH` root property
+pub fn socket_path() -> PathBuf {
+    ipc::socket_path().with_file_name(format!("rwm-i3-{}.sock", ipc::display_number()))
+}
+
+/// returns the path to advertise in `I3_SOCKET_PATH`
+pub(crate) fn listen(
+    tx: Sender<Request>,
+    waker: Arc<EventFd>,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let path = socket_path();
+    let listener = ipc::bind(&path)?;
+    log::info!("listening for i3 messages on {path:?}");
+
+    thread::spawn(move || {
+        listener.incoming().for_each(|stream| match stream {
+            Ok(stream) => {
+                let (tx, waker) = (tx.clone(), waker.clone());
+                // subscribers stay connected, so every client gets its own thread
+                thread::spawn(move || {
+                    if let Err(e) = handle_client(stream, &tx, &waker) {
+                        log::error!("i3 ipc client error {e:?}");
+                    }
+                });
+            }
+            Err(e) => log::error!("i3 ipc accept error {e:?}"),
+        })
+    });
+    Ok(path)
+}
+
+pub fn write_message(stream: &mut UnixStream, kind: u32, payload: &str) -> std::io::Result<()> {
+    let mut message = MAGIC.to_vec();
+    message.extend((payload.len() as u32).to_ne_bytes());
+    message.extend(kind.to_ne_bytes());
+    message.extend(payload.as_bytes());
+    stream.write_all(&message)
+}
+
+fn read_message(stream: &mut UnixStream) -> std::io::Result<Option<(u32, String)>> {
+    let mut header = [0; 14];
+    match stream.read_exact(&mut header) {
+        Ok(()) => (),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    if &header[..6] != MAGIC {
+        return Err(std::io::Error::other("bad magic"));
+    }
+    let len = u32::from_ne_bytes([header[6], header[7], header[8], header[9]]);
+    let kind = u32::from_ne_bytes([header[10], header[11], header[12], header[13]]);
+    let mut payload = vec![0; len as usize];
+    stream.read_exact(&mut payload)?;
+    Ok(Some((kind, String::from_utf8_lossy(&payload).into_owned())))
+}
+
+fn handle_client(
+    mut stream: UnixStream,
+    tx: &Sender<Request>,
+    waker: &EventFd,
+) -> Result<(), Box<dyn std::error::Error>> {
+    while let Some((kind, payload)) = read_message(&mut stream)? {
+        log::debug!("I3 IPC message {kind} {payload:?}");
+        let reply = match kind {
+            RUN_COMMAND => {
+                let results = split_commands(&payload)
+                    .into_iter()
+                    .map(|c| match translate_command(c) {
+                        Ok(command) => ipc::send(tx, waker, command),
+                        Err(e) => Ok(format!("error: {e}")),
+                    })
+                    .map(|reply| {
+                        Ok(match reply?.strip_prefix("error: ") {
+                            Some(e) => Outcome::error(e),
+                            None => Outcome::SUCCESS,
+                        })
+                    })
+                    .collect::<Result<Vec<_>, Box<dyn std::error::Error>>>()?;
+                to_json(&results)
+            }
+            GET_WORKSPACES => ipc::send(tx, waker, Command::Query(Query::Workspaces))?,
+            GET_OUTPUTS => ipc::send(tx, waker, Command::Query(Query::Outputs))?,
+            GET_TREE => ipc::send(tx, waker, Command::Query(Query::Tree))?,
+            SUBSCRIBE => {
+                // only workspace events are sent, but other subscriptions are harmless
+                write_message(&mut stream, kind, &to_json(&Outcome::SUCCESS))?;
+                ipc::send(tx, waker, Command::SubscribeI3(stream.try_clone()?))?;
+                return Ok(());
+            }
+            GET_MARKS => "[]".to_owned(),
+            // the bars are drawn by hematite itself, there are no i3bar ones to configure
+            GET_BAR_CONFIG if payload.is_empty() => "[]".to_owned(),
+            GET_BAR_CONFIG => to_json(&Outcome::error(format!("no bar with id {payload:?}"))),
+            GET_VERSION => to_json(&Version {
+                major: 4,
+                minor: 0,
+                patch: 0,
+                human_readable: "hematite",
+                loaded_config_file_name: "",
+            }),
+            GET_BINDING_MODES => to_json(&["default"]),
+            SEND_TICK => to_json(&Outcome::SUCCESS),
+            _ => to_json(&Outcome::error("unsupported message type")),
+        };
+        write_message(&mut stream, kind, &reply)?;
+    }
+    Ok(())
+}
+
+/// the commands of a `RUN_COMMAND` payload, separated by `;` or `,` outside of double quotes
+fn split_commands(payload: &str) -> Vec<&str> {
+    let (mut commands, mut start) = (Vec::new(), 0);
+    let (mut quoted, mut escaped) = (false, false);
+    for (i, c) in payload.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ';' | ',' if !quoted => {
+                commands.push(&payload[start..i]);
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    commands.push(&payload[start..]);
+    commands
+        .into_iter()
+        .map(str::trim)
+        .filter(|c| !c.is_empty())
+        .collect()
+}
+
+/// turns an i3 command into one of ours, e.g. `workspace number 3` into `switch-tag 3`
+fn translate_command(command: &str) -> Result<Command, String> {
+    let words = command.split_whitespace().collect::<Vec<_>>();
+    let ours = match words.as_slice() {
+        ["workspace", "next" | "next_on_output"] => "next-tag 1".to_owned(),
+        ["workspace", "prev" | "prev_on_output"] => "next-tag -1".to_owned(),
+        ["workspace", "number", tag] | ["workspace", tag] => format!("switch-tag {tag}"),
+        [
+            "move",
+            "container" | "window",
+            "to",
+            "workspace",
+            "number",
+            tag,
+        ]
+        | ["move", "container" | "window", "to", "workspace", tag] => format!("move-window {tag}"),
+        ["kill"] => "close-focused".to_owned(),
+        ["fullscreen"] | ["fullscreen", "toggle"] => "toggle-fullscreen".to_owned(),
+        ["floating", "toggle"] => "toggle-floating".to_owned(),
+        ["focus", "left" | "up"] => "next-focus -1".to_owned(),
+        ["focus", "right" | "down"] => "next-focus 1".to_owned(),
+        ["exec", "--no-startup-id", ..] | ["exec", ..] => {
+            let start = words
+                .iter()
+                .position(|w| !w.starts_with('-') && *w != "exec");
+            let command = match start {
+                Some(start) => words[start..].join(" "),
+                None => return Err("exec needs a command".to_owned()),
+            };
+            // a quoted command is handed to the shell without i3's quotes
+            match command.strip_prefix('"').and_then(|c| c.strip_suffix('"')) {
+                Some(quoted) => format!("spawn {}", quoted.replace("\\\"", "\"")),
+                None => format!("spawn {command}"),
+            }
+        }
+        ["reload"] => "reload-config".to_owned(),
+        ["restart"] => "restart".to_owned(),
+        ["exit"] => "quit".to_owned(),
+        _ => return Err(format!("unsupported command {command:?}")),
+    };
+    ipc::parse_command(&ours)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::{HotkeyAction, SpawnCommand};
+
+    #[test]
+    fn commands_split_outside_of_quotes() {
+        assert_eq!(
+            split_commands("workspace 2; kill,exec \"notify-send 'a; b, c'\" ;"),
+            ["workspace 2", "kill", "exec \"notify-send 'a; b, c'\""]
+        );
+        assert_eq!(
+            split_commands(r#"exec "echo \"x;y\""; reload"#),
+            [r#"exec "echo \"x;y\"""#, "reload"]
+        );
+        assert!(split_commands(" ; ").is_empty());
+    }
+
+    #[test]
+    fn quoted_exec_loses_the_quotes() {
+        for (i3, ours) in [
+            (r#"exec "echo \"x;y\"""#, r#"echo "x;y""#),
+            ("exec --no-startup-id firefox", "firefox"),
+        ] {
+            match translate_command(i3) {
+                Ok(Command::Action(HotkeyAction::Spawn(SpawnCommand::Shell(c)))) => {
+                    assert_eq!(c, ours)
+                }
+                _ => panic!("{i3:?} isn't a spawn"),
+            }
+        }
+    }
+
+    #[test]
+    fn outcomes_leave_out_a_missing_error() {
+        assert_eq!(to_json(&Outcome::SUCCESS), r#"{"success":true}"#);
+        assert_eq!(
+            to_json(&[Outcome::error("no \"bar\"")]),
+            r#"[{"success":false,"error":"no \"bar\""}]"#
+        );
+    }
+}
@@ -0,0 +1,571 @@
+//! the control socket that `rwmc` talks to, and its json
+
+use std::{
+    io::{BufRead, BufReader, ErrorKind, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        Arc,
+        mpsc::{self, Receiver, Sender},
+    },
+    thread,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::HotkeyConfig,
+    i3ipc,
+    keys::{HotkeyAction, SpawnCommand},
+    poll::EventFd,
+};
+
+/// a command read from the socket, answered through `reply`
+pub struct Request {
+    pub command: Command,
+    pub reply: Sender<String>,
+}
+
+pub enum Command {
+    Action(HotkeyAction),
+    Query(Query),
+    /// the stream gets a line of json for every event from then on
+    Subscribe(UnixStream),
+    /// the stream gets i3 workspace events from then on
+    SubscribeI3(UnixStream),
+    Bind(HotkeyConfig),
+    Unbind {
+        modifiers: String,
+        key: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Query {
+    Tags,
+    Windows,
+    Focused,
+    /// the focused window's title as plain text
+    Title,
+    /// the i3 shaped replies
+    Workspaces,
+    Outputs,
+    Tree,
+}
+
+/// `value` as json, which the reply and event types below always serialize to
+pub fn to_json(value: &impl Serialize) -> String {
+    serde_json::to_string(value).unwrap_or_else(|e| {
+        log::error!("couldn't write json {e}");
+        "null".to_owned()
+    })
+}
+
+/// a rectangle, like i3 gives them
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Rect {
+    pub x: i16,
+    pub y: i16,
+    pub width: u16,
+    pub height: u16,
+}
+
+/// a tag in the `query tags` reply, counting from 1 within the tags of a monitor
+#[derive(Debug, Serialize)]
+pub struct TagInfo {
+    pub tag: usize,
+    pub windows: usize,
+    pub visible: bool,
+    /// the monitor showing the tag, or with per-monitor tags the one it belongs to
+    pub monitor: Option<String>,
+    pub focused: bool,
+}
+
+/// a window in the `query windows` and `query focused` replies
+#[derive(Debug, Serialize)]
+pub struct WindowInfo {
+    pub id: u32,
+    pub title: String,
+    /// counting from 1 within the tags of a monitor
+    pub tag: usize,
+    pub x: i16,
+    pub y: i16,
+    pub width: u16,
+    pub height: u16,
+    pub floating: bool,
+    pub fullscreen: bool,
+    pub focused: bool,
+}
+
+/// an i3 workspace, its windows only listed in the tree
+#[derive(Debug, Serialize)]
+pub struct Workspace {
+    pub id: usize,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub num: usize,
+    pub name: String,
+    pub visible: bool,
+    pub focused: bool,
+    pub urgent: bool,
+    pub rect: Rect,
+    pub output: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nodes: Option<Vec<Con>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub floating_nodes: Option<Vec<Con>>,
+}
+
+/// an i3 container holding a window
+#[derive(Debug, Serialize)]
+pub struct Con {
+    pub id: u32,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub name: String,
+    pub window: u32,
+    pub rect: Rect,
+    pub focused: bool,
+    pub fullscreen_mode: u16,
+    pub nodes: Vec<Con>,
+}
+
+/// an i3 output
+#[derive(Debug, Serialize)]
+pub struct Output {
+    pub name: String,
+    pub active: bool,
+    pub primary: bool,
+    pub rect: Rect,
+    pub current_workspace: String,
+}
+
+/// a node of the i3 tree above the workspaces, the root or an output
+#[derive(Debug, Serialize)]
+pub struct Node<T> {
+    pub id: u32,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub name: String,
+    pub rect: Rect,
+    pub focused: bool,
+    pub nodes: Vec<T>,
+}
+
+/// the i3 workspace event, sent when a monitor shows another tag
+#[derive(Debug, Serialize)]
+pub struct WorkspaceEvent {
+    pub change: &'static str,
+    pub current: Workspace,
+    pub old: Option<Workspace>,
+}
+
+/// a line sent to subscribers, with its name as `event`
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    Map {
+        window: u32,
+        /// counting from 1 within the tags of a monitor
+        tag: usize,
+        title: String,
+    },
+    Unmap {
+        window: u32,
+    },
+    Title {
+        window: u32,
+        title: String,
+    },
+    Focus {
+        window: Option<u32>,
+    },
+    Tag {
+        monitor: String,
+        /// counting from 1 within the tags of a monitor
+        tag: usize,
+    },
+}
+
+/// clients listening for events, along with what they were last told
+#[derive(Default)]
+pub struct Subscribers {
+    streams: Vec<UnixStream>,
+    i3_streams: Vec<UnixStream>,
+    pub focus: Option<u32>,
+    pub tags: Vec<usize>,
+}
+
+impl Subscribers {
+    pub fn add(&mut self, stream: UnixStream, i3: bool) {
+        // a client that stops reading shouldn't stall the window manager
+        if let Err(e) = stream.set_write_timeout(Some(Duration::from_millis(100))) {
+            log::error!("couldn't set subscriber timeout {e:?}");
+        }
+        match i3 {
+            true => self.i3_streams.push(stream),
+            false => self.streams.push(stream),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.streams.is_empty()
+    }
+
+    pub fn publish_i3(&mut self, event: u32, payload: &impl Serialize) {
+        let payload = to_json(payload);
+        self.i3_streams
+            .retain_mut(|stream| i3ipc::write_message(stream, event, &payload).is_ok());
+    }
+
+    /// sends the event to every subscriber, dropping the ones that went away
+    pub fn publish(&mut self, event: Event) {
+        let line = to_json(&event);
+        log::debug!("IPC EVENT {line}");
+        self.streams
+            .retain_mut(|stream| writeln!(stream, "{line}").is_ok());
+    }
+}
+
+/// `$XDG_RUNTIME_DIR/rwm-<display>.sock`, falling back to /tmp, so instances on other
+/// displays each get their own
+pub fn socket_path() -> PathBuf {
+    let display = display_number();
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir).join(format!("rwm-{display}.sock")),
+        None => PathBuf::from(format!(
+            "/tmp/rwm-{}-{display}.sock",
+            std::env::var("USER").unwrap_or_default()
+        )),
+    }
+}
+
+/// the display number of `$DISPLAY`, `1` for `:1.0` or `host:1`
+pub fn display_number() -> String {
+    let display = std::env::var("DISPLAY").unwrap_or_default();
+    let number = display.rsplit(':').next().unwrap_or_default();
+    match number.split('.').next() {
+        Some(n) if !n.is_empty() => n.to_owned(),
+        _ => "0".to_owned(),
+    }
+}
+
+/// binds the socket at `path`, replacing a socket left over from a crashed instance
+/// but not one another instance still listens on
+pub(crate) fn bind(path: &Path) -> std::io::Result<UnixListener> {
+    match UnixStream::connect(path) {
+        Ok(_) => {
+            return Err(std::io::Error::new(
+                ErrorKind::AddrInUse,
+                format!("another instance listens on {path:?}"),
+            ));
+        }
+        Err(e) if e.kind() == ErrorKind::ConnectionRefused => std::fs::remove_file(path)?,
+        Err(_) => (),
+    }
+    UnixListener::bind(path)
+}
+
+pub fn remove_socket() {
+    let _ = std::fs::remove_file(socket_path());
+    let _ = std::fs::remove_file(i3ipc::socket_path());
+}
+
+/// starts listening on the socket and the other control interfaces, their requests are sent
+/// to the returned receiver and `waker` woken. the i3 socket's path comes along if it opened
+pub fn listen(
+    waker: Arc<EventFd>,
+) -> Result<(Receiver<Request>, Option<PathBuf>), Box<dyn std::error::Error>> {
+    let path = socket_path();
+    let listener = bind(&path)?;
+    log::info!("listening for commands on {path:?}");
+
+    let (tx, rx) = mpsc::channel();
+    let i3_path = match i3ipc::listen(tx.clone(), waker.clone()) {
+        Ok(path) => Some(path),
+        Err(e) => {
+            log::error!("couldn't open i3 socket {e:?}");
+            None
+        }
+    };
+    crate::watch::start(tx.clone(), waker.clone());
+    #[cfg(feature = "dbus")]
+    if let Err(e) = crate::dbus::serve(tx.clone(), waker.clone()) {
+        log::error!("couldn't register on the session bus {e:?}");
+    }
+    thread::spawn(move || {
+        listener.incoming().for_each(|stream| match stream {
+            Ok(stream) => {
+                let (tx, waker) = (tx.clone(), waker.clone());
+                // a client that keeps its connection open shouldn't hold up the others
+                thread::spawn(move || {
+                    if let Err(e) = handle_client(stream, &tx, &waker) {
+                        log::error!("ipc client error {e:?}");
+                    }
+                });
+            }
+            Err(e) => log::error!("ipc accept error {e:?}"),
+        })
+    });
+    Ok((rx, i3_path))
+}
+
+/// answers every line the client sends with one line
+fn handle_client(
+    stream: UnixStream,
+    tx: &Sender<Request>,
+    waker: &EventFd,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        log::debug!("IPC COMMAND {line}");
+        let command = match line.trim() {
+            "subscribe" => Ok(Command::Subscribe(writer.try_clone()?)),
+            line => parse_command(line),
+        };
+        // answered before handing the stream over, so the answer comes before any event
+        if let Ok(Command::Subscribe(_)) = command {
+            writeln!(writer, "ok")?;
+        }
+        let subscribe = matches!(command, Ok(Command::Subscribe(_)));
+        let reply = match command {
+            Ok(command) => send(tx, waker, command)?,
+            Err(e) => format!("error: {e}"),
+        };
+        // the stream now belongs to the event loop
+        if subscribe {
+            break;
+        }
+        writeln!(writer, "{reply}")?;
+    }
+    Ok(())
+}
+
+/// hands the command to the main loop and waits for its answer
+pub(crate) fn send(
+    tx: &Sender<Request>,
+    waker: &EventFd,
+    command: Command,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let (reply_tx, reply_rx) = mpsc::channel();
+    tx.send(Request {
+        command,
+        reply: reply_tx,
+    })?;
+    waker.wake()?;
+    Ok(reply_rx.recv()?)
+}
+
+/// parses commands like `switch-tag 3` or `query windows`,
+/// and `action <toml value>` for any hotkey action
+pub fn parse_command(line: &str) -> Result<Command, String> {
+    let line = line.trim();
+    let (name, arg) = match line.split_once(' ') {
+        Some((name, arg)) => (name, arg.trim()),
+        None => (line, ""),
+    };
+    match name {
+        "bind" => {
+            let (keys, action) = arg.split_once(' ').ok_or("bind needs keys and an action")?;
+            let (modifiers, key) = split_keys(keys);
+            return toml::from_str(&format!(
+                "modifiers = {modifiers:?}\nkey = {key:?}\naction = {action}"
+            ))
+            .map(Command::Bind)
+            .map_err(|e| e.message().to_owned());
+        }
+        "unbind" if !arg.is_empty() => {
+            let (modifiers, key) = split_keys(arg);
+            return Ok(Command::Unbind {
+                modifiers: modifiers.to_owned(),
+                key: key.to_owned(),
+            });
+        }
+        _ => (),
+    }
+    if name == "query" {
+        return Ok(Command::Query(match arg {
+            "tags" => Query::Tags,
+            "windows" => Query::Windows,
+            "focused" => Query::Focused,
+            "title" => Query::Title,
+            _ => return Err(format!("unknown query {arg:?}")),
+        }));
+    }
+    Ok(Command::Action(match name {
+        "switch-tag" => HotkeyAction::SwitchTag(parse_tag(arg)?),
+        "toggle-view-tag" => HotkeyAction::ToggleViewTag(parse_tag(arg)?),
+        "move-window" => HotkeyAction::MoveWindow(parse_tag(arg)?),
+        "toggle-tag" => HotkeyAction::ToggleTag(parse_tag(arg)?),
+        "move-window-and-follow" => HotkeyAction::MoveWindowAndFollow(parse_tag(arg)?),
+        "close-focused" => HotkeyAction::ExitFocusedWindow,
+        "set-ratio" => HotkeyAction::SetRatio(parse_arg(arg)?),
+        "change-ratio" => HotkeyAction::ChangeRatio(parse_arg(arg)?),
+        "cycle-ratio" => HotkeyAction::CycleRatio(parse_arg(arg)?),
+        "next-focus" => HotkeyAction::NextFocus(parse_arg(arg)?),
+        "next-tag" => HotkeyAction::NextTag(parse_arg(arg)?),
+        "swap-master" => HotkeyAction::SwapMaster,
+        "focus-monitor" => HotkeyAction::FocusMonitor(parse_arg(arg)?),
+        "spawn" if !arg.is_empty() => HotkeyAction::Spawn(SpawnCommand::Shell(arg.to_owned())),
+        "toggle-floating" => HotkeyAction::ToggleFloating,
+        "toggle-pin" => HotkeyAction::TogglePin,
+        "toggle-border" => HotkeyAction::ToggleBorder,
+        "raise" => HotkeyAction::Raise,
+        "lower" => HotkeyAction::Lower,
+        "toggle-fullscreen" => HotkeyAction::ToggleFullscreen,
+        "toggle-bar" => HotkeyAction::ToggleBar,
+        "toggle-show-desktop" => HotkeyAction::ToggleShowDesktop,
+        "toggle-scratchpad" if !arg.is_empty() => HotkeyAction::ToggleScratchpad(arg.to_owned()),
+        "reload-config" => HotkeyAction::ReloadConfig,
+        "restart" => HotkeyAction::Restart,
+        "quit" => HotkeyAction::Quit,
+        "action" => {
+            #[derive(Deserialize)]
+            struct Wrapper {
+                action: HotkeyAction,
+            }
+            toml::from_str::<Wrapper>(&format!("action = {arg}"))
+                .map_err(|e| e.message().to_owned())?
+                .action
+        }
+        _ => return Err(format!("unknown command {line:?}")),
+    }))
+}
+
+/// `MOD|SHIFT+x` into its modifiers and key
+fn split_keys(keys: &str) -> (&str, &str) {
+    keys.rsplit_once('+').unwrap_or(("", keys))
+}
+
+fn parse_arg<T: FromStr>(arg: &str) -> Result<T, String> {
+    arg.parse().map_err(|_| format!("bad argument {arg:?}"))
+}
+
+/// only checked against the tag count once it reaches the window manager
+fn parse_tag(arg: &str) -> Result<usize, String> {
+    match parse_arg(arg)? {
+        0 => Err("tags count from 1".to_owned()),
+        tag => Ok(tag),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn action(line: &str) -> HotkeyAction {
+        match parse_command(line) {
+            Ok(Command::Action(action)) => action,
+            Ok(_) => panic!("{line:?} isn't an action"),
+            Err(e) => panic!("{line:?} failed: {e}"),
+        }
+    }
+
+    #[test]
+    fn parses_commands_with_arguments() {
+        assert!(matches!(action("switch-tag 3"), HotkeyAction::SwitchTag(3)));
+        assert!(matches!(
+            action("  next-focus -1 "),
+            HotkeyAction::NextFocus(-1)
+        ));
+        assert!(matches!(action("swap-master"), HotkeyAction::SwapMaster));
+        match action("spawn firefox --new-window") {
+            HotkeyAction::Spawn(SpawnCommand::Shell(c)) => assert_eq!(c, "firefox --new-window"),
+            a => panic!("got {a:?}"),
+        }
+        assert!(matches!(
+            action("action { SwitchTag = 2 }"),
+            HotkeyAction::SwitchTag(2)
+        ));
+    }
+
+    #[test]
+    fn parses_queries_and_binds() {
+        assert!(matches!(
+            parse_command("query windows"),
+            Ok(Command::Query(Query::Windows))
+        ));
+        match parse_command("bind MOD|SHIFT+x \"SwapMaster\"") {
+            Ok(Command::Bind(bind)) => {
+                assert_eq!(
+                    (bind.modifiers.as_str(), bind.key.as_str()),
+                    ("MOD|SHIFT", "x")
+                );
+                assert!(matches!(bind.action[..], [HotkeyAction::SwapMaster]));
+            }
+            _ => panic!("bind didn't parse"),
+        }
+        match parse_command("unbind x") {
+            Ok(Command::Unbind { modifiers, key }) => {
+                assert_eq!((modifiers, key), ("".into(), "x".into()))
+            }
+            _ => panic!("unbind didn't parse"),
+        }
+    }
+
+    #[test]
+    fn rejects_bad_commands() {
+        for line in [
+            "",
+            "switch-tag",
+            "switch-tag 0",
+            "switch-tag x",
+            "query nothing",
+            "spawn",
+            "bind x",
+            "frobnicate",
+        ] {
+            assert!(parse_command(line).is_err(), "{line:?} parsed");
+        }
+    }
+
+    #[test]
+    fn events_start_with_their_name() {
+        let event = Event::Map {
+            window: 5,
+            tag: 2,
+            title: "a \"quoted\" title".to_owned(),
+        };
+        assert_eq!(
+            to_json(&event),
+            r#"{"event":"map","window":5,"tag":2,"title":"a \"quoted\" title"}"#
+        );
+        assert_eq!(
+            to_json(&Event::Focus { window: None }),
+            r#"{"event":"focus","window":null}"#
+        );
+    }
+
+    #[test]
+    fn workspaces_list_their_windows_only_in_the_tree() {
+        let workspace = Workspace {
+            id: 0,
+            kind: "workspace",
+            num: 1,
+            name: "1".to_owned(),
+            visible: true,
+            focused: true,
+            urgent: false,
+            rect: Rect {
+                x: 0,
+                y: 0,
+                width: 10,
+                height: 10,
+            },
+            output: None,
+            nodes: None,
+            floating_nodes: None,
+        };
+        let json = to_json(&workspace);
+        assert!(json.contains(r#""type":"workspace""#) && !json.contains("nodes"));
+        let json = to_json(&Workspace {
+            nodes: Some(Vec::new()),
+            floating_nodes: Some(Vec::new()),
+            ..workspace
+        });
+        assert!(json.ends_with(r#""output":null,"nodes":[],"floating_nodes":[]}"#));
+    }
+}
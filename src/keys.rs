@@ -1,42 +1,229 @@
-use std::collections::HashMap;
+//! hotkeys, mouse bindings and modes, and the actions they run
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    sync::OnceLock,
+};
 
 use serde::{Deserialize, Serialize};
 use x11rb::{
     connection::Connection,
-    errors::ReplyOrIdError,
-    protocol::xproto::{ConnectionExt, KeyButMask, KeyPressEvent, ModMask},
+    protocol::xproto::{ButtonPressEvent, ConnectionExt, KeyButMask, KeyPressEvent, ModMask},
 };
 use xkeysym::{KeyCode, Keysym};
 
-use crate::config::Config;
+use crate::{
+    config::{self, Config, HotkeyConfig, MouseContext, Trigger},
+    error::WmError,
+};
+
+/// lock modifiers (CapsLock and NumLock) that should not stop bindings from working,
+/// every binding is grabbed once per combination of them
+pub const LOCK_MASKS: [u16; 4] = [0, LOCK, NUM_LOCK, LOCK | NUM_LOCK];
+const LOCK: u16 = 2;
+const NUM_LOCK: u16 = 16;
+
+/// strips held buttons and lock modifiers from an event state
+pub fn clean_mask(state: KeyButMask) -> KeyButMask {
+    KeyButMask::from(u16::from(state) & 0xff & !LOCK_MASKS[3])
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum HotkeyAction {
-    Spawn(String),
+    Spawn(SpawnCommand),
     ExitFocusedWindow,
     SwitchTag(usize),
+    /// shows or hides the windows of a tag next to the active tag's
+    ToggleViewTag(usize),
     MoveWindow(usize),
+    /// puts the focused window on a tag as well, or takes it off again
+    ToggleTag(usize),
+    MoveWindowAndFollow(usize),
+    /// moves the focused window to the first empty tag, following it if true
+    MoveWindowToEmptyTag(bool),
     ChangeRatio(f32),
+    SetRatio(f32),
+    /// steps through `ratio_presets` by the given number of presets
+    CycleRatio(i16),
     NextFocus(i16),
     NextTag(i16),
     SwapMaster,
+    MoveTagToMonitor(i16),
+    SwapTagWithMonitor(i16),
+    ToggleSpanFullscreen,
+    FocusMonitor(i16),
+    EnterMode(String),
+    ExitMode,
+    Quit,
+    Restart,
+    ReloadConfig,
+    ToggleFloating,
+    /// keeps the focused window floating above the others, or lets it go back among them
+    TogglePin,
+    /// takes the border off the focused window, or puts it back
+    ToggleBorder,
+    /// puts the focused window on top of the others of its layer
+    Raise,
+    /// puts the focused window below the others of its layer
+    Lower,
+    ToggleFullscreen,
+    ToggleBar,
+    /// hides the windows of the shown tags to uncover the desktop, or brings them back
+    ToggleShowDesktop,
+    /// alt-tab through the windows of the tag, most recently used first
+    CycleWindows(i16),
+    /// moves the focused floating window by x and y pixels
+    MoveFloat(i16, i16),
+    /// grows the focused floating window by width and height pixels
+    ResizeFloat(i16, i16),
+    /// places the focused floating window on part of the workarea
+    SnapFloat(Snap),
+    /// moves the pointer out of the way to the bottom right corner of the monitor
+    BanishPointer,
+    /// lists all hotkeys until the next key press
+    ShowHotkeys,
+    /// marks the focused window with the letter typed next
+    SetMark,
+    /// focuses the window marked with the letter typed next
+    JumpToMark,
+    /// shows or hides the scratchpad of the given name, starting it if needed
+    ToggleScratchpad(String),
+    /// mouse binding only, moves the floating window under the pointer
+    DragMove,
+    /// mouse binding only, resizes the floating window under the pointer
+    DragResize,
+    /// mouse binding only, focuses the clicked window and passes the click on
+    FocusClicked,
+}
+
+impl HotkeyAction {
+    /// the tag the action is about, counting from 1
+    pub fn tag(&self) -> Option<usize> {
+        match self {
+            HotkeyAction::SwitchTag(tag)
+            | HotkeyAction::ToggleViewTag(tag)
+            | HotkeyAction::ToggleTag(tag)
+            | HotkeyAction::MoveWindow(tag)
+            | HotkeyAction::MoveWindowAndFollow(tag) => Some(*tag),
+            _ => None,
+        }
+    }
+}
+
+/// a command line run through `sh -c`, or a program started directly.
+/// environment variables in it are expanded when the config is read
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged, from = "SpawnCommandDeserialized")]
+pub enum SpawnCommand {
+    Shell(String),
+    Exec {
+        argv: Vec<String>,
+        #[serde(default)]
+        env: HashMap<String, String>,
+        cwd: Option<PathBuf>,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SpawnCommandDeserialized {
+    Shell(String),
+    Exec {
+        argv: Vec<String>,
+        #[serde(default)]
+        env: HashMap<String, String>,
+        cwd: Option<String>,
+    },
+}
+
+impl SpawnCommand {
+    /// the command with `$name` replaced by the program of that name in `apps`
+    pub fn with_apps(&self, apps: &BTreeMap<String, String>) -> SpawnCommand {
+        let expand = |s: &str| config::expand_with(s, |name| apps.get(name).cloned());
+        match self {
+            SpawnCommand::Shell(command) => SpawnCommand::Shell(expand(command)),
+            SpawnCommand::Exec { argv, env, cwd } => SpawnCommand::Exec {
+                argv: argv.iter().map(|a| expand(a)).collect(),
+                env: env.clone(),
+                cwd: cwd.clone(),
+            },
+        }
+    }
+}
+
+impl From<SpawnCommandDeserialized> for SpawnCommand {
+    fn from(command: SpawnCommandDeserialized) -> Self {
+        match command {
+            SpawnCommandDeserialized::Shell(command) => {
+                SpawnCommand::Shell(config::expand_env(&command))
+            }
+            SpawnCommandDeserialized::Exec { argv, env, cwd } => SpawnCommand::Exec {
+                argv: argv.iter().map(|a| config::expand_env(a)).collect(),
+                env: env
+                    .into_iter()
+                    .map(|(k, v)| (k, config::expand_env(&v)))
+                    .collect(),
+                cwd: cwd.map(|c| config::expand_env(&c).into()),
+            },
+        }
+    }
+}
+
+/// part of the workarea a floating window can be snapped to
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Snap {
+    Left,
+    Right,
+    Top,
+    Bottom,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    Center,
 }
 
 #[derive(Debug)]
 pub struct Hotkey {
     _sym: Keysym,
     mask: KeyButMask,
-    action: HotkeyAction,
+    trigger: Trigger,
+    actions: Vec<HotkeyAction>,
+    /// the binding as written in the config, for the cheat sheet
+    pub description: String,
     pub code: KeyCode,
     pub modifier: ModMask,
 }
 
+#[derive(Debug)]
+pub struct Mousebind {
+    mask: KeyButMask,
+    button: u8,
+    context: MouseContext,
+    action: HotkeyAction,
+}
+
+/// the key, modifiers and trigger a hotkey runs on
+type Keys = (Keysym, KeyButMask, Trigger);
+
 pub struct KeyHandler {
-    pub _sym_code: HashMap<Keysym, KeyCode>,
+    pub sym_code: HashMap<Keysym, KeyCode>,
+    /// the hotkeys of the config with the runtime ones in place
     pub hotkeys: Vec<Hotkey>,
+    /// hotkeys bound over IPC, `None` for keys unbound, put back in place after every reload
+    runtime: HashMap<Keys, Option<HotkeyConfig>>,
+    pub mousebinds: Vec<Mousebind>,
+    pub modes: HashMap<String, Vec<Hotkey>>,
+    /// names of the entered modes, the last one is active
+    pub mode_stack: Vec<String>,
+    /// a mark action waiting for its letter
+    pub pending_mark: Option<HotkeyAction>,
+    mod_key: KeyButMask,
 }
 
 impl KeyHandler {
-    pub fn new<C: Connection>(connection: &C, config: &Config) -> Result<Self, ReplyOrIdError> {
+    pub fn new<C: Connection>(connection: &C, config: &Config) -> Result<Self, WmError> {
         //get min-max code
         let min = connection.setup().min_keycode;
         let max = connection.setup().max_keycode;
@@ -49,85 +236,441 @@ impl KeyHandler {
         //get sym-code pairings
         let sym_code: HashMap<Keysym, KeyCode> = (min..=max)
             .filter_map(|x| {
-                if let Some(s) = xkeysym::keysym(
+                xkeysym::keysym(
                     x.into(),
                     0,
                     min.into(),
                     mapping.keysyms_per_keycode,
                     mapping.keysyms.as_slice(),
-                ) {
-                    Some((s, KeyCode::new(x.into())))
-                } else {
-                    None
-                }
+                )
+                .map(|s| (s, KeyCode::new(x.into())))
             })
             .collect();
 
         //get config hotkeys
+        let names = keysym_names();
+        let parse_hotkey = |c: HotkeyConfig| match parse_hotkey(names, &sym_code, config.mod_key, c)
+        {
+            Ok(h) => Some(h),
+            Err(e) => {
+                log::error!("{e} in config, ignoring hotkey");
+                None
+            }
+        };
         let hotkeys: Vec<Hotkey> = config
             .hotkeys
             .iter()
             .cloned()
-            .map(|c| {
-                let modi = c
-                    .modifiers
-                    .split("|")
-                    .map(|m| match m {
-                        "CONTROL" => KeyButMask::CONTROL,
-                        "SHIFT" => KeyButMask::SHIFT,
-                        "MOD" => KeyButMask::MOD4,
-                        _ => KeyButMask::default(),
-                    })
-                    .fold(KeyButMask::default(), |acc, m| acc | m);
-
-                let sym = match c.key.as_str() {
-                    "XK_Return" => Keysym::Return,
-                    "XF86_MonBrightnessUp" => Keysym::XF86_MonBrightnessUp,
-                    "XF86_MonBrightnessDown" => Keysym::XF86_MonBrightnessDown,
-                    "XF86_AudioRaiseVolume" => Keysym::XF86_AudioRaiseVolume,
-                    "XF86_AudioLowerVolume" => Keysym::XF86_AudioLowerVolume,
-                    "XF86_AudioMute" => Keysym::XF86_AudioMute,
-                    "XK_Left" => Keysym::Left,
-                    "XK_Right" => Keysym::Right,
-                    c => {
-                        let ch = match c.chars().next() {
-                            Some(c) => c,
-                            None => {
-                                log::error!("BAD KEYSYM {c}");
-                                char::default()
-                            }
-                        };
-                        Keysym::from_char(ch)
-                    }
-                };
-
-                Hotkey {
-                    _sym: sym,
-                    code: *sym_code.get(&sym).expect("expected sym to have code"),
-                    mask: modi,
-                    modifier: ModMask::from(modi.bits()),
-                    action: c.action,
+            .filter_map(parse_hotkey)
+            .collect();
+        report_conflicts(&hotkeys);
+
+        //get modes, escape leaves a mode unless it is bound inside it
+        let modes = config
+            .modes
+            .iter()
+            .map(|mode| {
+                let mut hotkeys = mode
+                    .hotkeys
+                    .iter()
+                    .cloned()
+                    .filter_map(parse_hotkey)
+                    .collect::<Vec<_>>();
+                if !hotkeys.iter().any(|h| h._sym == Keysym::Escape) {
+                    hotkeys.extend(parse_hotkey(HotkeyConfig {
+                        modifiers: String::new(),
+                        key: "Escape".to_string(),
+                        trigger: Trigger::Press,
+                        action: vec![HotkeyAction::ExitMode],
+                    }));
                 }
+                report_conflicts(&hotkeys);
+                (mode.name.clone(), hotkeys)
+            })
+            .collect();
+
+        let mousebinds = config
+            .mousebinds
+            .iter()
+            .cloned()
+            .map(|c| Mousebind {
+                mask: parse_modifiers(&c.modifiers, config.mod_key),
+                button: c.button,
+                context: c.context,
+                action: c.action,
             })
             .collect();
 
         Ok(KeyHandler {
-            _sym_code: sym_code,
+            sym_code,
             hotkeys,
+            runtime: HashMap::new(),
+            mousebinds,
+            modes,
+            mode_stack: Vec::new(),
+            pending_mark: None,
+            mod_key: config.mod_key,
+        })
+    }
+
+    /// builds the hotkeys of a reloaded config, keeping the ones bound and unbound at runtime
+    pub fn reload<C: Connection>(
+        &mut self,
+        connection: &C,
+        config: &Config,
+    ) -> Result<(), WmError> {
+        let runtime = std::mem::take(&mut self.runtime);
+        *self = KeyHandler::new(connection, config)?;
+        self.restore_runtime(runtime);
+        Ok(())
+    }
+
+    /// puts the hotkeys bound and unbound at runtime back in place of the config's
+    fn restore_runtime(&mut self, runtime: HashMap<Keys, Option<HotkeyConfig>>) {
+        for (keys, hotkey) in runtime {
+            if let Err(e) = self.apply_runtime(keys, hotkey.clone()) {
+                log::error!("{e}, dropping hotkey bound at runtime");
+                continue;
+            }
+            self.runtime.insert(keys, hotkey);
+        }
+    }
+
+    /// adds a hotkey outside of any mode, replacing the one on the same keys
+    pub fn bind(&mut self, config: HotkeyConfig) -> Result<(), String> {
+        let hotkey = parse_hotkey(keysym_names(), &self.sym_code, self.mod_key, config.clone())?;
+        let keys = (hotkey._sym, hotkey.mask, hotkey.trigger);
+        self.apply_runtime(keys, Some(config.clone()))?;
+        self.runtime.insert(keys, Some(config));
+        Ok(())
+    }
+
+    /// removes the hotkeys on the given keys, for both triggers
+    pub fn unbind(&mut self, modifiers: &str, key: &str) -> Result<(), String> {
+        let sym = parse_keysym(keysym_names(), key).ok_or(format!("unknown key name {key:?}"))?;
+        let mask = parse_modifiers(modifiers, self.mod_key);
+        if !self.hotkeys.iter().any(|h| (h._sym, h.mask) == (sym, mask)) {
+            return Err(format!("nothing is bound to {modifiers}+{key}"));
+        }
+        for trigger in [Trigger::Press, Trigger::Release] {
+            self.apply_runtime((sym, mask, trigger), None)?;
+            self.runtime.insert((sym, mask, trigger), None);
+        }
+        Ok(())
+    }
+
+    /// puts `hotkey` in place of the hotkeys on `keys`, only removing them when `None`
+    fn apply_runtime(&mut self, keys: Keys, hotkey: Option<HotkeyConfig>) -> Result<(), String> {
+        let hotkey = hotkey
+            .map(|c| parse_hotkey(keysym_names(), &self.sym_code, self.mod_key, c))
+            .transpose()?;
+        self.hotkeys.retain(|h| (h._sym, h.mask, h.trigger) != keys);
+        if let Some(hotkey) = hotkey {
+            log::info!("binding {}", hotkey.description);
+            self.hotkeys.push(hotkey);
+        }
+        Ok(())
+    }
+
+    fn get_registered_hotkey(
+        &self,
+        mask: KeyButMask,
+        code_raw: u32,
+        trigger: Trigger,
+    ) -> Option<&Hotkey> {
+        let hotkeys = match self.mode_stack.last() {
+            Some(mode) => &self.modes[mode],
+            None => &self.hotkeys,
+        };
+        hotkeys.iter().find(|h| {
+            // releasing a modifier key still has the modifier itself in its state
+            let mask_matches = mask == h.mask
+                || (trigger == Trigger::Release
+                    && h._sym.is_modifier_key()
+                    && mask.contains(h.mask));
+            h.trigger == trigger && mask_matches && code_raw == h.code.raw()
         })
     }
 
-    fn get_registered_hotkey(&self, mask: KeyButMask, code_raw: u32) -> Option<&Hotkey> {
-        self.hotkeys
+    /// one line per hotkey, like "MOD|SHIFT+q  ExitFocusedWindow"
+    pub fn describe_hotkeys(&self) -> Vec<String> {
+        self.hotkeys.iter().map(|h| h.description.clone()).collect()
+    }
+
+    pub fn get_keysym(&self, code: u8) -> Option<Keysym> {
+        self.sym_code
             .iter()
-            .find(|h| mask == h.mask && code_raw == h.code.raw())
+            .find(|(_, c)| c.raw() == code as u32)
+            .map(|(s, _)| *s)
     }
 
-    pub fn get_action(&self, event: KeyPressEvent) -> Option<HotkeyAction> {
-        if let Some(h) = self.get_registered_hotkey(event.state, event.detail as u32) {
-            Some(h.action.clone())
-        } else {
-            None
+    /// returns false if there is no such mode
+    pub fn enter_mode(&mut self, name: &str) -> bool {
+        if !self.modes.contains_key(name) {
+            log::error!("unknown mode {name}");
+            return false;
         }
+        self.mode_stack.push(name.to_owned());
+        true
+    }
+
+    /// returns true once no mode is active anymore
+    pub fn exit_mode(&mut self) -> bool {
+        self.mode_stack.pop();
+        self.mode_stack.is_empty()
+    }
+
+    /// key press and release events share the same type
+    pub fn get_actions(&self, event: KeyPressEvent, trigger: Trigger) -> Option<Vec<HotkeyAction>> {
+        self.get_registered_hotkey(clean_mask(event.state), event.detail as u32, trigger)
+            .map(|h| h.actions.clone())
+    }
+
+    pub fn get_mouse_action(
+        &self,
+        context: MouseContext,
+        event: ButtonPressEvent,
+    ) -> Option<HotkeyAction> {
+        let mask = clean_mask(event.state);
+        self.mousebinds
+            .iter()
+            .find(|m| m.context == context && m.button == event.detail && m.mask == mask)
+            .map(|m| m.action.clone())
+    }
+}
+
+/// maps X keysym names to keysyms, both with and without their `XK_` prefix,
+/// e.g. "XK_space" and "space", or "XF86XK_AudioMute", "XF86AudioMute" and "XF86_AudioMute".
+/// built on first use and kept for every reload after it
+pub(crate) fn keysym_names() -> &'static HashMap<String, Keysym> {
+    static NAMES: OnceLock<HashMap<String, Keysym>> = OnceLock::new();
+    NAMES.get_or_init(build_keysym_names)
+}
+
+fn build_keysym_names() -> HashMap<String, Keysym> {
+    // the ranges xkeysym has names for, scanning all 2^32 keysyms would be too slow
+    let ranges = [
+        0x0000..=0x0eff,
+        0x1300..=0x13ff,
+        0x2000..=0x20ff,
+        0xfd00..=0xffff,
+        0x0100_0000..=0x0100_2fff,
+        0x1000_fe00..=0x1000_ffff,
+        0x1004_ff00..=0x1004_ffff,
+        0x1005_ff00..=0x1005_ffff,
+        0x1008_1000..=0x1008_12ff,
+        0x1008_fe00..=0x1008_ffff,
+    ];
+    let mut names = HashMap::new();
+    ranges
+        .into_iter()
+        .flatten()
+        .map(Keysym::new)
+        .filter_map(|s| s.name().map(|n| (n, s)))
+        .for_each(|(name, sym)| {
+            if let Some(short) = name.strip_prefix("XK_") {
+                names.insert(short.to_owned(), sym);
+            } else if let Some(short) = name.strip_prefix("XF86XK_") {
+                names.insert(format!("XF86{short}"), sym);
+                names.insert(format!("XF86_{short}"), sym);
+            }
+            names.insert(name.to_owned(), sym);
+        });
+    names
+}
+
+/// the hotkey a config binding describes, looking its key up on the keyboard
+fn parse_hotkey(
+    names: &HashMap<String, Keysym>,
+    sym_code: &HashMap<Keysym, KeyCode>,
+    mod_key: KeyButMask,
+    c: HotkeyConfig,
+) -> Result<Hotkey, String> {
+    let modi = parse_modifiers(&c.modifiers, mod_key);
+    let sym = parse_keysym(names, &c.key).ok_or(format!("unknown key name {:?}", c.key))?;
+    let code = *sym_code
+        .get(&sym)
+        .ok_or(format!("key {:?} is not on the keyboard", c.key))?;
+
+    Ok(Hotkey {
+        _sym: sym,
+        code,
+        mask: modi,
+        trigger: c.trigger,
+        description: describe_hotkey(&c),
+        modifier: ModMask::from(modi.bits()),
+        actions: c.action,
+    })
+}
+
+/// logs hotkeys that can never run because an earlier one has the same keys
+fn report_conflicts(hotkeys: &[Hotkey]) {
+    hotkeys.iter().enumerate().for_each(|(i, first)| {
+        hotkeys[i + 1..]
+            .iter()
+            .filter(|h| h.code == first.code && h.mask == first.mask && h.trigger == first.trigger)
+            .for_each(|h| {
+                log::error!(
+                    "hotkey conflict: \"{}\" shadows \"{}\"",
+                    first.description,
+                    h.description
+                )
+            });
+    });
+}
+
+fn describe_hotkey(hotkey: &HotkeyConfig) -> String {
+    let keys = match hotkey.modifiers.is_empty() {
+        true => hotkey.key.clone(),
+        false => format!("{}+{}", hotkey.modifiers, hotkey.key),
+    };
+    let release = match hotkey.trigger {
+        Trigger::Press => "",
+        Trigger::Release => " (release)",
+    };
+    let actions = hotkey
+        .action
+        .iter()
+        .map(|a| format!("{a:?}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!("{keys:<24}{release} {actions}")
+}
+
+/// parses a key name from the config, falling back to the keysym of a single character
+fn parse_keysym(names: &HashMap<String, Keysym>, key: &str) -> Option<Keysym> {
+    if let Some(sym) = names.get(key) {
+        return Some(*sym);
+    }
+    let mut chars = key.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(Keysym::from_char(c)),
+        _ => None,
+    }
+}
+
+/// what is wrong with the modifiers and key of a binding, without a keyboard to look the key up on
+pub(crate) fn check_keys(
+    names: &HashMap<String, Keysym>,
+    modifiers: &str,
+    key: Option<&str>,
+) -> Vec<String> {
+    let mut problems = modifiers
+        .split("|")
+        .filter(|m| !m.is_empty() && *m != "MOD" && parse_modifier(m).is_none())
+        .map(|m| match m {
+            "MOD2" => "MOD2 is NumLock, which bindings ignore".to_owned(),
+            m => format!("unknown modifier {m:?}"),
+        })
+        .collect::<Vec<_>>();
+    if let Some(key) = key.filter(|k| parse_keysym(names, k).is_none()) {
+        problems.push(format!("unknown key name {key:?}"));
+    }
+    problems
+}
+
+/// parses modifiers like "MOD|SHIFT", with "MOD" standing for the configured mod key
+pub fn parse_modifiers(modifiers: &str, mod_key: KeyButMask) -> KeyButMask {
+    modifiers
+        .split("|")
+        .filter(|m| !m.is_empty())
+        .map(|m| match m {
+            "MOD" => mod_key,
+            m => parse_modifier(m).unwrap_or_else(|| {
+                log::error!("unknown modifier {m:?} in config");
+                KeyButMask::default()
+            }),
+        })
+        .fold(KeyButMask::default(), |acc, m| acc | m)
+}
+
+/// parses a single raw modifier, e.g. "SHIFT" or "MOD1",
+/// MOD2 is NumLock and stripped from events like CapsLock, so it is not one
+pub fn parse_modifier(modifier: &str) -> Option<KeyButMask> {
+    match modifier {
+        "CONTROL" => Some(KeyButMask::CONTROL),
+        "SHIFT" => Some(KeyButMask::SHIFT),
+        "MOD1" => Some(KeyButMask::MOD1),
+        "MOD3" => Some(KeyButMask::MOD3),
+        "MOD4" => Some(KeyButMask::MOD4),
+        "MOD5" => Some(KeyButMask::MOD5),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hotkey(modifiers: &str, key: &str, action: HotkeyAction) -> HotkeyConfig {
+        HotkeyConfig {
+            modifiers: modifiers.to_owned(),
+            key: key.to_owned(),
+            trigger: Trigger::Press,
+            action: vec![action],
+        }
+    }
+
+    /// a handler with the config's hotkeys, as after a reload, on a keyboard with t and q
+    fn handler() -> KeyHandler {
+        let sym_code =
+            HashMap::from([(Keysym::t, KeyCode::new(28)), (Keysym::q, KeyCode::new(24))]);
+        let hotkeys = [
+            hotkey("MOD", "t", HotkeyAction::Raise),
+            hotkey("MOD", "q", HotkeyAction::Quit),
+        ]
+        .into_iter()
+        .map(|c| parse_hotkey(keysym_names(), &sym_code, KeyButMask::MOD4, c).unwrap())
+        .collect();
+        KeyHandler {
+            sym_code,
+            hotkeys,
+            runtime: HashMap::new(),
+            mousebinds: Vec::new(),
+            modes: HashMap::new(),
+            mode_stack: Vec::new(),
+            pending_mark: None,
+            mod_key: KeyButMask::MOD4,
+        }
+    }
+
+    fn actions(handler: &KeyHandler) -> Vec<String> {
+        handler
+            .hotkeys
+            .iter()
+            .flat_map(|h| h.actions.iter().map(|a| format!("{a:?}")))
+            .collect()
+    }
+
+    #[test]
+    fn runtime_binds_survive_a_reload() {
+        let mut keys = handler();
+        keys.bind(hotkey("MOD", "t", HotkeyAction::Lower)).unwrap();
+        keys.unbind("MOD", "q").unwrap();
+        assert_eq!(actions(&keys), ["Lower"]);
+        assert!(keys.unbind("MOD", "q").is_err());
+
+        let runtime = std::mem::take(&mut keys.runtime);
+        let mut reloaded = handler();
+        reloaded.restore_runtime(runtime);
+        assert_eq!(actions(&reloaded), ["Lower"]);
+        assert_eq!(reloaded.runtime.len(), 3);
+    }
+
+    #[test]
+    fn keysym_names_are_built_once() {
+        assert!(std::ptr::eq(keysym_names(), keysym_names()));
+        assert_eq!(keysym_names().get("space"), Some(&Keysym::space));
+    }
+
+    #[test]
+    fn numlock_is_not_a_binding_modifier() {
+        let state = KeyButMask::MOD4 | KeyButMask::MOD2 | KeyButMask::LOCK | KeyButMask::BUTTON1;
+        assert_eq!(clean_mask(state), KeyButMask::MOD4);
+        assert_eq!(parse_modifier("MOD2"), None);
+        assert_eq!(
+            check_keys(keysym_names(), "MOD2|SHIFT", Some("a")),
+            ["MOD2 is NumLock, which bindings ignore"]
+        );
     }
 }
@@ -0,0 +1,187 @@
+//! hematite as a library, for building a customized window manager binary.
+//!
+//! the modules are layered:
+//! - [`state`] holds tags, monitors and windows, and tiles them, without talking to X
+//! - [`config`] is the `config.toml` format and the [`config::Config`] built from it,
+//!   [`check`] validates it for `--check-config`
+//! - [`keys`] turns hotkey and mouse binding configs into actions
+//! - [`actions`] and [`events`] apply the state to the X server and handle its events,
+//!   failing with an [`error::WmError`],
+//!   [`poll`] waits for them and for timers
+//! - [`session`] saves the window arrangement across restarts
+//! - [`ipc`], [`i3ipc`] and `dbus` (with the `dbus` feature) are the control interfaces,
+//!   [`watch`] sends them a reload when the config changes
+//! - [`signal`] turns the signals hematite handles into a pipe the main loop waits on
+//!
+//! the simplest custom binary builds a [`config::Config`] in code and hands it to [`run`]:
+//! ```no_run
+//! use hematite::config::{Config, ConfigDeserialized};
+//!
+//! let mut config = Config::from(ConfigDeserialized::default());
+//! config.ratio = 0.6;
+//! hematite::run(config, Default::default()).unwrap();
+//! ```
+//! note that `ReloadConfig`, which also runs on SIGUSR1 and when `config.toml` changes,
+//! still reads `config.toml`.
+
+pub mod actions;
+pub mod check;
+pub mod config;
+#[cfg(feature = "dbus")]
+pub mod dbus;
+pub mod error;
+pub mod events;
+pub mod i3ipc;
+pub mod ipc;
+pub mod keys;
+pub mod poll;
+pub mod session;
+pub mod signal;
+pub mod state;
+pub mod watch;
+
+use crate::{
+    actions::ConnectionHandler,
+    config::Config,
+    error::WmError,
+    events::{EventHandler, Shutdown},
+    keys::KeyHandler,
+    session::Session,
+    state::StateHandler,
+};
+use std::{
+    os::{fd::AsRawFd, unix::process::CommandExt},
+    process::Command,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use x11rb::{connection::Connection, protocol::xproto::ConnectionExt};
+
+/// how [`run`] starts, usually given by command line flags
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// takes over from a running window manager instead of refusing to start
+    pub replace: bool,
+}
+
+/// connects to the X server, becomes its window manager and runs until quit or restart
+pub fn run(config: Config, options: Options) -> Result<(), WmError> {
+    // installed first, so a signal during startup waits in the pipe instead of killing us
+    let signals = signal::Signals::install()?;
+    // children of the process before a restart may have exited while it was replaced
+    signal::reap_children();
+    let (conn, screen_num) = x11rb::connect(None)?;
+    let selection_owner = actions::acquire_wm_selection(&conn, screen_num, options.replace)?;
+    let conn_handler = ConnectionHandler::new(&conn, screen_num, &config)?;
+    let key_handler = KeyHandler::new(&conn, &config)?;
+    let mut manager = StateHandler::new(
+        conn_handler.create_monitors()?,
+        config.workspaces,
+        config.tag_count,
+    );
+    conn_handler.update_tag_settings(&mut manager);
+    let session = Session::load();
+    session.restore_monitors(&mut manager, |t| config.get_layout_name(t));
+
+    conn_handler.refresh(&manager)?;
+
+    let mut event_handler = EventHandler {
+        conn: conn_handler,
+        man: manager,
+        key: key_handler,
+        shutdown: None,
+        subscribers: Default::default(),
+        session,
+    };
+    event_handler.adopt_windows()?;
+
+    actions::run_hook("startup", &config.hooks.startup, &[]);
+
+    // requests from the control interfaces wake the loop through it
+    let waker = Arc::new(poll::EventFd::new()?);
+    let ipc = match ipc::listen(waker.clone()) {
+        Ok((rx, i3_path)) => {
+            if let Some(path) = i3_path {
+                event_handler.conn.set_i3_socket_path(&path)?;
+            }
+            Some(rx)
+        }
+        Err(e) => {
+            log::error!("couldn't open control socket {e:?}");
+            None
+        }
+    };
+
+    // redraws the status text every second, so a clock in it keeps going without any X events
+    let clock = poll::Timer::new(Duration::from_secs(1))?;
+
+    let mut last_save = Instant::now();
+    loop {
+        if clock.expirations() > 0 {
+            event_handler
+                .man
+                .monitors
+                .iter()
+                .try_for_each(|m| event_handler.conn.draw_status_bar(m))?;
+        }
+        if last_save.elapsed() >= session::SAVE_INTERVAL {
+            event_handler.save_session();
+            last_save = Instant::now();
+        }
+        waker.clear();
+        ipc.iter()
+            .flat_map(|rx| rx.try_iter())
+            .for_each(|request| event_handler.handle_ipc(request));
+        for signal in signals.pending() {
+            if let Err(e) = event_handler.handle_signal(signal) {
+                log::error!("{}", e.context(format!("handling signal {signal}")));
+            }
+        }
+
+        // the replies above may have read events along with them, which poll can't see
+        while let Some(event) = conn.poll_for_event()? {
+            let description = error::describe_event(&event);
+            if let Err(e) = event_handler.handle_event(event) {
+                log::error!("{}", e.context(format!("handling {description}")));
+            }
+        }
+
+        if let Some(shutdown) = event_handler.shutdown {
+            log::info!("shutting down: {shutdown:?}");
+            event_handler.save_session();
+            event_handler.conn.release_windows(&event_handler.man)?;
+            // gives up WM_Sn, so the next window manager doesn't need --replace
+            conn.destroy_window(selection_owner)?;
+            conn.flush()?;
+            // a socket another instance listens on is left alone
+            if ipc.is_some() {
+                ipc::remove_socket();
+            }
+            if shutdown == Shutdown::Restart {
+                restart();
+            }
+            return Ok(());
+        }
+
+        conn.flush()?;
+        poll::wait(&[
+            conn.stream().as_raw_fd(),
+            clock.as_raw_fd(),
+            signals.as_raw_fd(),
+            waker.as_raw_fd(),
+        ])?;
+    }
+}
+
+/// replaces the process with a fresh copy of the binary, picking up a new build and config
+fn restart() {
+    let exe = match std::env::current_exe() {
+        Ok(e) => e,
+        Err(e) => {
+            log::error!("couldn't find own executable {e:?}");
+            return;
+        }
+    };
+    let error = Command::new(exe).args(std::env::args().skip(1)).exec();
+    log::error!("couldn't restart {error:?}");
+}
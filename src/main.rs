@@ -1,69 +1,105 @@
 // Xephyr -br -ac -noreset -screen 800x600 :1
 
-mod actions;
-mod config;
-mod events;
-mod keys;
-mod state;
-use crate::{
-    actions::ConnectionHandler,
-    config::{Config, ConfigDeserialized},
-    events::EventHandler,
-    keys::KeyHandler,
-    state::*,
-};
-use std::{sync::mpsc, thread, time::Duration};
-use x11rb::{connection::Connection, errors::ReplyOrIdError};
+use hematite::config::{self, Config, ConfigDeserialized};
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    env_logger::Builder::from_default_env()
-        .target(env_logger::Target::Stdout)
-        .init();
+const USAGE: &str = "usage: hematite [--replace] [--config <path>] [--display <display>] \
+[--log-level <level>] [--check-config] [--print-default-config]";
 
-    let (conn, screen_num) = x11rb::connect(None)?;
-    let config = Config::from(ConfigDeserialized::new());
-    let conn_handler = ConnectionHandler::new(&conn, screen_num, &config)?;
-    let key_handler = KeyHandler::new(&conn, &config)?;
-    let manager = StateHandler::new(TilingInfo {
-        gap: config.spacing as u16,
-        ratio: config.ratio,
-        width: conn_handler.screen.width_in_pixels,
-        height: conn_handler.screen.height_in_pixels,
-        bar_height: conn_handler.bar.height,
-    });
+#[derive(Default)]
+struct Args {
+    replace: bool,
+    check_config: bool,
+    print_default_config: bool,
+    config: Option<String>,
+    display: Option<String>,
+    log_level: Option<String>,
+}
 
-    conn_handler.draw_bar(&manager, None)?;
+impl Args {
+    fn parse() -> Result<Self, String> {
+        let mut parsed = Args::default();
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let value = match arg.as_str() {
+                "--replace" => {
+                    parsed.replace = true;
+                    continue;
+                }
+                "--check-config" => {
+                    parsed.check_config = true;
+                    continue;
+                }
+                "--print-default-config" => {
+                    parsed.print_default_config = true;
+                    continue;
+                }
+                "--config" => &mut parsed.config,
+                "--display" => &mut parsed.display,
+                "--log-level" => &mut parsed.log_level,
+                _ => return Err(format!("unknown argument {arg:?}")),
+            };
+            match args.next() {
+                Some(v) => *value = Some(v),
+                None => return Err(format!("{arg} needs a value")),
+            }
+        }
+        Ok(parsed)
+    }
+}
 
-    let mut event_handler = EventHandler {
-        conn: &conn_handler,
-        man: manager,
-        key: key_handler,
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = match Args::parse() {
+        Ok(a) => a,
+        Err(e) => {
+            eprintln!("{e}\n{USAGE}");
+            std::process::exit(2);
+        }
     };
+    if let Some(display) = &args.display {
+        // SAFETY: nothing else is running yet. set for the connection, the font check
+        // and everything spawned, so programs open on the same display
+        unsafe { std::env::set_var("DISPLAY", display) };
+    }
+    if let Some(path) = args.config {
+        config::set_config_path(path.into());
+    }
 
-    let (tx, rx) = mpsc::channel();
+    if args.print_default_config {
+        print!("{}", ConfigDeserialized::default_document());
+        return Ok(());
+    }
+    if args.check_config {
+        std::process::exit(check_config());
+    }
 
-    thread::spawn(move || -> Result<(), ReplyOrIdError> {
-        loop {
-            let _ = tx.send(1);
-            thread::sleep(Duration::from_secs(1));
-        }
-    });
+    let mut logger = env_logger::Builder::from_default_env();
+    if let Some(level) = &args.log_level {
+        logger.parse_filters(level);
+    }
+    logger.target(env_logger::Target::Stdout).init();
 
-    loop {
-        if let Ok(_) = rx.try_recv() {
-            conn_handler.draw_bar(&event_handler.man,event_handler.man.get_focus())?;
-            conn_handler.draw_status_bar()?;
-        }
-        conn.flush()?;
-        let event = conn.wait_for_event()?;
-        let mut event_as_option = Some(event);
+    let options = hematite::Options {
+        replace: args.replace,
+    };
+    if let Err(e) = hematite::run(Config::from(ConfigDeserialized::new()), options) {
+        log::error!("{e}");
+        std::process::exit(1);
+    }
+    Ok(())
+}
 
-        while let Some(event) = event_as_option {
-            match event_handler.handle_event(event) {
-                Ok(_) => (),
-                Err(e) => log::error!("{}", e),
-            };
-            event_as_option = conn.poll_for_event()?;
-        }
+/// prints the problems with `config.toml`, returning the exit code
+fn check_config() -> i32 {
+    let path = match config::config_path() {
+        Some(p) => p,
+        None => return 1,
+    };
+    let problems = hematite::check::check_config(&path);
+    if problems.is_empty() {
+        println!("{} is fine", path.display());
+        return 0;
     }
+    problems.iter().for_each(|p| eprintln!("{p}"));
+    eprintln!("{} problems in {}", problems.len(), path.display());
+    1
 }
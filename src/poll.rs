@@ -0,0 +1,141 @@
+//! waiting on several file descriptors at once, the X connection, timers, signals and the
+//! control interfaces, so the main loop wakes for whichever is ready first
+
+use std::{
+    io,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    time::Duration,
+};
+
+/// a timerfd that becomes readable every `interval`
+pub struct Timer {
+    fd: OwnedFd,
+}
+
+impl Timer {
+    pub fn new(interval: Duration) -> io::Result<Self> {
+        // SAFETY: plain syscall, the returned fd is checked before it is owned
+        let fd = unsafe {
+            libc::timerfd_create(
+                libc::CLOCK_MONOTONIC,
+                libc::TFD_NONBLOCK | libc::TFD_CLOEXEC,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: fd was just created and nothing else owns it
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        let interval = libc::timespec {
+            tv_sec: interval.as_secs() as libc::time_t,
+            tv_nsec: interval.subsec_nanos() as libc::c_long,
+        };
+        let spec = libc::itimerspec {
+            it_interval: interval,
+            it_value: interval,
+        };
+        // SAFETY: spec outlives the call, the old value isn't asked for
+        if unsafe { libc::timerfd_settime(fd.as_raw_fd(), 0, &spec, std::ptr::null_mut()) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Timer { fd })
+    }
+
+    /// how often the timer went off since the last call, without blocking
+    pub fn expirations(&self) -> u64 {
+        let mut count = 0u64;
+        // SAFETY: reads at most the 8 bytes of count
+        let read = unsafe {
+            libc::read(
+                self.fd.as_raw_fd(),
+                &mut count as *mut u64 as *mut libc::c_void,
+                size_of::<u64>(),
+            )
+        };
+        match read {
+            8 => count,
+            _ => 0,
+        }
+    }
+}
+
+impl AsRawFd for Timer {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+/// an eventfd other threads make readable to wake the main loop
+pub struct EventFd {
+    fd: OwnedFd,
+}
+
+impl EventFd {
+    pub fn new() -> io::Result<Self> {
+        // SAFETY: plain syscall, the returned fd is checked before it is owned
+        let fd = unsafe { libc::eventfd(0, libc::EFD_NONBLOCK | libc::EFD_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: fd was just created and nothing else owns it
+        Ok(EventFd {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+        })
+    }
+
+    /// makes it readable until the next [`EventFd::clear`]
+    pub fn wake(&self) -> io::Result<()> {
+        let one = 1u64;
+        // SAFETY: writes the 8 bytes of one
+        let written = unsafe {
+            libc::write(
+                self.fd.as_raw_fd(),
+                &one as *const u64 as *const libc::c_void,
+                size_of::<u64>(),
+            )
+        };
+        match written {
+            8 => Ok(()),
+            _ => Err(io::Error::last_os_error()),
+        }
+    }
+
+    /// makes it unreadable again, without blocking
+    pub fn clear(&self) {
+        let mut count = 0u64;
+        // SAFETY: reads at most the 8 bytes of count
+        unsafe {
+            libc::read(
+                self.fd.as_raw_fd(),
+                &mut count as *mut u64 as *mut libc::c_void,
+                size_of::<u64>(),
+            )
+        };
+    }
+}
+
+impl AsRawFd for EventFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+/// blocks until one of `fds` can be read, returning early when a signal arrives
+pub fn wait(fds: &[RawFd]) -> io::Result<()> {
+    let mut fds = fds
+        .iter()
+        .map(|fd| libc::pollfd {
+            fd: *fd,
+            events: libc::POLLIN,
+            revents: 0,
+        })
+        .collect::<Vec<_>>();
+    // SAFETY: the pointer and length describe the vector
+    if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) } < 0 {
+        let error = io::Error::last_os_error();
+        if error.kind() != io::ErrorKind::Interrupted {
+            return Err(error);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,260 @@
+//! the window arrangement kept across restarts in
+//! `$XDG_STATE_HOME/hematite/session-<display>.toml`
+
+use crate::state::{StateHandler, WindowGroup};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashSet, time::Duration};
+
+/// how often the session is saved besides on exit
+pub const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Session {
+    #[serde(default)]
+    pub monitors: Vec<MonitorSession>,
+    #[serde(default)]
+    pub tags: Vec<TagSession>,
+    #[serde(default)]
+    pub windows: Vec<WindowSession>,
+    /// the windows being adopted after a restart, while entries may go to a window of
+    /// the same class. `None` once they are all managed
+    #[serde(skip)]
+    adopting: Option<HashSet<u32>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MonitorSession {
+    pub name: String,
+    /// counting from 1
+    pub tag: usize,
+}
+
+/// how a shown tag was tiled
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TagSession {
+    /// counting from 1
+    pub tag: usize,
+    /// its layout command, `floating` or `master-stack`
+    pub layout: String,
+    pub ratio: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WindowSession {
+    pub id: u32,
+    pub class: String,
+    /// counting from 1
+    pub tag: usize,
+    /// the other tags the window is on, counting from 1
+    #[serde(default)]
+    pub also_on: Vec<usize>,
+    pub floating: bool,
+    pub fullscreen: bool,
+    pub x: i16,
+    pub y: i16,
+    pub width: u16,
+    pub height: u16,
+}
+
+/// `session-<display>.toml`, so instances on other displays don't share a session
+fn file_name() -> String {
+    format!("session-{}.toml", crate::ipc::display_number())
+}
+
+impl Session {
+    pub fn load() -> Self {
+        let dirs = xdg::BaseDirectories::with_prefix("hematite");
+        let path = match dirs.get_state_file(file_name()) {
+            Some(p) => p,
+            None => return Self::default(),
+        };
+        let session = match std::fs::read_to_string(&path) {
+            Ok(s) => s,
+            Err(_) => return Self::default(),
+        };
+        match toml::from_str(&session) {
+            Ok(s) => s,
+            Err(e) => {
+                log::error!("bad session file {path:?} {e}");
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self) {
+        let dirs = xdg::BaseDirectories::with_prefix("hematite");
+        let path = match dirs.place_state_file(file_name()) {
+            Ok(p) => p,
+            Err(e) => {
+                log::error!("cant create session file with error {e:?}");
+                return;
+            }
+        };
+        let result = toml::to_string(self)
+            .map_err(|e| e.to_string())
+            .and_then(|s| std::fs::write(&path, s).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            log::error!("couldn't save session to {path:?} {e}");
+        }
+    }
+
+    /// the arrangement of every managed window, `class` giving the WM_CLASS of a window and
+    /// `layout` what tiles a tag
+    pub fn capture<'a>(
+        state: &StateHandler,
+        mut class: impl FnMut(u32) -> String,
+        layout: impl Fn(usize) -> &'a str,
+    ) -> Self {
+        Session {
+            monitors: state
+                .monitors
+                .iter()
+                .map(|m| MonitorSession {
+                    name: m.name.clone(),
+                    tag: m.active_tag + 1,
+                })
+                .collect(),
+            tags: state
+                .monitors
+                .iter()
+                .map(|m| TagSession {
+                    tag: m.active_tag + 1,
+                    layout: layout(m.active_tag).to_owned(),
+                    ratio: m.tiling.ratio,
+                })
+                .collect(),
+            windows: state
+                .tags
+                .iter()
+                .enumerate()
+                .flat_map(|(i, t)| t.windows.iter().map(move |w| (i, w)))
+                .map(|(i, w)| WindowSession {
+                    id: w.window,
+                    class: class(w.window),
+                    tag: i + 1,
+                    also_on: state
+                        .also_on
+                        .get(&w.window)
+                        .map(|s| s.iter().map(|t| t + 1).collect())
+                        .unwrap_or_default(),
+                    floating: w.group_before_fullscreen.unwrap_or(w.group) == WindowGroup::Floating,
+                    fullscreen: w.fullscreen,
+                    x: w.x,
+                    y: w.y,
+                    width: w.width,
+                    height: w.height,
+                })
+                .collect(),
+            adopting: None,
+        }
+    }
+
+    /// shows the saved tags on monitors of the same name, and gives every monitor the ratio
+    /// its tag had if the tag is still tiled by the same `layout`
+    pub fn restore_monitors<'a>(
+        &self,
+        state: &mut StateHandler,
+        layout: impl Fn(usize) -> &'a str,
+    ) {
+        for saved in &self.monitors {
+            let index = match state.monitors.iter().position(|m| m.name == saved.name) {
+                Some(i) => i,
+                None => continue,
+            };
+            let tag = saved.tag.wrapping_sub(1);
+            if tag < state.tags.len() && state.get_monitor_of_tag(tag).is_none() {
+                state.monitors[index].active_tag = tag;
+            }
+        }
+        for monitor in state.monitors.iter_mut() {
+            let tag = monitor.active_tag;
+            let saved = self
+                .tags
+                .iter()
+                .find(|t| t.tag == tag + 1 && t.layout == layout(tag));
+            if let Some(saved) = saved {
+                monitor.tiling.ratio = saved.ratio.clamp(0.15, 0.85);
+            }
+        }
+    }
+
+    /// from now on until [`Session::end_adoption`] the saved windows may go to windows of
+    /// the same class, other than `windows` which take their own
+    pub fn start_adoption(&mut self, windows: &[u32]) {
+        self.adopting = Some(windows.iter().copied().collect());
+    }
+
+    /// forgets the windows that weren't adopted, so windows mapped later start afresh
+    pub fn end_adoption(&mut self) {
+        self.adopting = None;
+        self.windows.clear();
+    }
+
+    /// takes the saved arrangement of a window, the same window if it survived, otherwise
+    /// while adopting the first one of its class that no window being adopted is saved as.
+    /// every entry is only taken once
+    pub fn take_window(&mut self, window: u32, class: &str) -> Option<WindowSession> {
+        let index = self
+            .windows
+            .iter()
+            .position(|w| w.id == window)
+            .or_else(|| {
+                let adopting = self.adopting.as_ref()?;
+                self.windows.iter().position(|w| {
+                    !class.is_empty() && w.class == class && !adopting.contains(&w.id)
+                })
+            })?;
+        Some(self.windows.remove(index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn saved(id: u32, class: &str, tag: usize) -> WindowSession {
+        WindowSession {
+            id,
+            class: class.to_owned(),
+            tag,
+            also_on: Vec::new(),
+            floating: false,
+            fullscreen: false,
+            x: 0,
+            y: 0,
+            width: 100,
+            height: 100,
+        }
+    }
+
+    fn session() -> Session {
+        Session {
+            windows: vec![saved(1, "term", 1), saved(2, "term", 2), saved(3, "web", 3)],
+            ..Session::default()
+        }
+    }
+
+    #[test]
+    fn windows_match_by_class_only_while_adopting() {
+        let mut session = session();
+        assert!(session.take_window(7, "web").is_none());
+        assert_eq!(session.take_window(3, "web").map(|w| w.tag), Some(3));
+        assert!(session.take_window(3, "web").is_none());
+
+        // window 2 is adopted as well, so its entry isn't given to a stranger
+        session.start_adoption(&[2, 8]);
+        assert_eq!(session.take_window(8, "term").map(|w| w.id), Some(1));
+        assert!(session.take_window(9, "term").is_none());
+        assert_eq!(session.take_window(2, "term").map(|w| w.tag), Some(2));
+        session.end_adoption();
+        assert!(session.windows.is_empty());
+    }
+
+    #[test]
+    fn leftover_windows_are_forgotten_after_adopting() {
+        let mut session = session();
+        session.start_adoption(&[]);
+        session.end_adoption();
+        assert!(session.take_window(1, "term").is_none());
+    }
+}
@@ -0,0 +1,147 @@
+//! the signals hematite handles, written to a pipe by their handlers
+//! so the main loop wakes for them along with X events, and reaping children on SIGCHLD
+
+use std::{
+    io,
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+        unix::process::ExitStatusExt,
+    },
+    process::ExitStatus,
+    sync::atomic::{AtomicI32, Ordering},
+};
+
+/// reloading the config, quitting, and children to reap
+pub const HANDLED: [libc::c_int; 5] = [
+    libc::SIGUSR1,
+    libc::SIGHUP,
+    libc::SIGTERM,
+    libc::SIGINT,
+    libc::SIGCHLD,
+];
+
+/// the write end of the pipe, for the handler
+static PIPE: AtomicI32 = AtomicI32::new(-1);
+
+extern "C" fn forward(signal: libc::c_int) {
+    let byte = signal as u8;
+    // SAFETY: write and errno are async-signal-safe, a full pipe drops the signal
+    // while the ones already in it are still to be handled
+    unsafe {
+        let errno = *libc::__errno_location();
+        libc::write(
+            PIPE.load(Ordering::Relaxed),
+            &byte as *const u8 as *const libc::c_void,
+            1,
+        );
+        *libc::__errno_location() = errno;
+    }
+}
+
+/// the read end of the pipe the handled signals arrive on
+pub struct Signals {
+    read: OwnedFd,
+    _write: OwnedFd,
+}
+
+impl Signals {
+    /// installs the handlers, until then the signals keep their default action
+    pub fn install() -> io::Result<Self> {
+        let mut fds = [0; 2];
+        // SAFETY: fds has room for both ends
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK | libc::O_CLOEXEC) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: both fds were just created and nothing else owns them
+        let (read, write) = unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+        PIPE.store(write.as_raw_fd(), Ordering::Relaxed);
+        for signal in HANDLED {
+            // SAFETY: the action is zeroed and then filled in, the handler only writes to the pipe
+            let result = unsafe {
+                let mut action: libc::sigaction = std::mem::zeroed();
+                action.sa_sigaction = forward as extern "C" fn(libc::c_int) as libc::sighandler_t;
+                // system calls of the other threads go on instead of failing with EINTR
+                action.sa_flags = libc::SA_RESTART | libc::SA_NOCLDSTOP;
+                libc::sigemptyset(&mut action.sa_mask);
+                libc::sigaction(signal, &action, std::ptr::null_mut())
+            };
+            if result < 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(Signals {
+            read,
+            _write: write,
+        })
+    }
+
+    /// the signals that came since the last call, without blocking
+    pub fn pending(&self) -> Vec<libc::c_int> {
+        let mut signals = Vec::new();
+        let mut buffer = [0u8; 64];
+        loop {
+            // SAFETY: reads at most the length of buffer
+            let read = unsafe {
+                libc::read(
+                    self.read.as_raw_fd(),
+                    buffer.as_mut_ptr() as *mut libc::c_void,
+                    buffer.len(),
+                )
+            };
+            if read <= 0 {
+                return signals;
+            }
+            signals.extend(buffer[..read as usize].iter().map(|s| *s as libc::c_int));
+        }
+    }
+}
+
+/// waits for every child that has exited, spawned commands and hooks as well as the ones
+/// inherited across a restart, so they don't linger as zombies. the ones that failed are logged
+pub fn reap_children() {
+    loop {
+        let mut status = 0;
+        // SAFETY: plain syscall, status outlives it
+        let pid = unsafe { libc::waitpid(-1, &mut status, libc::WNOHANG) };
+        if pid <= 0 {
+            return;
+        }
+        let status = ExitStatus::from_raw(status);
+        match status.success() {
+            true => log::debug!("child {pid} exited"),
+            false => log::warn!("child {pid} exited with {status}"),
+        }
+    }
+}
+
+impl Drop for Signals {
+    /// the signals get their default action back before the pipe closes
+    fn drop(&mut self) {
+        for signal in HANDLED {
+            // SAFETY: plain syscall
+            unsafe { libc::signal(signal, libc::SIG_DFL) };
+        }
+        PIPE.store(-1, Ordering::Relaxed);
+    }
+}
+
+impl AsRawFd for Signals {
+    fn as_raw_fd(&self) -> RawFd {
+        self.read.as_raw_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signals_arrive_on_the_pipe() {
+        let signals = Signals::install().unwrap();
+        // SAFETY: plain syscall, the handler is installed
+        unsafe { libc::raise(libc::SIGUSR1) };
+        // children of other tests may add a SIGCHLD
+        assert!(signals.pending().contains(&libc::SIGUSR1));
+        assert!(!signals.pending().contains(&libc::SIGUSR1));
+    }
+}
@@ -1,6 +1,19 @@
-use std::fmt::Debug;
-use x11rb::{errors::ReplyOrIdError};
+//! tags, monitors and windows, and the master-stack tiling of them
+
+use crate::{
+    config::{FloatPlacement, MonitorRef, Size, WorkspaceModel},
+    error::WmError,
+    keys::Snap,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+};
 type Window = u32;
+
+/// how far each cascading floating window is placed from the last one
+const CASCADE_STEP: i32 = 32;
+
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub enum WindowGroup {
     Master,
@@ -17,10 +30,34 @@ pub struct WindowState {
     pub width: u16,
     pub height: u16,
     pub(crate) group: WindowGroup,
+    pub fullscreen: bool,
+    /// the group it goes back to when it leaves fullscreen, set while fullscreen
+    pub(crate) group_before_fullscreen: Option<WindowGroup>,
+    pub fullscreen_monitors: Option<[usize; 4]>,
+    /// overrides the configured border size
+    pub border_width: Option<u32>,
+    /// overrides the unfocused border color
+    pub border_color: Option<u32>,
+    /// asking for attention through WM_HINTS or _NET_WM_STATE, until it is focused
+    pub urgent: bool,
+    /// set by a rule, kept whether focused or not
+    pub opacity: Option<f32>,
+    /// windows started from this one swallow it
+    pub swallow: bool,
+    /// where the window was when it last floated, taken back when it floats again
+    pub float_geometry: Option<(i16, i16, u16, u16)>,
+    /// kept above the other floating windows while floating
+    pub pinned: bool,
+    /// drawn without a border, whatever its size
+    pub borderless: bool,
+    /// has a titlebar drawn on its frame, above the window
+    pub titlebar: bool,
+    /// held by a tag without tiling, so it floats whatever its group. set on every refresh
+    pub on_floating_tag: bool,
 }
 
 impl WindowState {
-    pub fn new(window: Window, frame_window: Window) -> Result<WindowState, ReplyOrIdError> {
+    pub fn new(window: Window, frame_window: Window) -> Result<WindowState, WmError> {
         Ok(WindowState {
             window,
             frame_window,
@@ -29,8 +66,27 @@ impl WindowState {
             width: 100,
             height: 100,
             group: WindowGroup::Stack,
+            fullscreen: false,
+            group_before_fullscreen: None,
+            fullscreen_monitors: None,
+            border_width: None,
+            border_color: None,
+            urgent: false,
+            opacity: None,
+            swallow: false,
+            float_geometry: None,
+            pinned: false,
+            borderless: false,
+            titlebar: false,
+            on_floating_tag: false,
         })
     }
+
+    /// floating by its own group or because its tag doesn't tile
+    pub fn is_floating(&self) -> bool {
+        self.group == WindowGroup::Floating || self.on_floating_tag
+    }
+
     pub fn print(&self) {
         log::debug!(
             "id {} fid {} x {} y {} w {} h {} g {:?}",
@@ -45,10 +101,52 @@ impl WindowState {
     }
 }
 
+/// a set of tags, a bit for each, growing with the highest tag put in it
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct TagSet {
+    bits: Vec<u64>,
+}
+
+impl TagSet {
+    pub fn contains(&self, tag: usize) -> bool {
+        self.bits
+            .get(tag / u64::BITS as usize)
+            .is_some_and(|b| b & 1 << (tag % u64::BITS as usize) != 0)
+    }
+
+    pub fn set(&mut self, tag: usize, on: bool) {
+        let (word, bit) = (tag / u64::BITS as usize, tag % u64::BITS as usize);
+        if word >= self.bits.len() {
+            if !on {
+                return;
+            }
+            self.bits.resize(word + 1, 0);
+        }
+        match on {
+            true => self.bits[word] |= 1 << bit,
+            false => self.bits[word] &= !(1 << bit),
+        }
+        while self.bits.last() == Some(&0) {
+            self.bits.pop();
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bits.is_empty()
+    }
+
+    /// the tags in the set, lowest first
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.bits.len() * u64::BITS as usize).filter(|t| self.contains(*t))
+    }
+}
+
 pub struct Tag {
     tag: usize,
     pub focus: Option<u32>,
     pub windows: Vec<WindowState>,
+    /// windows in the order they were last focused, most recent first
+    pub mru: Vec<Window>,
 }
 impl Tag {
     fn new(tag: usize) -> Self {
@@ -56,80 +154,718 @@ impl Tag {
             tag,
             focus: None,
             windows: Vec::new(),
+            mru: Vec::new(),
         }
     }
 }
 
+/// an alt-tab cycle through the most recently used windows of a tag in progress
+#[derive(Clone, PartialEq, Debug)]
+pub struct Cycle {
+    pub overlay: Window,
+    pub tag: usize,
+    pub windows: Vec<Window>,
+    pub index: usize,
+}
+
+/// size constraints from WM_NORMAL_HINTS
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct SizeHints {
+    /// the size the client asks for
+    pub size: Option<(u16, u16)>,
+    pub min: (u16, u16),
+    pub max: Option<(u16, u16)>,
+    pub base: (u16, u16),
+    pub increment: (u16, u16),
+}
+
+impl SizeHints {
+    pub fn apply(&self, width: i32, height: i32) -> (u16, u16) {
+        let constrain = |size: i32, min: u16, max: Option<u16>, base: u16, inc: u16| {
+            let mut size = size.max(min.max(1) as i32);
+            if inc > 1 {
+                size = (base as i32 + (size - base as i32) / inc as i32 * inc as i32)
+                    .max(min.max(1) as i32);
+            }
+            if let Some(max) = max.filter(|m| *m > 0) {
+                size = size.min(max as i32);
+            }
+            size.min(u16::MAX as i32) as u16
+        };
+        (
+            constrain(
+                width,
+                self.min.0,
+                self.max.map(|m| m.0),
+                self.base.0,
+                self.increment.0,
+            ),
+            constrain(
+                height,
+                self.min.1,
+                self.max.map(|m| m.1),
+                self.base.1,
+                self.increment.1,
+            ),
+        )
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DragKind {
+    Move,
+    /// resizing from the corner nearest to the pointer
+    Resize {
+        left: bool,
+        top: bool,
+        hints: SizeHints,
+    },
+}
+
+/// an interactive mouse move or resize of a floating window in progress
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Drag {
+    pub window: Window,
+    pub kind: DragKind,
+    pub pointer_x: i16,
+    pub pointer_y: i16,
+    pub x: i16,
+    pub y: i16,
+    pub width: u16,
+    pub height: u16,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub struct TilingInfo {
     pub gap: u16,
     pub ratio: f32,
+    pub bar_height: u16,
+    pub show_bar: bool,
+    pub padding: Edges,
+}
+
+/// `base` moved by `by` pixels, stopping at the edge of the coordinate space
+fn offset(base: i16, by: u16) -> i16 {
+    (base as i32 + by as i32).min(i16::MAX as i32) as i16
+}
+
+/// space reserved on each edge of a monitor
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Edges {
+    pub left: u16,
+    pub right: u16,
+    pub top: u16,
+    pub bottom: u16,
+}
+
+/// an unmanaged panel window with its `_NET_WM_STRUT` values relative to the root window
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Dock {
+    pub window: Window,
+    pub x: i16,
+    pub y: i16,
+    pub strut: Edges,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Monitor {
+    pub name: String,
+    pub x: i16,
+    pub y: i16,
     pub width: u16,
     pub height: u16,
-    pub bar_height: u16,
+    /// the tag new windows go to
+    pub active_tag: usize,
+    /// tags toggled into view next to the active one, their windows tiled together with its own
+    pub extra_tags: Vec<usize>,
+    pub bar: WindowState,
+    pub tiling: TilingInfo,
+    pub strut: Edges,
+}
+
+impl Monitor {
+    /// the active tag followed by the ones toggled into view
+    pub fn viewed_tags(&self) -> impl Iterator<Item = usize> + '_ {
+        std::iter::once(self.active_tag).chain(self.extra_tags.iter().copied())
+    }
+}
+
+/// the monitors and the windows shown on them, as tiling leaves them
+#[derive(Clone, PartialEq, Debug)]
+pub struct Layout {
+    monitors: Vec<Monitor>,
+    windows: Vec<WindowState>,
 }
 
 pub struct StateHandler {
     pub tags: Vec<Tag>,
-    pub active_tag: usize,
-    pub tiling: TilingInfo,
+    pub monitors: Vec<Monitor>,
+    pub active_monitor: usize,
+    pub workspaces: WorkspaceModel,
+    /// tags shown on each monitor, with per-monitor tags also the size of every monitor's range
+    pub tag_count: usize,
+    pub docks: Vec<Dock>,
+    /// tag last shown on each disconnected monitor, by output name
+    pub monitor_history: HashMap<String, usize>,
+    pub drag: Option<Drag>,
+    pub cycle: Option<Cycle>,
+    /// overlay listing the hotkeys while it is shown
+    pub cheat_sheet: Option<Window>,
+    /// vim style marks, kept when their window moves between tags
+    pub marks: HashMap<char, Window>,
+    /// hidden windows by the window that took their place
+    pub swallowed: HashMap<Window, WindowState>,
+    /// windows a rule keeps from being managed, only passed through
+    pub unmanaged: Vec<Window>,
+    /// hidden scratchpad windows, kept outside of the tags
+    pub scratch: Vec<WindowState>,
+    /// scratchpad windows by scratchpad name, whether shown or hidden
+    pub scratchpads: HashMap<String, Window>,
+    /// spacing of tags that replace the one of their monitor, by tag counting from 0 within
+    /// the tags of a monitor
+    pub tag_spacing: HashMap<usize, Size>,
+    /// tags without tiling, by tag counting from 0 within the tags of a monitor
+    pub floating_tags: HashSet<usize>,
+    /// the tags windows are also on besides the one holding them, only for windows on several
+    pub also_on: HashMap<Window, TagSet>,
+    /// the windows of the shown tags are hidden to uncover the desktop
+    pub showing_desktop: bool,
+    /// pixels of a floating window kept on its monitor
+    pub float_margin: u16,
+    pub float_placement: FloatPlacement,
+    /// where the last floating window was placed, for cascading from it
+    pub last_placement: Option<(i16, i16)>,
+    /// managed windows in the order they were raised, the last one on top of its layer
+    pub stacking: Vec<Window>,
+    /// the focus last raised, raised again only once the focus moves
+    pub raised_focus: Option<Window>,
+    /// frames from the bottom up as they were last stacked
+    pub stacked: Vec<Window>,
+    /// the layout after the last tiling, `None` to tile again on the next refresh
+    pub tiled: Option<Layout>,
 }
 
 impl StateHandler {
-    pub fn new(tiling: TilingInfo) -> Self {
-        StateHandler {
-            tags: (0..=8).map(|n| Tag::new(n)).collect(),
-            active_tag: 0,
-            tiling,
+    pub fn new(monitors: Vec<Monitor>, workspaces: WorkspaceModel, tag_count: usize) -> Self {
+        let mut state = StateHandler {
+            tags: Vec::new(),
+            monitors,
+            active_monitor: 0,
+            workspaces,
+            tag_count,
+            docks: Vec::new(),
+            monitor_history: HashMap::new(),
+            drag: None,
+            cycle: None,
+            cheat_sheet: None,
+            marks: HashMap::new(),
+            swallowed: HashMap::new(),
+            unmanaged: Vec::new(),
+            scratch: Vec::new(),
+            scratchpads: HashMap::new(),
+            tag_spacing: HashMap::new(),
+            floating_tags: HashSet::new(),
+            also_on: HashMap::new(),
+            showing_desktop: false,
+            float_margin: 0,
+            float_placement: FloatPlacement::default(),
+            last_placement: None,
+            stacking: Vec::new(),
+            raised_focus: None,
+            stacked: Vec::new(),
+            tiled: None,
+        };
+        state.ensure_tags_for_monitor(state.monitors.len().saturating_sub(1));
+        state
+    }
+
+    /// tag a monitor at `index` starts on when it is first shown
+    pub fn initial_tag(workspaces: WorkspaceModel, tag_count: usize, index: usize) -> usize {
+        match workspaces {
+            WorkspaceModel::Global => index,
+            WorkspaceModel::PerMonitor => index * tag_count,
+        }
+    }
+
+    /// with per-monitor tags every monitor needs its own range of tags
+    pub fn ensure_tags_for_monitor(&mut self, index: usize) {
+        let count = match self.workspaces {
+            WorkspaceModel::Global => self.tag_count,
+            WorkspaceModel::PerMonitor => (index + 1) * self.tag_count,
+        };
+        while self.tags.len() < count {
+            self.tags.push(Tag::new(self.tags.len()));
+        }
+    }
+
+    /// returns the tag a reconnected monitor last showed, or an unused one for a new monitor
+    pub fn get_free_tag_for_monitor(&mut self, index: usize, name: &str) -> Option<usize> {
+        self.ensure_tags_for_monitor(index);
+        let remembered = self.monitor_history.get(name).copied().filter(|t| {
+            self.get_monitor_of_tag(*t).is_none()
+                && match self.workspaces {
+                    WorkspaceModel::Global => true,
+                    WorkspaceModel::PerMonitor => t / self.tag_count == index,
+                }
+        });
+        if remembered.is_some() {
+            log::debug!("restoring tag {remembered:?} on monitor {name}");
+            return remembered;
+        }
+        match self.workspaces {
+            WorkspaceModel::Global => {
+                (0..self.tags.len()).find(|t| self.get_monitor_of_tag(*t).is_none())
+            }
+            WorkspaceModel::PerMonitor => Some(index * self.tag_count),
+        }
+    }
+
+    /// index of the first tag belonging to the monitor
+    pub fn get_tag_offset(&self, monitor: &Monitor) -> usize {
+        match self.workspaces {
+            WorkspaceModel::Global => 0,
+            WorkspaceModel::PerMonitor => monitor.active_tag - monitor.active_tag % self.tag_count,
+        }
+    }
+
+    /// converts a tag number relative to the active monitor into a tag index
+    pub fn get_tag_index(&self, tag: usize) -> usize {
+        self.get_tag_offset(&self.monitors[self.active_monitor]) + tag
+    }
+
+    pub fn get_relative_tag(&self, change: i16) -> usize {
+        let offset = self.get_tag_offset(&self.monitors[self.active_monitor]);
+        offset
+            + ((self.active_tag() - offset) as i16 + change).rem_euclid(self.tag_count as i16)
+                as usize
+    }
+
+    /// first tag of the active monitor without windows that no monitor is showing
+    pub fn get_empty_tag(&self) -> Option<usize> {
+        let offset = self.get_tag_offset(&self.monitors[self.active_monitor]);
+        (offset..offset + self.tag_count)
+            .find(|t| !self.is_tag_occupied(*t) && self.get_monitor_of_tag(*t).is_none())
+    }
+
+    /// whether any window is on the tag, held by it or also on it
+    pub fn is_tag_occupied(&self, tag: usize) -> bool {
+        !self.tags[tag].windows.is_empty() || self.also_on.values().any(|s| s.contains(tag))
+    }
+
+    /// whether the window is also on `tag`, besides the tag holding it
+    pub fn is_also_on(&self, window: Window, tag: usize) -> bool {
+        self.also_on.get(&window).is_some_and(|s| s.contains(tag))
+    }
+
+    pub fn set_also_on(&mut self, window: Window, tag: usize, on: bool) {
+        let tags = self.also_on.entry(window).or_default();
+        tags.set(tag, on);
+        if tags.is_empty() {
+            self.also_on.remove(&window);
+        }
+    }
+
+    pub fn active_tag(&self) -> usize {
+        self.monitors[self.active_monitor].active_tag
+    }
+
+    pub fn get_monitor_of_tag(&self, tag: usize) -> Option<usize> {
+        self.monitors
+            .iter()
+            .position(|m| m.viewed_tags().any(|t| t == tag))
+    }
+
+    pub fn find_monitor(&self, monitor: &MonitorRef) -> Option<usize> {
+        self.monitors
+            .iter()
+            .enumerate()
+            .position(|(i, m)| monitor.matches(i, &m.name))
+    }
+
+    pub fn get_monitor_at(&self, x: i16, y: i16) -> Option<usize> {
+        self.monitors.iter().position(|m| {
+            (m.x..m.x.saturating_add_unsigned(m.width)).contains(&x)
+                && (m.y..m.y.saturating_add_unsigned(m.height)).contains(&y)
+        })
+    }
+
+    pub fn get_relative_monitor(&self, change: i16) -> usize {
+        (self.active_monitor as i16 + change).rem_euclid(self.monitors.len() as i16) as usize
+    }
+
+    pub fn add_dock(&mut self, dock: Dock) {
+        log::debug!("adding dock {} with strut {:?}", dock.window, dock.strut);
+        self.docks.retain(|d| d.window != dock.window);
+        self.docks.push(dock);
+        self.update_struts();
+    }
+
+    /// returns whether the window was a dock
+    pub fn remove_dock(&mut self, window: Window) -> bool {
+        let len = self.docks.len();
+        self.docks.retain(|d| d.window != window);
+        if len == self.docks.len() {
+            return false;
         }
+        self.update_struts();
+        true
+    }
+
+    /// recomputes the strut of every monitor from the docks placed on it
+    pub fn update_struts(&mut self) {
+        let root_width = self
+            .monitors
+            .iter()
+            .map(|m| m.x as i32 + m.width as i32)
+            .max()
+            .unwrap_or_default();
+        let root_height = self
+            .monitors
+            .iter()
+            .map(|m| m.y as i32 + m.height as i32)
+            .max()
+            .unwrap_or_default();
+
+        self.monitors
+            .iter_mut()
+            .for_each(|m| m.strut = Edges::default());
+
+        for dock in self.docks.clone() {
+            let monitor = match self.get_monitor_at(dock.x, dock.y) {
+                Some(m) => &mut self.monitors[m],
+                None => continue,
+            };
+            let (left, top) = (monitor.x as i32, monitor.y as i32);
+            let (right, bottom) = (left + monitor.width as i32, top + monitor.height as i32);
+            let inset =
+                |edge: u16, size: i32| (edge as i32 - size).clamp(0, u16::MAX as i32) as u16;
+            let strut = &mut monitor.strut;
+            if dock.strut.left > 0 {
+                strut.left = strut.left.max(inset(dock.strut.left, left));
+            }
+            if dock.strut.right > 0 {
+                strut.right = strut.right.max(inset(dock.strut.right, root_width - right));
+            }
+            if dock.strut.top > 0 {
+                strut.top = strut.top.max(inset(dock.strut.top, top));
+            }
+            if dock.strut.bottom > 0 {
+                strut.bottom = strut
+                    .bottom
+                    .max(inset(dock.strut.bottom, root_height - bottom));
+            }
+        }
+    }
+
+    /// geometry covering the monitors the window spans, or the monitor it is on
+    pub fn get_fullscreen_geometry(&self, window: Window) -> Option<(i16, i16, u16, u16)> {
+        let state = self.get_window_state(window)?;
+        let [top, bottom, left, right] = match state.fullscreen_monitors {
+            Some(m) if m.iter().all(|i| *i < self.monitors.len()) => m.map(|i| &self.monitors[i]),
+            _ => {
+                let monitor = &self.monitors[self.get_monitor_of_window(window)?];
+                return Some((monitor.x, monitor.y, monitor.width, monitor.height));
+            }
+        };
+        let (x, y) = (left.x, top.y);
+        Some((
+            x,
+            y,
+            (right.x as i32 + right.width as i32 - x as i32) as u16,
+            (bottom.y as i32 + bottom.height as i32 - y as i32) as u16,
+        ))
+    }
+
+    /// top, bottom, left and right monitor of the whole screen
+    pub fn get_spanning_monitors(&self) -> [usize; 4] {
+        let find = |key: &dyn Fn(&Monitor) -> i32| {
+            (0..self.monitors.len())
+                .min_by_key(|i| key(&self.monitors[*i]))
+                .unwrap_or_default()
+        };
+        [
+            find(&|m| m.y as i32),
+            find(&|m| -(m.y as i32 + m.height as i32)),
+            find(&|m| m.x as i32),
+            find(&|m| -(m.x as i32 + m.width as i32)),
+        ]
     }
 
     pub fn get_focus(&self) -> Option<u32> {
-        self.tags[self.active_tag].focus
+        self.tags[self.active_tag()].focus
     }
 
     pub fn get_active_tag_windows(&self) -> &Vec<WindowState> {
-        &self.tags[self.active_tag].windows
+        &self.tags[self.active_tag()].windows
     }
 
-    pub fn get_mut_active_tag_windows(&mut self) -> &mut Vec<WindowState> {
-        &mut self.tags[self.active_tag].windows
+    pub fn get_visible_windows(&self) -> impl Iterator<Item = &WindowState> {
+        (0..self.monitors.len())
+            .flat_map(|m| self.get_shown_windows(m))
+            .map(|(t, i)| &self.tags[t].windows[i])
     }
 
-    pub fn get_window_state(&self, window: Window) -> Option<&WindowState> {
-        self.tags[self.active_tag]
-            .windows
+    /// the windows shown on the monitor as tag and index pairs, the ones held by its tags first,
+    /// then the ones of hidden tags that are also on one of its tags
+    pub fn get_shown_windows(&self, monitor: usize) -> Vec<(usize, usize)> {
+        let held = self.monitors[monitor]
+            .viewed_tags()
+            .flat_map(|t| (0..self.tags[t].windows.len()).map(move |i| (t, i)));
+        // only the few windows on several tags can be shown away from their own
+        let mut guests = self
+            .also_on
             .iter()
-            .find(|w| w.window == window || w.frame_window == window)
+            .filter(|(_, tags)| self.get_monitor_viewing(tags) == Some(monitor))
+            .filter_map(|(w, _)| self.locate_window(*w))
+            .filter(|(t, _)| self.get_monitor_of_tag(*t).is_none())
+            .collect::<Vec<_>>();
+        guests.sort_unstable();
+        held.chain(guests).collect()
+    }
+
+    /// the first monitor viewing one of `tags`
+    fn get_monitor_viewing(&self, tags: &TagSet) -> Option<usize> {
+        self.monitors
+            .iter()
+            .position(|m| m.viewed_tags().any(|t| tags.contains(t)))
+    }
+
+    /// the tag and index of a window on any tag, with the monitor showing it if it is shown:
+    /// the one of its own tag or else the first viewing one of the other tags it is on
+    pub fn locate_shown_window(&self, window: Window) -> Option<(usize, usize, Option<usize>)> {
+        let (tag, index) = self.locate_window(window)?;
+        let monitor = self.get_monitor_of_tag(tag).or_else(|| {
+            let tags = self.also_on.get(&self.tags[tag].windows[index].window)?;
+            self.get_monitor_viewing(tags)
+        });
+        Some((tag, index, monitor))
+    }
+
+    pub fn get_monitor_of_window(&self, window: Window) -> Option<usize> {
+        self.locate_shown_window(window)?.2
+    }
+
+    /// the tag holding a shown window
+    pub fn get_tag_of_window(&self, window: Window) -> Option<usize> {
+        match self.locate_shown_window(window)? {
+            (tag, _, Some(_)) => Some(tag),
+            _ => None,
+        }
+    }
+
+    /// like `get_tag_of_window`, but also searches hidden tags
+    pub fn find_tag_of_window(&self, window: Window) -> Option<usize> {
+        self.locate_window(window).map(|(tag, _)| tag)
+    }
+
+    /// the tag and the index in it of a window or frame, on any tag
+    pub fn locate_window(&self, window: Window) -> Option<(usize, usize)> {
+        self.tags.iter().enumerate().find_map(|(i, t)| {
+            t.windows
+                .iter()
+                .position(|w| w.window == window || w.frame_window == window)
+                .map(|index| (i, index))
+        })
+    }
+
+    /// like `get_window_state`, but also searches hidden tags
+    pub fn find_window_state(&self, window: Window) -> Option<&WindowState> {
+        let (tag, index) = self.locate_window(window)?;
+        Some(&self.tags[tag].windows[index])
+    }
+
+    /// like `get_mut_window_state`, but also searches hidden tags
+    pub fn find_mut_window_state(&mut self, window: Window) -> Option<&mut WindowState> {
+        let (tag, index) = self.locate_window(window)?;
+        Some(&mut self.tags[tag].windows[index])
+    }
+
+    pub fn get_window_state(&self, window: Window) -> Option<&WindowState> {
+        match self.locate_shown_window(window)? {
+            (tag, index, Some(_)) => Some(&self.tags[tag].windows[index]),
+            _ => None,
+        }
     }
 
     pub fn get_mut_window_state(&mut self, window: Window) -> Option<&mut WindowState> {
-        self.tags[self.active_tag]
-            .windows
-            .iter_mut()
-            .find(|w| w.window == window || w.frame_window == window)
+        match self.locate_shown_window(window)? {
+            (tag, index, Some(_)) => Some(&mut self.tags[tag].windows[index]),
+            _ => None,
+        }
     }
 
     pub fn add_window(&mut self, window: WindowState) {
-        log::debug!("adding window to tag {}", self.active_tag);
-        self.tags[self.active_tag].windows.push(window);
-        self.tags[self.active_tag].focus = Some(window.window);
+        self.add_window_to_tag(window, self.active_tag());
+    }
+
+    pub fn add_window_to_tag(&mut self, window: WindowState, tag: usize) {
+        log::debug!("adding window to tag {tag}");
+        self.set_also_on(window.window, tag, false);
+        self.tags[tag].windows.push(window);
+        self.tags[tag].focus = Some(window.window);
     }
 
-    pub fn set_tag_focus_to_master(&mut self) {
-        log::debug!("setting tag focus to master");
-        self.tags[self.active_tag].focus = match self.tags[self.active_tag].windows.last() {
-            Some(w) => Some(w.window),
-            None => None,
+    /// adds the window without taking the focus from the tag's focused window, if it has one
+    pub fn add_window_to_tag_unfocused(&mut self, window: WindowState, tag: usize) {
+        let focus = self.tags[tag].focus;
+        self.add_window_to_tag(window, tag);
+        if focus.is_some() {
+            self.tags[tag].focus = focus;
+        }
+    }
+
+    /// moves a window onto the tag shown on the monitor under its center
+    pub fn move_window_to_monitor_under(&mut self, window: Window) {
+        let state = match self.get_window_state(window) {
+            Some(s) => *s,
+            None => return,
+        };
+        let (from, to) = match (
+            self.get_tag_of_window(window),
+            self.get_monitor_at(
+                state.x + (state.width / 2) as i16,
+                state.y + (state.height / 2) as i16,
+            ),
+        ) {
+            // a window of a tag the monitor views is already there
+            (Some(t), Some(m)) if !self.monitors[m].viewed_tags().any(|v| v == t) => {
+                (t, self.monitors[m].active_tag)
+            }
+            _ => return,
         };
+        log::debug!("moving window {window} from tag {from} to tag {to}");
+        self.detach_window(state.window);
+        self.add_window_to_tag(state, to);
+    }
+
+    /// puts `window` in the place of `swallowed`, returning the hidden window's state
+    pub fn swallow(&mut self, swallowed: Window, mut window: WindowState) -> Option<WindowState> {
+        let tag = self.find_tag_of_window(swallowed)?;
+        let slot = self.tags[tag]
+            .windows
+            .iter_mut()
+            .find(|w| w.window == swallowed)?;
+        let old = *slot;
+        (window.x, window.y, window.width, window.height) = (old.x, old.y, old.width, old.height);
+        window.group = old.group;
+        *slot = window;
+        if let Some(tags) = self.also_on.remove(&swallowed) {
+            self.also_on.insert(window.window, tags);
+        }
+        if self.tags[tag].focus == Some(swallowed) {
+            self.tags[tag].focus = Some(window.window);
+        }
+        self.marks
+            .values_mut()
+            .filter(|w| **w == swallowed)
+            .for_each(|w| *w = window.window);
+        self.swallowed.insert(window.window, old);
+        Some(old)
     }
 
-    pub fn set_last_master_others_stack(&mut self) {
-        self.get_mut_active_tag_windows()
+    /// puts a swallowed window back in the place of `window`, returning its state
+    pub fn unswallow(&mut self, window: Window) -> Option<WindowState> {
+        let old = self.swallowed.remove(&window)?;
+        let tag = self.find_tag_of_window(window)?;
+        let slot = self.tags[tag]
+            .windows
             .iter_mut()
-            .filter(|w| w.group != WindowGroup::Floating)
-            .for_each(|w| w.group = WindowGroup::Stack);
+            .find(|w| w.window == window)?;
+        *slot = WindowState {
+            x: slot.x,
+            y: slot.y,
+            width: slot.width,
+            height: slot.height,
+            group: slot.group,
+            ..old
+        };
+        let restored = *slot;
+        if let Some(tags) = self.also_on.remove(&window) {
+            self.also_on.insert(old.window, tags);
+        }
+        if self.tags[tag].focus == Some(window) {
+            self.tags[tag].focus = Some(old.window);
+        }
+        self.marks
+            .values_mut()
+            .filter(|w| **w == window)
+            .for_each(|w| *w = old.window);
+        Some(restored)
+    }
 
-        if let Some(w) = self.get_mut_active_tag_windows().last_mut() {
+    /// takes a window out of the scratch container or off the tag it is on
+    pub fn take_window(&mut self, window: Window) -> Option<WindowState> {
+        if let Some(i) = self.scratch.iter().position(|w| w.window == window) {
+            return Some(self.scratch.remove(i));
+        }
+        let tag = self.find_tag_of_window(window)?;
+        let index = self.tags[tag]
+            .windows
+            .iter()
+            .position(|w| w.window == window)?;
+        let state = self.tags[tag].windows.remove(index);
+        if self.tags[tag].focus == Some(window) {
+            self.set_tag_focus_to_previous(tag);
+        }
+        Some(state)
+    }
+
+    /// forgets `window` along with its marks and the other tags it is on, for windows going away
+    pub fn remove_window(&mut self, window: Window) {
+        self.marks.retain(|_, w| *w != window);
+        self.also_on.remove(&window);
+        self.detach_window(window);
+    }
+
+    /// takes `window` out of its tag, keeping its marks, for windows moving to another tag
+    pub fn detach_window(&mut self, window: Window) {
+        let tag = match self.find_tag_of_window(window) {
+            Some(t) => t,
+            None => return,
+        };
+        log::debug!("removing window {window} from tag {tag}");
+        self.tags[tag].windows.retain(|w| w.window != window);
+        if self.tags[tag].focus.is_none_or(|f| f == window) {
+            self.set_tag_focus_to_previous(tag);
+        }
+    }
+
+    /// focuses the window of `tag` focused before the current one, the master if there is none
+    pub fn set_tag_focus_to_previous(&mut self, tag: usize) {
+        let focus = self.tags[tag].focus;
+        self.tags[tag].focus = self
+            .get_mru_windows(tag)
+            .into_iter()
+            .find(|w| Some(*w) != focus);
+        log::debug!("setting tag {tag} focus to {:?}", self.tags[tag].focus);
+    }
+
+    pub fn set_tag_focus_to_master(&mut self, tag: usize) {
+        log::debug!("setting tag {tag} focus to master");
+        self.tags[tag].focus = self.tags[tag].windows.last().map(|w| w.window);
+    }
+
+    pub fn set_active_monitor_of_window(&mut self, window: Window) {
+        if let Some(m) = self.get_monitor_of_window(window) {
+            self.active_monitor = m;
+        }
+    }
+
+    /// the newest of `windows` held by the tag of the first one becomes the master
+    pub fn set_last_master_others_stack(&mut self, windows: &[(usize, usize)]) {
+        windows.iter().for_each(|(t, i)| {
+            let w = &mut self.tags[*t].windows[*i];
+            if w.group != WindowGroup::Floating {
+                w.group = WindowGroup::Stack;
+            }
+        });
+
+        let first = match windows.first() {
+            Some((t, _)) => *t,
+            None => return,
+        };
+        if let Some((t, i)) = windows.iter().rev().find(|(t, _)| *t == first) {
+            let w = &mut self.tags[*t].windows[*i];
             if w.group == WindowGroup::Floating {
                 return;
             };
@@ -137,100 +873,436 @@ impl StateHandler {
         };
     }
 
-    pub fn tile_windows(&mut self) {
-        log::debug!("tiling tag {}", self.active_tag);
+    /// area of the monitor left over after struts, padding and the bar
+    pub fn get_workarea(&self, monitor: usize) -> (i16, i16, u16, u16) {
+        let mon = &self.monitors[monitor];
+        let bar_height = if mon.tiling.show_bar {
+            mon.tiling.bar_height
+        } else {
+            0
+        };
+        let (strut, padding) = (mon.strut, mon.tiling.padding);
+        let left = strut.left.saturating_add(padding.left);
+        let right = strut.right.saturating_add(padding.right);
+        let top = strut
+            .top
+            .saturating_add(padding.top)
+            .saturating_add(bar_height);
+        let bottom = strut.bottom.saturating_add(padding.bottom);
+        // struts and padding taking the whole monitor still leave a pixel at its far edge
+        let width = mon.width.saturating_sub(left.saturating_add(right)).max(1);
+        let height = mon.height.saturating_sub(top.saturating_add(bottom)).max(1);
+        (
+            offset(mon.x, left.min(mon.width.saturating_sub(width))),
+            offset(mon.y, top.min(mon.height.saturating_sub(height))),
+            width,
+            height,
+        )
+    }
 
-        let (gap, ratio) = (self.tiling.gap, self.tiling.ratio);
-        let (maxw, maxh) = (self.tiling.width, self.tiling.height);
-        let bar_height = self.tiling.bar_height;
+    /// geometry of a floating window of size `width` and `height` snapped on `monitor`
+    pub fn get_snap_geometry(
+        &self,
+        monitor: usize,
+        snap: Snap,
+        width: u16,
+        height: u16,
+    ) -> (i16, i16, u16, u16) {
+        let gap = self.get_gap(monitor);
+        let (x, y, w, h) = self.get_workarea(monitor);
+        let (half_w, half_h) = (w / 2, h / 2);
+        let (right, bottom) = (x + half_w as i16, y + half_h as i16);
+        let (x, y, w, h) = match snap {
+            Snap::Left => (x, y, half_w, h),
+            Snap::Right => (right, y, w - half_w, h),
+            Snap::Top => (x, y, w, half_h),
+            Snap::Bottom => (x, bottom, w, h - half_h),
+            Snap::TopLeft => (x, y, half_w, half_h),
+            Snap::TopRight => (right, y, w - half_w, half_h),
+            Snap::BottomLeft => (x, bottom, half_w, h - half_h),
+            Snap::BottomRight => (right, bottom, w - half_w, h - half_h),
+            Snap::Center => {
+                let (width, height) = (width.min(w), height.min(h));
+                return (
+                    x + (w - width) as i16 / 2,
+                    y + (h - height) as i16 / 2,
+                    width,
+                    height,
+                );
+            }
+        };
+        (
+            x + gap as i16,
+            y + gap as i16,
+            w.saturating_sub(gap * 2),
+            h.saturating_sub(gap * 2),
+        )
+    }
 
-        let stack_count = self.get_active_tag_windows().len().clamp(1, 100) - 1;
+    /// the gap around windows of the tag shown on the monitor
+    pub fn get_gap(&self, monitor: usize) -> u16 {
+        let mon = &self.monitors[monitor];
+        match self.tag_spacing.get(&(mon.active_tag % self.tag_count)) {
+            Some(spacing) => spacing.pixels(mon.width) as u16,
+            None => mon.tiling.gap,
+        }
+    }
 
-        self.get_mut_active_tag_windows()
-            .iter_mut()
-            .enumerate()
-            .for_each(|(i, w)| match w.group {
+    /// a new floating window on `monitor`, at the size its hints ask for or else `size`,
+    /// placed by `float_placement` within the workarea
+    pub fn get_float_geometry(
+        &mut self,
+        monitor: usize,
+        hints: &SizeHints,
+        size: (u16, u16),
+        pointer: (i16, i16),
+    ) -> (i16, i16, u16, u16) {
+        let (width, height) = hints.size.unwrap_or(size);
+        let (width, height) = hints.apply(width as i32, height as i32);
+        let (wx, wy, ww, wh) = self.get_workarea(monitor);
+        let (width, height) = (width.min(ww), height.min(wh));
+        let (wx, wy, ww, wh) = (wx as i32, wy as i32, ww as i32, wh as i32);
+        let (w, h) = (width as i32, height as i32);
+        let (x, y) = match self.float_placement {
+            FloatPlacement::Center => {
+                let (x, y, _, _) = self.get_snap_geometry(monitor, Snap::Center, width, height);
+                (x as i32, y as i32)
+            }
+            FloatPlacement::Pointer => (
+                (pointer.0 as i32 - w / 2).clamp(wx, wx + ww - w),
+                (pointer.1 as i32 - h / 2).clamp(wy, wy + wh - h),
+            ),
+            FloatPlacement::Cascade => {
+                let start = self.get_gap(monitor) as i32;
+                // starting over at the top left once the next one would leave the workarea
+                match self.last_placement.map(|(x, y)| (x as i32, y as i32)) {
+                    Some((x, y))
+                        if x >= wx
+                            && y >= wy
+                            && x + CASCADE_STEP + w <= wx + ww
+                            && y + CASCADE_STEP + h <= wy + wh =>
+                    {
+                        (x + CASCADE_STEP, y + CASCADE_STEP)
+                    }
+                    _ => (wx + start.min(ww - w), wy + start.min(wh - h)),
+                }
+            }
+        };
+        self.last_placement = Some((x as i16, y as i16));
+        (x as i16, y as i16, width, height)
+    }
+
+    /// tiles the windows shown on the monitor together, in the order of its tags
+    pub fn tile_windows(&mut self, monitor: usize) {
+        let windows = self.get_shown_windows(monitor);
+        log::debug!("tiling {} windows on monitor {monitor}", windows.len());
+
+        let (gap, ratio) = (self.get_gap(monitor), self.monitors[monitor].tiling.ratio);
+        let (offx, offy, maxw, maxh) = self.get_workarea(monitor);
+
+        let windows = windows
+            .into_iter()
+            .filter(|(t, i)| !self.tags[*t].windows[*i].on_floating_tag)
+            .collect::<Vec<_>>();
+        let stack_count = windows
+            .iter()
+            .filter(|(t, i)| self.tags[*t].windows[*i].group == WindowGroup::Stack)
+            .count();
+
+        // gaps wider than the workarea shrink windows to a pixel instead of wrapping around
+        let gaps = gap.saturating_mul(2);
+        let mut i = 0;
+        windows.iter().for_each(|(t, index)| {
+            let w = &mut self.tags[*t].windows[*index];
+            match w.group {
                 WindowGroup::Master => {
-                    w.x = 0 + gap as i16;
-                    w.y = 0 + gap as i16 + bar_height as i16;
+                    w.x = offset(offx, gap);
+                    w.y = offset(offy, gap);
+                    // float to int casts saturate, so a negative width becomes 0
                     w.width = if stack_count == 0 {
-                        maxw - gap as u16 * 2
+                        maxw.saturating_sub(gaps)
                     } else {
                         ((maxw as f32 * (1.0 - ratio)) - (gap as f32 * 2.0)) as u16
-                    };
-                    w.height = maxh - gap as u16 * 2 - bar_height;
+                    }
+                    .max(1);
+                    w.height = maxh.saturating_sub(gaps).max(1);
                 }
                 WindowGroup::Stack => {
-                    w.x = (maxw as f32 * (1.0 - ratio)) as i16;
-                    w.y = if i == 0 {
-                        (i * (maxh as usize / stack_count) + gap as usize) as i16
-                            + bar_height as i16
-                    } else {
-                        (i * (maxh as usize / stack_count)) as i16
-                    };
-                    w.width = (maxw as f32 * ratio) as u16 - gap as u16;
+                    let slice = (maxh as usize / stack_count) as u16;
+                    w.x = offset(offx, (maxw as f32 * (1.0 - ratio)) as u16);
+                    w.y = offset(
+                        offy,
+                        if i == 0 {
+                            gap
+                        } else {
+                            (i * slice as usize).min(u16::MAX as usize) as u16
+                        },
+                    );
+                    w.width = ((maxw as f32 * ratio) as u16).saturating_sub(gap).max(1);
 
                     w.height = if i == 0 {
-                        (maxh as usize / stack_count) as u16 - gap as u16 * 2 - bar_height
+                        slice.saturating_sub(gaps)
                     } else {
-                        (maxh as usize / stack_count) as u16 - gap as u16
-                    };
+                        slice.saturating_sub(gap)
+                    }
+                    .max(1);
+                    i += 1;
                 }
                 _ => (),
-            });
+            }
+        });
+    }
+
+    /// `tag` counted the way the config and commands count it, from 1 within the tags of a monitor
+    pub fn tag_number(&self, tag: usize) -> usize {
+        tag % self.tag_count.max(1) + 1
+    }
+
+    pub fn is_floating_tag(&self, tag: usize) -> bool {
+        self.floating_tags.contains(&(tag % self.tag_count))
     }
 
     pub fn refresh(&mut self) {
-        self.set_last_master_others_stack();
-        self.tile_windows();
+        self.settle_focus();
+        // windows brought to a floating tag float where they are, and tile again once they leave
+        (0..self.tags.len()).for_each(|t| {
+            let floating = self.is_floating_tag(t);
+            self.tags[t]
+                .windows
+                .iter_mut()
+                .for_each(|w| w.on_floating_tag = floating);
+        });
+        (0..self.monitors.len()).for_each(|m| {
+            let tiled = self
+                .get_shown_windows(m)
+                .into_iter()
+                .filter(|(t, _)| !self.is_floating_tag(*t))
+                .collect::<Vec<_>>();
+            self.set_last_master_others_stack(&tiled);
+            self.tile_windows(m);
+        });
+        let floating = self
+            .get_visible_windows()
+            .filter(|w| w.is_floating())
+            .map(|w| w.window)
+            .collect::<Vec<_>>();
+        floating.into_iter().for_each(|w| self.keep_on_screen(w));
+    }
+
+    /// moves a floating window back until at least `float_margin` pixels of it are on the
+    /// monitor under its center, or else the one showing it
+    pub fn keep_on_screen(&mut self, window: Window) {
+        let state = match self.find_window_state(window) {
+            Some(s) if s.is_floating() && !s.fullscreen => *s,
+            _ => return,
+        };
+        let (width, height) = (state.width as i32, state.height as i32);
+        let monitor = self
+            .get_monitor_at(
+                (state.x as i32 + width / 2) as i16,
+                (state.y as i32 + height / 2) as i16,
+            )
+            .or_else(|| self.get_monitor_of_window(window))
+            .unwrap_or(self.active_monitor);
+        let m = &self.monitors[monitor];
+        let (mx, my) = (m.x as i32, m.y as i32);
+        let (mw, mh) = (m.width as i32, m.height as i32);
+        let margin_x = (self.float_margin as i32).min(width).min(mw);
+        let margin_y = (self.float_margin as i32).min(height).min(mh);
+        let x = (state.x as i32).clamp(mx + margin_x - width, mx + mw - margin_x);
+        let y = (state.y as i32).clamp(my + margin_y - height, my + mh - margin_y);
+        if (x, y) == (state.x as i32, state.y as i32) {
+            return;
+        }
+        log::debug!("keeping window {window} on screen at {x} {y}");
+        if let Some(state) = self.find_mut_window_state(window) {
+            (state.x, state.y) = (x as i16, y as i16);
+        }
+    }
+
+    /// everything tiling starts from besides the settings, and what it gives
+    pub fn get_layout(&self) -> Layout {
+        Layout {
+            monitors: self.monitors.clone(),
+            windows: self.get_visible_windows().copied().collect(),
+        }
+    }
+
+    /// the focus of a monitor is kept on its active tag, and may be any window shown on it.
+    /// a focus no longer shown there falls back to the active tag's previous one
+    pub fn settle_focus(&mut self) {
+        (0..self.monitors.len()).for_each(|m| {
+            let tag = self.monitors[m].active_tag;
+            let viewed = self.tags[tag]
+                .focus
+                .is_none_or(|f| self.get_monitor_of_window(f) == Some(m));
+            if !viewed {
+                self.set_tag_focus_to_previous(tag);
+            }
+            if self.tags[tag].focus.is_none() {
+                self.tags[tag].focus = self.monitors[m]
+                    .extra_tags
+                    .iter()
+                    .find_map(|t| self.tags[*t].focus);
+            }
+        });
+    }
+
+    /// moves the focused window of every visible tag to the front of its mru list,
+    /// left alone while cycling so the order stays stable
+    pub fn record_focus(&mut self) {
+        if self.cycle.is_some() {
+            return;
+        }
+        self.monitors.iter().for_each(|m| {
+            let focus = self.tags[m.active_tag].focus;
+            m.viewed_tags().for_each(|t| {
+                let tag = &mut self.tags[t];
+                let windows = &tag.windows;
+                tag.mru.retain(|w| windows.iter().any(|s| s.window == *w));
+                // the focus may be on another of the viewed tags
+                if let Some(focus) = focus.filter(|f| windows.iter().any(|s| s.window == *f)) {
+                    tag.mru.retain(|w| *w != focus);
+                    tag.mru.insert(0, focus);
+                }
+            });
+        });
+    }
+
+    /// puts the window on top of the others of its layer
+    pub fn raise_window(&mut self, window: Window) {
+        self.forget_stacking(window);
+        self.stacking.push(window);
+    }
+
+    /// puts the window below the others of its layer
+    pub fn lower_window(&mut self, window: Window) {
+        self.forget_stacking(window);
+        self.stacking.insert(0, window);
+    }
+
+    fn forget_stacking(&mut self, window: Window) {
+        self.stacking = self
+            .stacking
+            .iter()
+            .copied()
+            .filter(|w| *w != window && self.find_window_state(*w).is_some())
+            .collect();
+    }
+
+    /// raises the focus once each time it moves to another window
+    pub fn raise_focus(&mut self) {
+        let focus = self.get_focus();
+        if focus == self.raised_focus {
+            return;
+        }
+        self.raised_focus = focus;
+        if let Some(focus) = focus {
+            self.raise_window(focus);
+        }
+    }
+
+    /// the visible windows from the bottom up: tiled, floating, pinned and then fullscreen ones,
+    /// each layer in the order they were raised. windows never raised go on top of their layer
+    pub fn get_stacking_order(&self) -> Vec<&WindowState> {
+        let mut windows = self.get_visible_windows().collect::<Vec<_>>();
+        windows.sort_by_key(|w| {
+            let layer = match w {
+                w if w.fullscreen => 3,
+                w if w.pinned => 2,
+                w if w.is_floating() => 1,
+                _ => 0,
+            };
+            let position = self.stacking.iter().position(|s| *s == w.window);
+            (layer, position.unwrap_or(usize::MAX))
+        });
+        windows
+    }
+
+    /// windows of `tag` from most to least recently focused
+    pub fn get_mru_windows(&self, tag: usize) -> Vec<Window> {
+        let tag = &self.tags[tag];
+        let mut windows = tag
+            .mru
+            .iter()
+            .copied()
+            .filter(|w| tag.windows.iter().any(|s| s.window == *w))
+            .collect::<Vec<_>>();
+        tag.windows.iter().rev().for_each(|s| {
+            if !windows.contains(&s.window) {
+                windows.push(s.window);
+            }
+        });
+        windows
     }
 
+    /// swaps the focus with the master of its monitor, or the master with the window after it.
+    /// the master is held by the tag of the first shown window, the active one unless it is empty
     pub fn swap_master(&mut self) {
-        let focus_window = match self.tags[self.active_tag].focus {
+        let focus_window = match self.get_focus() {
             Some(w) => w,
             None => return,
         };
-        let len = self.tags[self.active_tag].windows.len();
-        let mut master = self.tags[self.active_tag].windows[len - 1].window;
+        let tag = match self.get_shown_windows(self.active_monitor).first() {
+            Some((t, _)) => *t,
+            None => return,
+        };
+        let windows = &self.tags[tag].windows;
+        let len = windows.len();
+        let mut master = windows[len - 1].window;
         if master == focus_window && len > 1 {
-            master = self.tags[self.active_tag].windows[len - 2].window;
+            master = windows[len - 2].window;
         }
-        let index_f = match self.get_index_of_window(focus_window) {
+        let index_f = match self.get_index_of_window(tag, focus_window) {
             Some(i) => i,
             None => return,
         };
-        let index_m = match self.get_index_of_window(master) {
+        let index_m = match self.get_index_of_window(tag, master) {
             Some(i) => i,
             None => return,
         };
-        self.tags[self.active_tag].windows.swap(index_f, index_m);
+        self.tags[tag].windows.swap(index_f, index_m);
     }
 
     pub fn switch_focus_next(&mut self, change: i16) {
-        let focus_window = match self.tags[self.active_tag].focus {
+        let tag = self.active_tag();
+        let focus_window = match self.tags[tag].focus {
             Some(w) => w,
             None => return,
         };
-        let focus_index = (match self
-            .get_active_tag_windows()
-            .iter()
-            .position(|w| w.window == focus_window)
-        {
+        let windows = self
+            .get_shown_windows(self.active_monitor)
+            .into_iter()
+            .map(|(t, i)| self.tags[t].windows[i].window)
+            .collect::<Vec<_>>();
+        let focus_index = (match windows.iter().position(|w| *w == focus_window) {
             Some(i) => i,
             None => return,
         } as i16
             + change)
-            .rem_euclid(self.get_active_tag_windows().len() as i16);
-        self.tags[self.active_tag].focus =
-            Some(self.get_active_tag_windows()[focus_index as usize].window);
+            .rem_euclid(windows.len() as i16);
+        self.tags[tag].focus = Some(windows[focus_index as usize]);
     }
 
     pub fn print_state(&self) {
         log::debug!(
-            "Manager state: active tag {} focus {:?}",
-            self.active_tag,
-            self.tags[self.active_tag].focus
+            "Manager state: active monitor {} active tag {} focus {:?}",
+            self.active_monitor,
+            self.active_tag(),
+            self.get_focus()
         );
+        self.monitors.iter().enumerate().for_each(|(i, m)| {
+            log::debug!(
+                "monitor {i} {} x {} y {} w {} h {} tag {} extra tags {:?}",
+                m.name,
+                m.x,
+                m.y,
+                m.width,
+                m.height,
+                m.active_tag,
+                m.extra_tags
+            )
+        });
         self.tags
             .iter()
             .filter(|t| !t.windows.is_empty())
@@ -240,10 +1312,228 @@ impl StateHandler {
             });
     }
 
-    fn get_index_of_window(&self, window: Window) -> Option<usize> {
-        self.tags[self.active_tag]
+    fn get_index_of_window(&self, tag: usize, window: Window) -> Option<usize> {
+        self.tags[tag]
             .windows
             .iter()
             .position(|w| w.window == window || w.frame_window == window)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor(x: i16, active_tag: usize) -> Monitor {
+        Monitor {
+            name: format!("monitor {x}"),
+            x,
+            y: 0,
+            width: 1000,
+            height: 800,
+            active_tag,
+            extra_tags: Vec::new(),
+            bar: WindowState::new(0, 0).unwrap(),
+            tiling: TilingInfo {
+                gap: 10,
+                ratio: 0.5,
+                bar_height: 20,
+                show_bar: false,
+                padding: Edges::default(),
+            },
+            strut: Edges::default(),
+        }
+    }
+
+    fn state(monitors: usize) -> StateHandler {
+        let monitors = (0..monitors).map(|i| monitor(i as i16 * 1000, i)).collect();
+        StateHandler::new(monitors, WorkspaceModel::Global, 9)
+    }
+
+    fn window(id: Window) -> WindowState {
+        WindowState::new(id, id + 100).unwrap()
+    }
+
+    fn ids(state: &StateHandler, tag: usize) -> Vec<Window> {
+        state.tags[tag].windows.iter().map(|w| w.window).collect()
+    }
+
+    #[test]
+    fn swap_master_with_only_a_toggled_tag_shown() {
+        let mut state = state(1);
+        state.add_window_to_tag(window(5), 1);
+        state.monitors[0].extra_tags = vec![1];
+        state.refresh();
+        assert_eq!(state.get_focus(), Some(5));
+        state.swap_master();
+        assert_eq!(ids(&state, 1), [5]);
+    }
+
+    #[test]
+    fn swap_master_without_windows() {
+        let mut state = state(1);
+        state.tags[0].focus = Some(5);
+        state.swap_master();
+        assert!(state.tags[0].windows.is_empty());
+    }
+
+    #[test]
+    fn swap_master_swaps_focus_and_master() {
+        let mut state = state(1);
+        [1, 2, 3]
+            .into_iter()
+            .for_each(|w| state.add_window(window(w)));
+        state.tags[0].focus = Some(1);
+        state.swap_master();
+        assert_eq!(ids(&state, 0), [3, 2, 1]);
+        // the master swaps with the window after it
+        state.swap_master();
+        assert_eq!(ids(&state, 0), [3, 1, 2]);
+    }
+
+    fn geometry(state: &StateHandler, window: Window) -> (i16, i16, u16, u16) {
+        let w = state.find_window_state(window).unwrap();
+        (w.x, w.y, w.width, w.height)
+    }
+
+    fn tiled(state: &StateHandler) -> Vec<(i16, i16, u16, u16)> {
+        state.tags[0]
+            .windows
+            .iter()
+            .map(|w| (w.x, w.y, w.width, w.height))
+            .collect()
+    }
+
+    #[test]
+    fn master_and_stack_split_the_workarea() {
+        let mut state = state(1);
+        [1, 2, 3]
+            .into_iter()
+            .for_each(|w| state.add_window(window(w)));
+        state.refresh();
+        assert_eq!(
+            tiled(&state),
+            [
+                (500, 10, 490, 380),
+                (500, 400, 490, 390),
+                (10, 10, 480, 780)
+            ]
+        );
+    }
+
+    #[test]
+    fn workarea_leaves_out_struts_padding_and_bar() {
+        let mut state = state(1);
+        state.monitors[0].strut.left = 30;
+        state.monitors[0].tiling.padding.top = 5;
+        state.monitors[0].tiling.show_bar = true;
+        assert_eq!(state.get_workarea(0), (30, 25, 970, 775));
+    }
+
+    #[test]
+    fn oversized_struts_and_gaps_leave_a_pixel() {
+        let mut state = state(1);
+        state.monitors[0].strut.left = 900;
+        state.monitors[0].tiling.padding.right = 300;
+        state.monitors[0].tiling.padding.bottom = u16::MAX;
+        assert_eq!(state.get_workarea(0), (900, 0, 1, 1));
+
+        state.monitors[0].strut = Edges::default();
+        state.monitors[0].tiling.padding = Edges::default();
+        state.monitors[0].tiling.gap = 40000;
+        [1, 2].into_iter().for_each(|w| state.add_window(window(w)));
+        state.refresh();
+        assert!(tiled(&state).iter().all(|&(_, _, w, h)| w >= 1 && h >= 1));
+    }
+
+    #[test]
+    fn tag_set_grows_past_64_tags() {
+        let mut tags = TagSet::default();
+        tags.set(3, true);
+        tags.set(100, true);
+        assert!(tags.contains(100) && !tags.contains(99) && !tags.contains(1000));
+        assert_eq!(tags.iter().collect::<Vec<_>>(), [3, 100]);
+        tags.set(100, false);
+        tags.set(3, false);
+        assert!(tags.is_empty());
+    }
+
+    #[test]
+    fn shared_window_is_shown_once() {
+        let mut state = state(2);
+        state.add_window_to_tag(window(5), 1);
+        state.set_also_on(5, 0, true);
+        // its own tag is shown, so it stays there
+        assert!(state.get_shown_windows(0).is_empty());
+        assert_eq!(state.get_shown_windows(1), [(1, 0)]);
+        state.monitors[1].active_tag = 2;
+        assert_eq!(state.get_shown_windows(0), [(1, 0)]);
+        assert!(state.get_shown_windows(1).is_empty());
+        assert_eq!(state.get_monitor_of_window(5), Some(0));
+        state.set_also_on(5, 0, false);
+        assert!(state.also_on.is_empty());
+        assert_eq!(state.get_monitor_of_window(5), None);
+    }
+
+    #[test]
+    fn shared_window_is_tiled_focused_and_swapped() {
+        let mut state = state(1);
+        state.add_window(window(1));
+        state.add_window_to_tag(window(5), 1);
+        state.set_also_on(5, 0, true);
+        state.refresh();
+        assert_eq!(state.get_shown_windows(0), [(0, 0), (1, 0)]);
+        assert_eq!(geometry(&state, 1), (10, 10, 480, 780));
+        assert_eq!(geometry(&state, 5), (500, 10, 490, 780));
+
+        state.switch_focus_next(1);
+        assert_eq!(state.get_focus(), Some(5));
+        // the focus isn't held by the tag of the master, so nothing moves
+        state.swap_master();
+        assert_eq!((ids(&state, 0), ids(&state, 1)), (vec![1], vec![5]));
+
+        state.remove_window(1);
+        state.tags[0].focus = Some(5);
+        state.refresh();
+        state.swap_master();
+        assert_eq!(ids(&state, 1), [5]);
+        assert_eq!(geometry(&state, 5), (10, 10, 980, 780));
+    }
+
+    #[test]
+    fn floating_tag_floats_its_windows_without_changing_their_group() {
+        let mut state = state(1);
+        state.floating_tags.insert(0);
+        state.add_window(WindowState {
+            x: 50,
+            y: 60,
+            ..window(1)
+        });
+        state.refresh();
+        let w = state.find_window_state(1).unwrap();
+        assert!(w.is_floating() && w.group != WindowGroup::Floating);
+        assert_eq!(geometry(&state, 1), (50, 60, 100, 100));
+
+        state.floating_tags.clear();
+        state.refresh();
+        assert!(!state.find_window_state(1).unwrap().is_floating());
+        assert_eq!(geometry(&state, 1), (10, 10, 980, 780));
+    }
+
+    #[test]
+    fn marks_survive_moving_to_another_tag() {
+        let mut state = state(2);
+        // dragged over to the second monitor, which shows tag 1
+        state.add_window(WindowState {
+            x: 1200,
+            ..window(5)
+        });
+        state.marks.insert('a', 5);
+        state.move_window_to_monitor_under(5);
+        assert_eq!(state.find_tag_of_window(5), Some(1));
+        assert_eq!(state.marks.get(&'a'), Some(&5));
+
+        state.remove_window(5);
+        assert!(state.marks.is_empty());
+    }
+}
@@ -0,0 +1,49 @@
+//! reloads the config when `config.toml` or a file it includes changes
+
+use std::{
+    sync::{Arc, mpsc::Sender},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    config,
+    ipc::{self, Command, Request},
+    keys::HotkeyAction,
+    poll::EventFd,
+};
+
+/// the latest change to any of the config files
+fn modified() -> Option<SystemTime> {
+    let path = config::config_path()?;
+    let paths = match config::read_config_files(&path) {
+        Ok(files) => files.into_iter().map(|f| f.path).collect(),
+        Err(_) => vec![path],
+    };
+    paths
+        .iter()
+        .filter_map(|p| std::fs::metadata(p).and_then(|m| m.modified()).ok())
+        .max()
+}
+
+/// checks every second, sending `ReloadConfig` to the main loop like a control command would
+pub(crate) fn start(tx: Sender<Request>, waker: Arc<EventFd>) {
+    thread::spawn(move || {
+        let mut last = modified();
+        loop {
+            thread::sleep(Duration::from_secs(1));
+            let current = modified();
+            let changed = current.is_some() && current != last;
+            last = current;
+            if !changed {
+                continue;
+            }
+            log::info!("config changed, reloading");
+            let command = Command::Action(HotkeyAction::ReloadConfig);
+            if let Err(e) = ipc::send(&tx, &waker, command) {
+                log::error!("couldn't ask for a reload {e:?}");
+                return;
+            }
+        }
+    });
+}